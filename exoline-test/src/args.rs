@@ -24,6 +24,15 @@ pub struct Cli {
     /// Controller directory.
     #[arg(short, long, default_value = ".")]
     pub controller_dir: PathBuf,
+
+    /// Start a virtual controller on the port and connect to it.
+    #[arg(short, long)]
+    pub emulate: bool,
+
+    /// Write every frame to a trace file.
+    /// Files ending with .jsonl get one JSON object per line, other files get plain text.
+    #[arg(long)]
+    pub trace: Option<PathBuf>,
 }
 
 #[derive(Parser, Debug)]
@@ -50,9 +59,15 @@ pub enum InteractiveCommands {
     /// Export the previously printed table
     Export(ExportArgs),
 
+    /// List the reads and writes in a pcap or pcapng capture
+    Analyze(AnalyzeArgs),
+
     /// Auto discover the EXOline address from device
     Address,
 
+    /// Find controllers in a range of IP addresses
+    Scan(ScanArgs),
+
     /// Set configuration
     Set(SetArgs),
 
@@ -68,7 +83,9 @@ impl Display for InteractiveCommands {
             InteractiveCommands::Write(_) => write!(f, "Write"),
             InteractiveCommands::Dump(_) => write!(f, "Dump"),
             InteractiveCommands::Export(_) => write!(f, "Export"),
+            InteractiveCommands::Analyze(_) => write!(f, "Analyze"),
             InteractiveCommands::Address => write!(f, "Address"),
+            InteractiveCommands::Scan(_) => write!(f, "Scan"),
             InteractiveCommands::Set(_) => write!(f, "Set"),
             InteractiveCommands::Exit => write!(f, "Exit"),
         }
@@ -103,6 +120,38 @@ pub struct ExportArgs {
     pub filename: PathBuf,
 }
 
+#[derive(Args, Debug)]
+pub struct AnalyzeArgs {
+    /// The capture file to read
+    pub filename: PathBuf,
+
+    /// Only list writes
+    #[arg(short, long)]
+    pub writes: bool,
+
+    /// Only list this variable
+    #[arg(short, long)]
+    pub variable: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ScanArgs {
+    /// IP addresses and CIDR ranges, like 192.168.1.0/24,10.0.0.5
+    pub hosts: String,
+
+    /// TCP port number
+    #[arg(short, long, default_value = "26486")]
+    pub port: u16,
+
+    /// Number of hosts probed at once
+    #[arg(short, long, default_value = "64")]
+    pub concurrency: usize,
+
+    /// Write the controllers to a CSV file instead of printing a table
+    #[arg(long)]
+    pub csv: Option<PathBuf>,
+}
+
 #[derive(Args, Debug)]
 pub struct SetArgs {
     #[command(subcommand)]
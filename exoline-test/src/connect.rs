@@ -4,21 +4,26 @@ use chrono::DateTime;
 use clap::Parser;
 use comfy_table::{presets, CellAlignment, Table};
 use exoline::{
-    client::{EXOlineTCPClient, Variant},
+    client::{
+        parse_hosts, scan, ConnectionState, EXOlineTCPClient, FrameObserver, JsonLinesTraceWriter, ReconnectOptions, ScanOptions,
+        TextTraceWriter, Variant,
+    },
     controller::{Controller, ControllerLoader, FileKind, LoadMode, VariableKind},
+    emulator::VirtualController,
+    protocol::{analyze_capture_file, Direction, Operation},
 };
 use rustyline::{completion::Completer, history::MemHistory, Editor, Helper, Highlighter, Hinter, Validator};
-use tokio::{net::TcpStream, sync::Mutex, time::Instant};
+use tokio::{select, sync::broadcast::error::RecvError, sync::Mutex, time::Instant};
 
 use crate::{
     args::*,
-    util::{format_variant, timeout_or_cancel},
+    util::{cancellable, format_disconnect_reason, format_variant, write_table_csv, AbortReason},
 };
 
 use super::args::{Cli, ExportArgs, ReadArgs};
 
 pub async fn run(args: Cli) -> Result<(), Box<dyn Error>> {
-    let host_port = match args.host {
+    let mut host_port = match args.host {
         None => None,
         Some(host) => Some(format!("{}:{}", host, args.port)),
     };
@@ -38,7 +43,20 @@ pub async fn run(args: Cli) -> Result<(), Box<dyn Error>> {
     println!("address = {}:{}", controller.address.0, controller.address.1);
     println!();
 
-    let mut client = ClientImpl::new(args.timeout, host_port, controller);
+    if args.emulate {
+        let (addr, _) = VirtualController::new(&controller).listen(("127.0.0.1", args.port)).await?;
+        println!("Virtual controller listening on {addr}");
+        println!();
+        host_port = Some(addr.to_string());
+    }
+
+    let trace: Option<Arc<dyn FrameObserver>> = match &args.trace {
+        None => None,
+        Some(path) if path.extension().is_some_and(|extension| extension == "jsonl") => Some(Arc::new(JsonLinesTraceWriter::create(path)?)),
+        Some(path) => Some(Arc::new(TextTraceWriter::create(path)?)),
+    };
+
+    let mut client = ClientImpl::new(args.timeout, host_port, controller, trace);
 
     client.command_loop().await?;
 
@@ -50,18 +68,20 @@ struct ClientImpl {
     host_port: Option<String>,
     client: Arc<Mutex<Option<Arc<EXOlineTCPClient>>>>,
     controller: Arc<Controller>,
+    trace: Option<Arc<dyn FrameObserver>>,
     last_table: Option<Table>,
     address: (u8, u8),
 }
 
 impl ClientImpl {
-    pub fn new(timeout: Duration, host_port: Option<String>, controller: Controller) -> Self {
+    pub fn new(timeout: Duration, host_port: Option<String>, controller: Controller, trace: Option<Arc<dyn FrameObserver>>) -> Self {
         Self {
             timeout,
             host_port,
             address: controller.address,
             client: Arc::new(Mutex::new(None)),
             controller: Arc::new(controller),
+            trace,
             last_table: None,
         }
     }
@@ -122,7 +142,9 @@ impl ClientImpl {
             InteractiveCommands::Write(args) => self.write(args).await,
             InteractiveCommands::Dump(args) => self.dump(args).await,
             InteractiveCommands::Export(args) => self.export_csv(args).await,
+            InteractiveCommands::Analyze(args) => self.analyze(args).await,
             InteractiveCommands::Address => self.address().await,
+            InteractiveCommands::Scan(args) => self.scan_network(args).await,
             InteractiveCommands::Set(args) => match args.command {
                 SetCommands::Host { ref host, port } => {
                     self.host_port = Some(format!("{}:{}", host, port));
@@ -163,11 +185,7 @@ impl ClientImpl {
             kind: VariableKind,
             offset: u32,
         ) -> Result<Variant, Box<dyn Error>> {
-            Ok(timeout_or_cancel(
-                this.timeout,
-                client.read_variable_raw(this.address, FileKind::VPac, load_number, kind, offset),
-            )
-            .await??)
+            Ok(cancellable(client.read_variable_raw(this.address, FileKind::VPac, load_number, kind, offset)).await??)
         }
 
         async fn get_partition_attribute(
@@ -177,7 +195,7 @@ impl ClientImpl {
             kind: VariableKind,
             id: u16,
         ) -> Result<Variant, Box<dyn Error>> {
-            Ok(timeout_or_cancel(this.timeout, client.read_partition_attribute(this.address, partition, kind, id)).await??)
+            Ok(cancellable(client.read_partition_attribute(this.address, partition, kind, id)).await??)
         }
 
         const Q_SYSTEM: u8 = 241;
@@ -262,7 +280,7 @@ impl ClientImpl {
     async fn address(&mut self) -> Result<(), Box<dyn Error>> {
         let client = self.connect_if_needed().await?;
 
-        let (pla, ela) = timeout_or_cancel(self.timeout, client.read_exoline_address()).await??;
+        let (pla, ela) = cancellable(client.read_exoline_address()).await??;
 
         self.address = (pla, ela);
 
@@ -281,7 +299,7 @@ impl ClientImpl {
             Some(variable) => {
                 let client = self.connect_if_needed().await?;
 
-                let value = timeout_or_cancel(self.timeout, client.read_variable(self.address, &variable)).await??;
+                let value = cancellable(client.read_variable(self.address, &variable)).await??;
 
                 table.add_row([
                     variable.full_name().unwrap().as_str(),
@@ -298,7 +316,7 @@ impl ClientImpl {
 
                 let client = self.connect_if_needed().await?;
 
-                let values = timeout_or_cancel(self.timeout, client.read_dpac(self.address, &file)).await??;
+                let values = cancellable(client.read_dpac_with_strings(self.address, &file)).await??;
 
                 let mut variables = file.iter().collect::<Vec<_>>();
                 variables.sort_by_key(|v| v.offset());
@@ -306,7 +324,8 @@ impl ClientImpl {
                 for variable in variables {
                     let value_text = match values.get(&variable) {
                         None => "",
-                        Some(value) => &format_variant(value),
+                        Some(Ok(value)) => &format_variant(value),
+                        Some(Err(err)) => &err.to_string(),
                     };
                     table.add_row([
                         variable.full_name().unwrap().as_str(),
@@ -341,7 +360,7 @@ impl ClientImpl {
 
         let client = self.connect_if_needed().await?;
 
-        timeout_or_cancel(self.timeout, client.write_variable(self.address, &variable, &variant)).await??;
+        cancellable(client.write_variable(self.address, &variable, &variant)).await??;
 
         Ok(())
     }
@@ -357,10 +376,14 @@ impl ClientImpl {
                 for file in files {
                     print!("Reading {}... ", file.name());
                     std::io::stdout().flush()?;
-                    match timeout_or_cancel(self.timeout, client.read_dpac(self.address, &file)).await? {
+                    match cancellable(client.read_dpac_with_strings(self.address, &file)).await? {
                         Ok(data) => {
-                            println!("OK");
-                            values.extend(data)
+                            let failed = data.values().filter(|value| value.is_err()).count();
+                            match failed {
+                                0 => println!("OK"),
+                                _ => println!("OK, {failed} strings failed"),
+                            }
+                            values.extend(data.into_iter().filter_map(|(variable, value)| Some((variable, value.ok()?))));
                         }
                         Err(err) => println!("{err}"),
                     }
@@ -419,25 +442,116 @@ impl ClientImpl {
             }
         };
 
-        let mut writer = csv::Writer::from_path(&args.filename)?;
+        write_table_csv(table, &args.filename)?;
+
+        println!("Exported");
+
+        Ok(())
+    }
 
-        let header = table.header().unwrap().cell_iter().map(|c| c.content()).collect::<Vec<String>>();
-        writer.write_record(header)?;
+    async fn analyze(&mut self, args: &AnalyzeArgs) -> Result<(), Box<dyn Error>> {
+        let analysis = analyze_capture_file(&args.filename, Some(&self.controller))?;
 
-        for row in table.row_iter() {
-            let record = row.cell_iter().map(|c| c.content()).collect::<Vec<String>>();
-            writer.write_record(record)?;
+        let mut table = Table::new();
+        table.load_preset(presets::NOTHING);
+        table.set_header(["Time", "Client", "Device", "Operation", "Variable", "Value", "Result"]);
+        table.column_mut(5).unwrap().set_cell_alignment(CellAlignment::Right);
+
+        let mut count = 0;
+        for event in analysis.events {
+            if args.writes && event.operation != Operation::Write {
+                continue;
+            }
+            if let Some(variable) = &args.variable {
+                if !event.name.as_ref().is_some_and(|name| name.eq_ignore_ascii_case(variable)) {
+                    continue;
+                }
+            }
+            count += 1;
+
+            let time = DateTime::<chrono::Local>::from(event.timestamp);
+            let variable = match event.name {
+                Some(name) => name,
+                None => format!("{:?} {}@{}", event.file_kind, event.load_number, event.offset),
+            };
+            let result = match (event.exception, event.latency) {
+                (Some(exception), _) => format!("{exception:?}"),
+                (None, Some(latency)) => format!("OK {}ms", latency.as_millis()),
+                (None, None) => "No response".into(),
+            };
+            table.add_row([
+                time.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+                event.client.to_string(),
+                format!("{}:{}", event.address.0, event.address.1),
+                format!("{:?}", event.operation),
+                variable,
+                event.value.as_ref().map(format_variant).unwrap_or_default(),
+                result,
+            ]);
         }
-        writer.flush()?;
 
-        println!("Exported");
+        println!("{table}");
+        println!("{count} operations");
+        for gap in &analysis.gaps {
+            let time = DateTime::<chrono::Local>::from(gap.timestamp);
+            let direction = match gap.direction {
+                Direction::Request => "requests",
+                Direction::Response => "responses",
+            };
+            println!(
+                "Warning: {} bytes missing from the {direction} of {} at {}, the operations around it may be wrong",
+                gap.bytes,
+                gap.client,
+                time.format("%Y-%m-%d %H:%M:%S%.3f")
+            );
+        }
+        self.last_table = Some(table);
 
         Ok(())
     }
 
-    async fn connect_if_needed(&self) -> Result<Arc<EXOlineTCPClient>, Box<dyn Error>> {
+    async fn scan_network(&mut self, args: &ScanArgs) -> Result<(), Box<dyn Error>> {
+        let hosts = parse_hosts(&args.hosts)?;
+        println!("Scanning {} hosts...", hosts.len());
+
+        let options = ScanOptions {
+            port: args.port,
+            concurrency: args.concurrency,
+            timeout: self.timeout,
+            ..Default::default()
+        };
+        let found = select! {
+            found = scan(hosts, &options) => found,
+            _ = tokio::signal::ctrl_c() => return Err(AbortReason::Cancel.into()),
+        };
+
+        let mut table = Table::new();
+        table.load_preset(presets::NOTHING);
+        table.set_header(["IP address", "EXOline address", "Model", "Firmware"]);
+        for controller in &found {
+            let (pla, ela) = controller.address;
+            table.add_row([
+                controller.addr.ip().to_string(),
+                format!("{pla}:{ela}"),
+                controller.model().unwrap_or_default().to_string(),
+                controller.firmware().unwrap_or_default().to_string(),
+            ]);
+        }
+
+        match &args.csv {
+            Some(filename) => write_table_csv(&table, filename)?,
+            None => println!("{table}"),
+        }
+        println!("Found {} controllers", found.len());
+        self.last_table = Some(table);
+
+        Ok(())
+    }
+
+    /// Returns the client, connected if needed, with the current timeout.
+    async fn connect_if_needed(&self) -> Result<EXOlineTCPClient, Box<dyn Error>> {
         if let Some(client) = self.client.lock().await.as_ref() {
-            return Ok(client.clone());
+            return Ok(client.with_timeout(self.timeout));
         }
 
         let addr = match &self.host_port {
@@ -445,35 +559,56 @@ impl ClientImpl {
             Some(addr) => addr,
         };
 
+        let options = ReconnectOptions {
+            connect_timeout: self.timeout,
+            ..Default::default()
+        };
+        let (client, _) = EXOlineTCPClient::connect_to_controller(addr.as_str(), &self.controller, options);
+        client.set_frame_observer(self.trace.clone());
+        let mut events = client.connection_events();
+
         print!("Connecting... ");
         std::io::stdout().flush()?;
 
-        let stream = timeout_or_cancel(self.timeout, TcpStream::connect(addr)).await??;
+        // The first attempt is waited for, so that a wrong host is reported right away.
+        let mut state = client.connection_state();
+        loop {
+            match state {
+                ConnectionState::Connecting => {}
+                ConnectionState::Connected => break,
+                ConnectionState::Disconnected(reason) => return Err(format_disconnect_reason(&reason).into()),
+            }
+            state = match cancellable(events.recv()).await? {
+                Ok(state) => state,
+                Err(RecvError::Lagged(_)) => client.connection_state(),
+                Err(RecvError::Closed) => return Err("Connection closed".into()),
+            };
+        }
 
         println!("Connected");
         println!();
 
-        let (client, handle) = EXOlineTCPClient::new(stream);
-
         let client = Arc::new(client);
 
         _ = self.client.lock().await.insert(client.clone());
 
-        let client_ = self.client.clone();
-
+        // The client reconnects by itself, this only reports it.
         tokio::spawn(async move {
-            let result = handle.await.unwrap_or(Ok(()));
-            _ = client_.lock().await.take();
-            println!();
-            println!();
-            match result {
-                Ok(_) => println!("Connection closed"),
-                Err(err) => println!("{err}"),
+            loop {
+                let message = match events.recv().await {
+                    Ok(ConnectionState::Connecting) | Err(RecvError::Lagged(_)) => continue,
+                    Ok(ConnectionState::Connected) => "Reconnected".to_string(),
+                    Ok(ConnectionState::Disconnected(reason)) => format!("{}, reconnecting", format_disconnect_reason(&reason)),
+                    Err(RecvError::Closed) => break,
+                };
+                println!();
+                println!();
+                println!("{message}");
+                println!();
             }
-            println!();
         });
 
-        Ok(client)
+        Ok(client.with_timeout(self.timeout))
     }
 }
 
@@ -481,9 +616,10 @@ impl ClientImpl {
 struct InteractiveHelper {
     controller: Arc<Controller>,
 }
-const COMPLETIONS: [&str; 11] = [
+const COMPLETIONS: [&str; 13] = [
     "info",
     "address",
+    "scan ",
     "read ",
     "write ",
     "set address ",
@@ -491,6 +627,7 @@ const COMPLETIONS: [&str; 11] = [
     "set timeout ",
     "dump ",
     "export ",
+    "analyze ",
     "help",
     "exit",
 ];
@@ -1,18 +1,17 @@
-use std::{error::Error, fmt::Display, future::IntoFuture, time::Duration};
+use std::{error::Error, fmt::Display, future::IntoFuture, path::Path};
 
-use exoline::client::Variant;
+use comfy_table::Table;
+use exoline::client::{DisconnectReason, Variant};
 use tokio::select;
 
 #[derive(Debug)]
 pub enum AbortReason {
-    Timeout,
     Cancel,
 }
 
 impl Display for AbortReason {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            AbortReason::Timeout => write!(f, "Timeout"),
             AbortReason::Cancel => write!(f, "Cancel"),
         }
     }
@@ -20,13 +19,12 @@ impl Display for AbortReason {
 
 impl Error for AbortReason {}
 
-pub async fn timeout_or_cancel<F>(timeout: Duration, future: F) -> Result<F::Output, AbortReason>
+pub async fn cancellable<F>(future: F) -> Result<F::Output, AbortReason>
 where
     F: IntoFuture,
 {
     select! {
         result = future => Ok(result),
-        _ = tokio::time::sleep(timeout) => Err(AbortReason::Timeout),
         _ = tokio::signal::ctrl_c() => Err(AbortReason::Cancel)
     }
 }
@@ -56,3 +54,24 @@ pub fn format_variant(value: &Variant) -> String {
         Variant::String(value) => value.to_string(),
     }
 }
+
+pub fn format_disconnect_reason(reason: &DisconnectReason) -> String {
+    match reason {
+        DisconnectReason::Closed => "Connection closed".into(),
+        DisconnectReason::ConnectFailed(err) => format!("Connection failed: {err}"),
+        DisconnectReason::Error(err) => format!("Connection lost: {err}"),
+    }
+}
+
+/// Writes the header and the rows of a table to a CSV file.
+pub fn write_table_csv(table: &Table, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    let mut writer = csv::Writer::from_path(path)?;
+    if let Some(header) = table.header() {
+        writer.write_record(header.cell_iter().map(|c| c.content()))?;
+    }
+    for row in table.row_iter() {
+        writer.write_record(row.cell_iter().map(|c| c.content()))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
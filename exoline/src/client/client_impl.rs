@@ -1,16 +1,30 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{collections::VecDeque, error::Error, fmt::Display, sync::Arc};
 
-use tokio::{net::TcpStream, task::AbortHandle};
+use std::future::Future;
+
+#[cfg(feature = "tokio")]
+use tokio::net::TcpStream;
+use tokio::{select, task::AbortHandle};
 use tokio::{
-    sync::{oneshot, Mutex},
-    task::JoinHandle,
+    sync::{broadcast, mpsc, oneshot, watch, Mutex, MutexGuard, Notify},
+    task::{JoinError, JoinHandle, JoinSet},
 };
 
-use crate::controller::{File, FileKind, Variable, VariableKind};
+use crate::commands::*;
+use crate::controller::{Controller, File, FileKind, Variable, VariableKind};
 
-use super::internal::{command_id::CommandId, commands::*, connection::*, encoding::*};
+use super::internal::command_id::{CommandId, ResponseShape};
+use super::internal::{connection::*, encoding::*};
 use super::{exoline_exception::EXOlineException, variant::Variant};
+use super::{ConnectionState, DisconnectReason, InFlightPolicy, ReconnectOptions, RetryPolicy};
+#[cfg(feature = "unstable-stats")]
+use super::{ControllerStats, DPacStats, TaskStats};
+use super::{ClientMetrics, MetricsEvent, MetricsObserver, ResponseOutcome};
+use super::{Frame, FrameObserver, Priority, SubscribedVariable, Subscription, Transport};
 
 /// Errors returned by the [`EXOlineTCPClient`].
 #[derive(Debug, Clone)]
@@ -26,6 +40,11 @@ pub enum EXOlineError {
     InvalidResponse(&'static str),
     /// Exception code reported by the server.
     ExolineException(EXOlineException),
+    /// No response was received within the timeout.
+    Timeout,
+    /// A response didn't fit the request it was paired with, so the responses no longer match the requests.
+    /// Every request that was waiting for a response fails with this error.
+    OutOfSync,
 }
 
 impl Display for EXOlineError {
@@ -36,471 +55,1560 @@ impl Display for EXOlineError {
             Self::Internal(err) => write!(f, "Internal error: {err}"),
             Self::InvalidResponse(err) => write!(f, "Invalid response: {err}"),
             Self::ExolineException(ex) => write!(f, "{ex:?}"),
+            Self::Timeout => write!(f, "Timeout"),
+            Self::OutOfSync => write!(f, "The responses got out of sync with the requests"),
         }
     }
 }
 
 impl Error for EXOlineError {}
 
+impl EXOlineError {
+    /// Whether sending the request again may succeed.
+    ///
+    /// Errors in the transfer are transient. Exceptions from the controller, and errors in the request itself, are not.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::IO(_) | Self::InvalidResponse(_) | Self::Timeout | Self::OutOfSync => true,
+            Self::InvalidArguments(_) | Self::Internal(_) | Self::ExolineException(_) => false,
+        }
+    }
+}
+
 impl From<DecodeError> for EXOlineError {
     fn from(_: DecodeError) -> Self {
         EXOlineError::InvalidResponse("Error when decoding response")
     }
 }
 
+/// The TCP port EXOline devices listen on.
+pub const EXOLINE_TCP_PORT: u16 = 26486;
+
+const DEFAULT_CONCURRENCY: usize = 8;
+const DEFAULT_IN_FLIGHT_WINDOW: usize = 1;
+/// How long the connection must be quiet before the client is back in sync.
+const RESYNC_QUIET_TIME: Duration = Duration::from_millis(200);
+/// How long a request in flight is kept after its caller has given up, so that a late response is thrown away
+/// instead of being handed to the next request.
+///
+/// Responses don't tell which request they answer, so a response that arrives even later is paired with the next request.
+/// That is only caught when the response doesn't fit the request, see [response_fits].
+const LATE_RESPONSE_GRACE: Duration = Duration::from_secs(1);
+
 type ResponseResult = Result<Vec<u8>, EXOlineError>;
-type ResponseQueue = Arc<Mutex<VecDeque<oneshot::Sender<ResponseResult>>>>;
+type ResponseQueue = Mutex<VecDeque<ResponseSlot>>;
+
+/// A request waiting to be written to the connection.
+struct PendingRequest {
+    data: Vec<u8>,
+    address: (u8, u8),
+    command_id: CommandId,
+    priority: Priority,
+    /// Whether sending the request twice is harmless. Writes are only when the client says so.
+    idempotent: bool,
+    sender: oneshot::Sender<ResponseResult>,
+}
+
+impl PendingRequest {
+    /// The payload a response to this request can have.
+    fn response_shape(&self) -> ResponseShape {
+        let request = unescape(&self.data);
+        // The address and command come before the payload, and the CRC after it.
+        let payload = request.get(3..request.len().saturating_sub(1)).unwrap_or_default();
+        self.command_id.response_shape(payload)
+    }
+}
+
+/// Requests waiting for room in the in-flight window.
+#[derive(Default)]
+struct WaitingRequests {
+    /// Requests that go before anything else, like retries after a reconnect.
+    first: VecDeque<PendingRequest>,
+    by_priority: BTreeMap<Priority, VecDeque<PendingRequest>>,
+}
+
+impl WaitingRequests {
+    fn push(&mut self, request: PendingRequest) {
+        self.by_priority.entry(request.priority).or_default().push_back(request);
+    }
+
+    fn len(&self) -> usize {
+        self.first.len() + self.by_priority.values().map(VecDeque::len).sum::<usize>()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Takes the oldest request of the highest priority.
+    fn pop(&mut self) -> Option<PendingRequest> {
+        self.first.pop_front().or_else(|| self.by_priority.values_mut().rev().find_map(VecDeque::pop_front))
+    }
+}
+
+/// A request that has been written and is waiting for its response.
+/// Slots are kept for [LATE_RESPONSE_GRACE] after the caller has given up, so that a late response is thrown away.
+struct ResponseSlot {
+    request: PendingRequest,
+    /// When the client noticed that the caller had given up.
+    given_up: Option<Instant>,
+    /// When the request was queued for writing.
+    sent: Instant,
+}
+
+impl ResponseSlot {
+    /// Notes when the caller has given up.
+    fn check_given_up(&mut self, now: Instant) {
+        if self.given_up.is_none() && self.request.sender.is_closed() {
+            self.given_up = Some(now);
+        }
+    }
+
+    /// Tells if the response is assumed to be lost.
+    fn is_lost(&self, now: Instant) -> bool {
+        self.given_up.is_some_and(|given_up| now >= given_up + LATE_RESPONSE_GRACE)
+    }
+}
+
+/// Wakes the task that owns the connection when the caller stops waiting for a response,
+/// so that the request can be given up on after [LATE_RESPONSE_GRACE].
+struct WaitGuard<'a>(&'a Shared);
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        self.0.slot_freed.notify_one();
+    }
+}
+
+/// A unit of work for [EXOlineTCPClient::read_many].
+enum ReadJob {
+    Single(Variable),
+    Page {
+        file_kind: FileKind,
+        load_number: u8,
+        page: u8,
+        slots: Vec<PageSlot>,
+    },
+}
+
+/// A variable and the bytes it occupies in a page.
+type PageSlot = (Variable, Range<usize>);
+
+/// Why a connection stopped being used.
+enum ConnectionEnd {
+    /// Every clone of the client was dropped.
+    Dropped,
+    /// The connection was lost.
+    Lost(DisconnectReason),
+}
+
+/// State shared by the client and the task that owns the connection.
+struct Shared {
+    /// Forgotten on every new connection, since the device may have been replaced or updated meanwhile.
+    capabilities: std::sync::Mutex<Capabilities>,
+    logins: std::sync::Mutex<Logins>,
+    frame_observer: std::sync::RwLock<Option<Arc<dyn FrameObserver>>>,
+    metrics: std::sync::Mutex<ClientMetrics>,
+    metrics_observer: std::sync::RwLock<Option<Arc<dyn MetricsObserver>>>,
+    in_flight_window: AtomicUsize,
+    /// Notified when a request in flight has been answered or failed, or its caller has stopped waiting.
+    slot_freed: Notify,
+}
+
+impl Shared {
+    fn frame_observer(&self) -> Option<Arc<dyn FrameObserver>> {
+        self.frame_observer.read().unwrap().clone()
+    }
+
+    /// Notes the access level a login gave, or that the login failed.
+    fn record_login(&self, address: (u8, u8), response_data: &[u8]) {
+        let access_level = decode_response(response_data).and_then(|data| Ok(LoginResponse::decode_from_bytes(&data)?.access_level));
+        let mut logins = self.logins.lock().unwrap();
+        match access_level {
+            Ok(access_level) => logins.access_levels.insert(address, access_level),
+            Err(_) => logins.access_levels.remove(&address),
+        };
+    }
+
+    fn record(&self, event: MetricsEvent) {
+        self.metrics.lock().unwrap().record(&event);
+        let observer = self.metrics_observer.read().unwrap().clone();
+        if let Some(observer) = observer {
+            observer.on_event(&event);
+        }
+    }
+}
+
+/// Publishes the connection state to the client.
+struct StateNotifier {
+    state: watch::Sender<ConnectionState>,
+    events: broadcast::Sender<ConnectionState>,
+}
+
+impl StateNotifier {
+    fn set(&self, state: ConnectionState) {
+        _ = self.events.send(state.clone());
+        self.state.send_replace(state);
+    }
+}
 
 /// EXOline TCP client. Supports reading and writing to a device.
+///
+/// Cloning the client is cheap. All clones share the same connection.
+#[derive(Clone)]
 pub struct EXOlineTCPClient {
-    connection: Arc<Connection>,
-    response_queue: ResponseQueue,
+    inner: Arc<ClientInner>,
+    timeout: Option<Duration>,
+    priority: Option<Priority>,
+    retry_policy: Option<RetryPolicy>,
+    idempotent_writes: bool,
+    specific_commands: bool,
+    concurrency: usize,
+}
+
+struct ClientInner {
+    request_sender: mpsc::UnboundedSender<PendingRequest>,
+    /// The connection is established again when it's lost.
+    reconnects: bool,
+    shared: Arc<Shared>,
     abort_handle: AbortHandle,
+    state: watch::Receiver<ConnectionState>,
+    events: broadcast::Sender<ConnectionState>,
+}
+
+/// What the controllers that the client has talked to don't support.
+#[derive(Default)]
+struct Capabilities {
+    /// Controllers and kinds of files where the generic commands are rejected, and the file specific ones are needed.
+    specific_command_files: HashSet<((u8, u8), FileKind)>,
+}
+
+/// Passwords used to log in to controllers, and the access levels they gave on the current connection.
+#[derive(Default)]
+struct Logins {
+    passwords: HashMap<(u8, u8), String>,
+    access_levels: HashMap<(u8, u8), u8>,
+}
+
+impl Logins {
+    /// The system password of `controller`, if it requires a password.
+    /// A controller that requires a password but has none configured gets an empty one.
+    fn for_controller(controller: &Controller) -> Self {
+        let mut logins = Self::default();
+        if controller.require_password {
+            logins.passwords.insert(controller.address, controller.system_password.clone().unwrap_or_default());
+        }
+        logins
+    }
+}
+
+impl Drop for ClientInner {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+    }
 }
 
 impl EXOlineTCPClient {
-    pub fn new(stream: TcpStream) -> (Self, JoinHandle<Result<(), EXOlineError>>) {
-        let connection = Arc::new(Connection::new(stream));
-        let response_queue = Arc::new(Mutex::new(VecDeque::new()));
+    /// Creates a client using an established connection, like a [TcpStream](tokio::net::TcpStream) or any other [Transport].
+    /// The client stops working when the connection is lost.
+    pub fn new(stream: impl Transport) -> (Self, JoinHandle<Result<(), EXOlineError>>) {
+        Self::spawn(ConnectionState::Connected, Logins::default(), |request_receiver, notifier, shared| {
+            Self::run(stream, request_receiver, notifier, shared)
+        })
+    }
+
+    /// Creates a client that calls `connect` in the background to establish a connection,
+    /// and calls it again whenever the connection is lost.
+    ///
+    /// Requests made while disconnected are sent once a connection is established.
+    /// The task only ends when all clones of the client are dropped, or when `options.max_attempts` is reached.
+    pub fn new_reconnecting<C, F, S>(connect: C, options: ReconnectOptions) -> (Self, JoinHandle<Result<(), EXOlineError>>)
+    where
+        C: FnMut() -> F + Send + 'static,
+        F: Future<Output = std::io::Result<S>> + Send + 'static,
+        S: Transport,
+    {
+        Self::spawn_reconnecting(connect, options, Logins::default())
+    }
 
-        let join_handle = tokio::spawn(Self::receive_response(connection.clone(), response_queue.clone()));
+    /// Creates a client that connects to `addr` over TCP in the background, and connects again whenever the connection is lost.
+    ///
+    /// See [new_reconnecting](Self::new_reconnecting).
+    #[cfg(feature = "tokio")]
+    pub fn connect_with_reconnect(addr: impl Into<String>, options: ReconnectOptions) -> (Self, JoinHandle<Result<(), EXOlineError>>) {
+        Self::spawn_reconnecting(tcp_connector(addr.into()), options, Logins::default())
+    }
+
+    /// Like [connect_with_reconnect](Self::connect_with_reconnect), but if `controller` requires a password,
+    /// the client logs in with its system password before anything else is sent on every connection.
+    ///
+    /// See [log_in](Self::log_in) and [access_level](Self::access_level).
+    #[cfg(feature = "tokio")]
+    pub fn connect_to_controller(
+        addr: impl Into<String>,
+        controller: &Controller,
+        options: ReconnectOptions,
+    ) -> (Self, JoinHandle<Result<(), EXOlineError>>) {
+        Self::spawn_reconnecting(tcp_connector(addr.into()), options, Logins::for_controller(controller))
+    }
+
+    fn spawn_reconnecting<C, F, S>(connect: C, options: ReconnectOptions, logins: Logins) -> (Self, JoinHandle<Result<(), EXOlineError>>)
+    where
+        C: FnMut() -> F + Send + 'static,
+        F: Future<Output = std::io::Result<S>> + Send + 'static,
+        S: Transport,
+    {
+        Self::spawn(ConnectionState::Connecting, logins, |request_receiver, notifier, shared| {
+            Self::run_reconnecting(connect, options, request_receiver, notifier, shared)
+        })
+    }
+
+    fn spawn<F, Fut>(initial_state: ConnectionState, logins: Logins, run: F) -> (Self, JoinHandle<Result<(), EXOlineError>>)
+    where
+        F: FnOnce(mpsc::UnboundedReceiver<PendingRequest>, StateNotifier, Arc<Shared>) -> Fut,
+        Fut: Future<Output = Result<(), EXOlineError>> + Send + 'static,
+    {
+        // Only clients that reconnect start out without a connection.
+        let reconnects = matches!(initial_state, ConnectionState::Connecting);
+        let (request_sender, request_receiver) = mpsc::unbounded_channel();
+        let (state_sender, state) = watch::channel(initial_state);
+        let (events, _) = broadcast::channel(16);
+
+        let notifier = StateNotifier {
+            state: state_sender,
+            events: events.clone(),
+        };
+        let shared = Arc::new(Shared {
+            capabilities: Default::default(),
+            logins: std::sync::Mutex::new(logins),
+            frame_observer: Default::default(),
+            metrics: Default::default(),
+            metrics_observer: Default::default(),
+            in_flight_window: AtomicUsize::new(DEFAULT_IN_FLIGHT_WINDOW),
+            slot_freed: Notify::new(),
+        });
+        let join_handle = tokio::spawn(run(request_receiver, notifier, shared.clone()));
 
         let client = Self {
-            connection,
-            response_queue,
-            abort_handle: join_handle.abort_handle(),
+            inner: Arc::new(ClientInner {
+                request_sender,
+                reconnects,
+                shared,
+                abort_handle: join_handle.abort_handle(),
+                state,
+                events,
+            }),
+            timeout: None,
+            priority: None,
+            retry_policy: None,
+            idempotent_writes: false,
+            specific_commands: false,
+            concurrency: DEFAULT_CONCURRENCY,
         };
 
         (client, join_handle)
     }
 
-    async fn receive_response(connection: Arc<Connection>, response_queue: ResponseQueue) -> Result<(), EXOlineError> {
-        loop {
-            let msg = match connection.read_response().await {
-                Ok(Some(msg)) => msg,
-                Ok(None) => return Ok(()),
-                Err(error) => {
-                    let error = match error {
-                        ReadError::IO(error) => EXOlineError::IO(error.into()),
-                        ReadError::InvalidData => EXOlineError::InvalidResponse("The server sent invalid data"),
-                    };
-                    let mut response_queue = response_queue.lock().await;
-                    while let Some(sender) = response_queue.pop_front() {
-                        _ = sender.send(Err(error.clone()));
-                    }
-                    return Err(error);
-                }
-            };
+    /// The time to wait for a response before failing with [EXOlineError::Timeout].
+    /// `None` waits forever, which is the default.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
 
-            let sender = response_queue.lock().await.pop_front();
-            match sender {
-                None => return Err(EXOlineError::InvalidResponse("The server sent an unexpected response")),
-                Some(sender) => _ = sender.send(Ok(msg)),
-            }
+    /// Sets the time to wait for a response. `None` waits forever.
+    ///
+    /// After a timeout, or when a request is cancelled by dropping its future, nothing else is sent in its place for a second,
+    /// so that a late response can be thrown away. Responses don't tell which request they answer,
+    /// so one that arrives even later is taken as the answer to the next request, unless it doesn't fit that request.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Returns a client that shares the connection but uses a different timeout.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..self.clone()
         }
     }
-}
 
-impl EXOlineTCPClient {
-    /// Reads the EXOline address of the connected controller.
-    pub async fn read_exoline_address(&self) -> Result<(u8, u8), EXOlineError> {
-        let pla = self.read_variable_raw((255, 30), FileKind::VPac, 0xF1, VariableKind::Index, 0).await?;
-        let ela = self.read_variable_raw((255, 30), FileKind::VPac, 0xF1, VariableKind::Index, 1).await?;
-        let address = (pla.index().unwrap(), ela.index().unwrap());
-        Ok(address)
+    /// The priority of the requests made with this client. `None` picks one per request, see [Priority].
+    pub fn priority(&self) -> Option<Priority> {
+        self.priority
     }
 
-    /// Reads a page from a DPac. Strings are not read.
-    pub async fn read_dpac_page(&self, address: (u8, u8), file: &File, page: u8) -> Result<HashMap<Variable, Variant>, EXOlineError> {
-        self.read_dpac_internal(address, file, Some(page)).await
+    /// Sets the priority of the requests made with this client. `None` picks one per request, which is the default.
+    pub fn set_priority(&mut self, priority: Option<Priority>) {
+        self.priority = priority;
     }
 
-    /// Reads an entire DPac. Strings are not read.
-    pub async fn read_dpac(&self, address: (u8, u8), file: &File) -> Result<HashMap<Variable, Variant>, EXOlineError> {
-        self.read_dpac_internal(address, file, None).await
+    /// Returns a client that shares the connection but sends its requests with a different priority.
+    pub fn with_priority(&self, priority: Priority) -> Self {
+        Self {
+            priority: Some(priority),
+            ..self.clone()
+        }
     }
 
-    async fn read_dpac_internal(&self, address: (u8, u8), file: &File, only_page: Option<u8>) -> Result<HashMap<Variable, Variant>, EXOlineError> {
-        match file.kind() {
-            FileKind::BPac | FileKind::VPac => {}
-            _ => return Err(EXOlineError::InvalidArguments("Can only read pages from DPac's")),
+    /// A client that sends with [Priority::Bulk], unless a priority has been set.
+    fn bulk(&self) -> Self {
+        Self {
+            priority: Some(self.priority.unwrap_or(Priority::Bulk)),
+            ..self.clone()
         }
+    }
 
-        let mut result = HashMap::with_capacity(only_page.map(|_| 60).unwrap_or_else(|| file.len()));
+    /// The policy for sending failed requests again. `None` never retries, which is the default.
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_ref()
+    }
 
-        let mut data = Vec::new();
-        let mut page: i32 = -1;
+    /// Sets the policy for sending failed requests again. `None` never retries.
+    pub fn set_retry_policy(&mut self, retry_policy: Option<RetryPolicy>) {
+        self.retry_policy = retry_policy;
+    }
 
-        for variable in file.iter() {
-            if variable.kind() == VariableKind::String {
-                continue;
+    /// Returns a client that shares the connection but sends failed requests again according to `retry_policy`.
+    pub fn with_retry_policy(&self, retry_policy: RetryPolicy) -> Self {
+        Self {
+            retry_policy: Some(retry_policy),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a client that shares the connection but also sends failed writes again, according to the retry policy.
+    /// Only use it for writes where performing the write twice is harmless, such as setting a variable to a value.
+    pub fn with_idempotent_writes(&self) -> Self {
+        Self {
+            idempotent_writes: true,
+            ..self.clone()
+        }
+    }
+
+    /// Returns a client that shares the connection but switches to the BPac and task specific commands when a controller
+    /// rejects the generic commands for a BPac or task with [EXOlineException::IllegalCommand]. The layouts of the specific commands are
+    /// not documented, so they are never sent unless asked for.
+    pub fn with_specific_commands(&self) -> Self {
+        Self {
+            specific_commands: true,
+            ..self.clone()
+        }
+    }
+
+    /// The number of requests that are sent before their responses have arrived.
+    pub fn in_flight_window(&self) -> usize {
+        self.inner.shared.in_flight_window.load(Ordering::Relaxed)
+    }
+
+    /// Sets the number of requests that are sent before their responses have arrived. The default is 1,
+    /// which suits devices that handle one request at a time. Other requests wait their turn, see [Priority].
+    /// The window is shared by all clones of the client.
+    pub fn set_in_flight_window(&self, window: usize) {
+        self.inner.shared.in_flight_window.store(window.max(1), Ordering::Relaxed);
+        self.inner.shared.slot_freed.notify_one();
+    }
+
+    /// The number of requests [read_many](Self::read_many) keeps in flight at once.
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    /// Sets the number of requests [read_many](Self::read_many) keeps in flight at once. The default is 8.
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.concurrency = concurrency.max(1);
+    }
+
+    /// Sets an observer that receives every frame sent and received on the connection, or removes it with `None`.
+    /// The observer is shared by all clones of the client.
+    pub fn set_frame_observer(&self, observer: Option<Arc<dyn FrameObserver>>) {
+        *self.inner.shared.frame_observer.write().unwrap() = observer;
+    }
+
+    /// A snapshot of the measurements made so far. The measurements are shared by all clones of the client.
+    pub fn metrics(&self) -> ClientMetrics {
+        self.inner.shared.metrics.lock().unwrap().clone()
+    }
+
+    /// Sets an observer that receives every measurement as it's made, or removes it with `None`.
+    /// The observer is shared by all clones of the client.
+    pub fn set_metrics_observer(&self, observer: Option<Arc<dyn MetricsObserver>>) {
+        *self.inner.shared.metrics_observer.write().unwrap() = observer;
+    }
+
+    /// The current state of the connection.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.inner.state.borrow().clone()
+    }
+
+    /// Subscribes to changes of the connection state.
+    /// Only changes after the call are received.
+    pub fn connection_events(&self) -> broadcast::Receiver<ConnectionState> {
+        self.inner.events.subscribe()
+    }
+
+    async fn run(
+        stream: impl Transport,
+        mut request_receiver: mpsc::UnboundedReceiver<PendingRequest>,
+        notifier: StateNotifier,
+        shared: Arc<Shared>,
+    ) -> Result<(), EXOlineError> {
+        let response_queue = ResponseQueue::default();
+        let mut waiting = WaitingRequests::default();
+
+        let connection = Connection::new(stream);
+        let reason = match Self::run_connection(connection, &response_queue, &mut request_receiver, &mut waiting, Vec::new(), &shared).await {
+            ConnectionEnd::Dropped => return Ok(()),
+            ConnectionEnd::Lost(reason) => reason,
+        };
+
+        let result = match &reason {
+            DisconnectReason::Error(error) | DisconnectReason::ConnectFailed(error) => {
+                Self::fail_all(&response_queue, error).await;
+                Err(error.clone())
             }
+            DisconnectReason::Closed => Ok(()),
+        };
+        notifier.set(ConnectionState::Disconnected(reason));
 
-            let file_offset = variable.offset() as usize;
+        result
+    }
 
-            let (page_size, page_offset) = match file.kind() {
-                FileKind::BPac => (variable.kind().page_size_of_bpac_variable() as usize, file_offset),
-                FileKind::VPac => (variable.kind().page_size_of_vpac_variable() as usize, file_offset * 2),
-                _ => unreachable!(),
+    async fn run_reconnecting<C, F, S>(
+        mut connect: C,
+        options: ReconnectOptions,
+        mut request_receiver: mpsc::UnboundedReceiver<PendingRequest>,
+        notifier: StateNotifier,
+        shared: Arc<Shared>,
+    ) -> Result<(), EXOlineError>
+    where
+        C: FnMut() -> F,
+        F: Future<Output = std::io::Result<S>>,
+        S: Transport,
+    {
+        let mut retry: Vec<PendingRequest> = Vec::new();
+        let mut waiting = WaitingRequests::default();
+        let mut connected_before = false;
+        let mut backoff = options.initial_backoff;
+        let mut failed_attempts = 0;
+
+        loop {
+            notifier.set(ConnectionState::Connecting);
+
+            let stream = match tokio::time::timeout(options.connect_timeout, connect()).await {
+                Ok(Ok(stream)) => Ok(stream),
+                Ok(Err(error)) => Err(EXOlineError::IO(error.into())),
+                Err(_) => Err(EXOlineError::Timeout),
             };
 
-            if let Some(only_page) = only_page {
-                if page_offset / 120 != only_page as usize {
-                    continue;
-                }
-            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    failed_attempts += 1;
+                    notifier.set(ConnectionState::Disconnected(DisconnectReason::ConnectFailed(error.clone())));
 
-            let mut bytes = None;
-            loop {
-                let data_offset = match only_page {
-                    None => page_offset,
-                    Some(_) => page_offset % 120,
-                };
-                match data.get(data_offset..data_offset + page_size).map(|b| b.to_owned()) {
-                    Some(b) => {
-                        bytes = Some(b);
-                        break;
-                    }
-                    None => {
-                        if page >= 0xFF {
-                            break;
-                        }
-                        page += 1;
-                        match self
-                            .read_dpac_page_raw(address, file.kind(), file.load_number(), only_page.unwrap_or(page as u8))
-                            .await
-                        {
-                            Ok(mut next_data) => {
-                                next_data.resize(120, 0); // in case
-                                data.extend(next_data);
-                                if only_page.is_some() {
-                                    page = 0xFF;
-                                }
-                            }
-                            Err(EXOlineError::ExolineException(EXOlineException::AddressOutsideRange)) => {
-                                page = 0xFF; // No more pages.
-                                break;
-                            }
-                            Err(err) => return Err(err),
+                    if options.max_attempts.is_some_and(|max_attempts| failed_attempts >= max_attempts) {
+                        for request in retry {
+                            _ = request.sender.send(Err(error.clone()));
                         }
+                        return Err(error);
                     }
+
+                    tokio::time::sleep(backoff).await;
+                    backoff = backoff.mul_f64(options.backoff_multiplier).min(options.max_backoff);
+                    continue;
                 }
+            };
+
+            failed_attempts = 0;
+            backoff = options.initial_backoff;
+            *shared.capabilities.lock().unwrap() = Capabilities::default();
+            shared.logins.lock().unwrap().access_levels.clear();
+            notifier.set(ConnectionState::Connected);
+            if std::mem::replace(&mut connected_before, true) {
+                shared.record(MetricsEvent::Reconnected);
             }
 
-            let bytes = match bytes {
-                None => continue,
-                Some(bytes) => bytes,
+            // Logging in has to come before anything else on the new connection.
+            let mut requests = Self::login_requests(&shared, options.connect_timeout);
+            requests.append(&mut retry);
+
+            let response_queue = ResponseQueue::default();
+            let connection = Connection::new(stream);
+            let reason = match Self::run_connection(connection, &response_queue, &mut request_receiver, &mut waiting, requests, &shared).await {
+                ConnectionEnd::Dropped => return Ok(()),
+                ConnectionEnd::Lost(reason) => reason,
             };
 
-            let variant = match file.kind() {
-                FileKind::VPac => match variable.kind() {
-                    VariableKind::Huge => Variant::Huge(i32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]])),
-                    VariableKind::Index => Variant::Index(bytes[1]),
-                    VariableKind::Integer => Variant::Integer(i16::from_le_bytes([bytes[1], bytes[2]])),
-                    VariableKind::Logic => Variant::Logic(bytes[1] != 0),
-                    VariableKind::Real => Variant::Real(f32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]])),
-                    VariableKind::String => unreachable!(),
-                },
-                FileKind::BPac => match variable.kind() {
-                    VariableKind::Huge => Variant::Huge(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
-                    VariableKind::Index => Variant::Index(bytes[0]),
-                    VariableKind::Integer => Variant::Integer(i16::from_le_bytes([bytes[0], bytes[1]])),
-                    VariableKind::Logic => Variant::Logic(bytes[0] != 0),
-                    VariableKind::Real => Variant::Real(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
-                    VariableKind::String => unreachable!(),
-                },
-                _ => unreachable!(),
+            retry = match options.in_flight {
+                InFlightPolicy::Retry => {
+                    // A write may have been performed before the connection was lost, so only idempotent ones are sent again.
+                    let (retry, writes): (Vec<_>, Vec<_>) =
+                        Self::take_unanswered(&response_queue).await.into_iter().partition(|request| request.idempotent);
+                    let error = match &reason {
+                        DisconnectReason::Error(error) => error.clone(),
+                        _ => not_connected(),
+                    };
+                    for request in writes {
+                        _ = request.sender.send(Err(error.clone()));
+                    }
+                    retry
+                }
+                InFlightPolicy::Fail => {
+                    if let DisconnectReason::Error(error) = &reason {
+                        Self::fail_all(&response_queue, error).await;
+                    }
+                    // Otherwise the slots are dropped, and the callers see that the connection is gone.
+                    Vec::new()
+                }
             };
 
-            result.insert(variable, variant);
+            notifier.set(ConnectionState::Disconnected(reason));
         }
-
-        Ok(result)
     }
 
-    /// Read a page from a DPac by manually providing the parameters
-    pub async fn read_dpac_page_raw(&self, address: (u8, u8), file_kind: FileKind, load_number: u8, page: u8) -> Result<Vec<u8>, EXOlineError> {
-        match file_kind {
-            FileKind::BPac | FileKind::VPac => {}
-            _ => {
-                return Err(EXOlineError::InvalidArguments("Can only read pages from DPac's"));
-            }
-        }
-        let response_data = self
-            .send_request(address, CommandId::ReadDPacPage, &ReadDPacPageRequest { load_number, page })
-            .await?;
-        let response = ReadDPacPageResponse::decode_from_bytes(&response_data)?;
-        Ok(response.data.into())
-    }
+    /// Creates requests that log in with every password that has been used.
+    /// A login that isn't answered within `timeout` is given up on.
+    fn login_requests(shared: &Arc<Shared>, timeout: Duration) -> Vec<PendingRequest> {
+        let passwords = shared.logins.lock().unwrap().passwords.clone();
 
-    /// Read an entire DPac by manually providing the parameters
-    pub async fn read_dpac_raw(&self, address: (u8, u8), file_kind: FileKind, load_number: u8) -> Result<Vec<u8>, EXOlineError> {
-        let mut data = Vec::new();
+        let mut requests = Vec::with_capacity(passwords.len());
+        for (address, password) in passwords {
+            let Ok(data) = encode_request(address, CommandId::Login, &LoginRequest { password: password.into() }) else {
+                continue;
+            };
+            let (sender, receiver) = oneshot::channel::<ResponseResult>();
+            requests.push(PendingRequest {
+                data,
+                address,
+                command_id: CommandId::Login,
+                priority: Priority::Interactive,
+                // The next connection logs in again anyway.
+                idempotent: false,
+                sender,
+            });
 
-        for page in 0..=0xFF {
-            match self.read_dpac_page_raw(address, file_kind, load_number, page).await {
-                Ok(bytes) => data.extend(bytes),
-                Err(EXOlineError::ExolineException(EXOlineException::AddressOutsideRange)) => break,
-                Err(err) => return Err(err),
-            }
+            // The access level is noted when the response is received, this only waits for it.
+            let shared = shared.clone();
+            tokio::spawn(async move {
+                _ = tokio::time::timeout(timeout, receiver).await;
+                shared.slot_freed.notify_one();
+            });
         }
-
-        Ok(data)
+        requests
     }
 
-    /// Read a variable
-    pub async fn read_variable(&self, address: (u8, u8), variable: &Variable) -> Result<Variant, EXOlineError> {
-        self.read_variable_raw(address, variable.file_kind(), variable.load_number(), variable.kind(), variable.offset())
-            .await
+    async fn run_connection(
+        connection: Connection,
+        response_queue: &ResponseQueue,
+        request_receiver: &mut mpsc::UnboundedReceiver<PendingRequest>,
+        waiting: &mut WaitingRequests,
+        retry: Vec<PendingRequest>,
+        shared: &Shared,
+    ) -> ConnectionEnd {
+        select! {
+            end = Self::receive_responses(&connection, response_queue, shared) => end,
+            end = Self::send_requests(&connection, response_queue, request_receiver, waiting, retry, shared) => end,
+        }
     }
 
-    /// Read a variable by manually providing the parameters
-    pub async fn read_variable_raw(
-        &self,
-        address: (u8, u8),
-        file_kind: FileKind,
-        load_number: u8,
-        variable_kind: VariableKind,
-        offset: u32,
-    ) -> Result<Variant, EXOlineError> {
-        match file_kind {
-            FileKind::Task => {
-                let request = ReadRequest {
-                    kind: CommandFileKind::Task,
-                    load_number,
-                    offset,
-                };
-                match variable_kind {
-                    VariableKind::Huge => {
-                        let response_data = self.send_request(address, CommandId::ReadHuge, &request).await?;
-                        let response = ReadHugeResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Huge(response.value))
-                    }
-                    VariableKind::Index => {
-                        let response_data = self.send_request(address, CommandId::ReadIndex, &request).await?;
-                        let response = ReadIndexResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Index(response.value))
-                    }
-                    VariableKind::Integer => {
-                        let response_data = self.send_request(address, CommandId::ReadInteger, &request).await?;
-                        let response = ReadIntegerResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Integer(response.value))
-                    }
-                    VariableKind::Logic => {
-                        let response_data = self.send_request(address, CommandId::ReadLogic, &request).await?;
-                        let response = ReadLogicResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Logic(response.value))
-                    }
-                    VariableKind::Real => {
-                        let response_data = self.send_request(address, CommandId::ReadReal, &request).await?;
-                        let response = ReadRealResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Real(response.value))
-                    }
-                    VariableKind::String => {
-                        let response_data = self.send_request(address, CommandId::ReadString, &request).await?;
-                        let response = ReadStringResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::String(response.value.to_string()))
-                    }
+    async fn send_requests(
+        connection: &Connection,
+        response_queue: &ResponseQueue,
+        request_receiver: &mut mpsc::UnboundedReceiver<PendingRequest>,
+        waiting: &mut WaitingRequests,
+        retry: Vec<PendingRequest>,
+        shared: &Shared,
+    ) -> ConnectionEnd {
+        for request in retry.into_iter().rev() {
+            waiting.first.push_front(request);
+        }
+
+        let mut queue_depth = 0;
+        loop {
+            // Everything that has been queued competes for the next free slot.
+            while let Ok(request) = request_receiver.try_recv() {
+                waiting.push(request);
+            }
+            if waiting.len() != queue_depth {
+                queue_depth = waiting.len();
+                shared.record(MetricsEvent::QueueDepth(queue_depth));
+            }
+
+            // Checked even when nothing is waiting, so that the grace period starts as soon as a caller gives up.
+            if Self::has_room(response_queue, shared).await && !waiting.is_empty() {
+                let request = waiting.pop().unwrap();
+                if let Err(error) = Self::send_pending_request(connection, response_queue, request, shared).await {
+                    return ConnectionEnd::Lost(DisconnectReason::Error(error));
                 }
+                continue;
             }
-            FileKind::VPac => {
-                let request = ReadRequest {
-                    kind: CommandFileKind::VPac,
-                    load_number,
-                    offset,
-                };
-                match variable_kind {
-                    VariableKind::Huge => {
-                        let response_data = self.send_request(address, CommandId::ReadHuge, &request).await?;
-                        let response = ReadHugeResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Huge(response.value))
-                    }
-                    VariableKind::Index => {
-                        let response_data = self.send_request(address, CommandId::ReadIndex, &request).await?;
-                        let response = ReadIndexResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Index(response.value))
-                    }
-                    VariableKind::Integer => {
-                        let response_data = self.send_request(address, CommandId::ReadInteger, &request).await?;
-                        let response = ReadIntegerResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Integer(response.value))
-                    }
-                    VariableKind::Logic => {
-                        let response_data = self.send_request(address, CommandId::ReadLogic, &request).await?;
-                        let response = ReadLogicResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Logic(response.value))
-                    }
-                    VariableKind::Real => {
-                        let response_data = self.send_request(address, CommandId::ReadReal, &request).await?;
-                        let response = ReadRealResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Real(response.value))
-                    }
-                    VariableKind::String => {
-                        let response_data = self.send_request(address, CommandId::ReadString, &request).await?;
-                        let response = ReadStringResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::String(response.value.to_string()))
-                    }
+
+            select! {
+                request = request_receiver.recv() => match request {
+                    Some(request) => waiting.push(request),
+                    None => return ConnectionEnd::Dropped,
+                },
+                _ = Self::slot_freed(response_queue, shared) => {}
+            }
+        }
+    }
+
+    /// Removes the oldest requests if they are assumed lost, and tells if another request fits in the in-flight window.
+    async fn has_room(response_queue: &ResponseQueue, shared: &Shared) -> bool {
+        let mut response_queue = response_queue.lock().await;
+        let now = Instant::now();
+        // Every slot is checked, so that the grace period starts as soon as the caller is noticed to have given up.
+        response_queue.iter_mut().for_each(|slot| slot.check_given_up(now));
+        let lost = response_queue.iter().take_while(|slot| slot.is_lost(now)).count();
+        response_queue.drain(..lost);
+        response_queue.len() < shared.in_flight_window.load(Ordering::Relaxed)
+    }
+
+    /// Waits until a request in flight has been answered or given up on, or until the oldest one may be assumed lost.
+    async fn slot_freed(response_queue: &ResponseQueue, shared: &Shared) {
+        let given_up = response_queue.lock().await.front().and_then(|slot| slot.given_up);
+        match given_up {
+            Some(given_up) => {
+                select! {
+                    _ = shared.slot_freed.notified() => {}
+                    _ = tokio::time::sleep_until((given_up + LATE_RESPONSE_GRACE).into()) => {}
                 }
             }
-            FileKind::BPac => {
-                let request = ReadRequest {
-                    kind: CommandFileKind::BPac,
-                    load_number,
-                    offset,
-                };
-                match variable_kind {
-                    VariableKind::Huge => {
-                        let response_data = self.send_request(address, CommandId::ReadHuge, &request).await?;
-                        let response = ReadHugeResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Huge(response.value))
-                    }
-                    VariableKind::Index => {
-                        let response_data = self.send_request(address, CommandId::ReadIndex, &request).await?;
-                        let response = ReadIndexResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Index(response.value))
-                    }
-                    VariableKind::Integer => {
-                        let response_data = self.send_request(address, CommandId::ReadInteger, &request).await?;
-                        let response = ReadIntegerResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Integer(response.value))
-                    }
-                    VariableKind::Logic => {
-                        let response_data = self.send_request(address, CommandId::ReadLogic, &request).await?;
-                        let response = ReadLogicResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Logic(response.value))
-                    }
-                    VariableKind::Real => {
-                        let response_data = self.send_request(address, CommandId::ReadReal, &request).await?;
-                        let response = ReadRealResponse::decode_from_bytes(&response_data)?;
-                        Ok(Variant::Real(response.value))
+            None => shared.slot_freed.notified().await,
+        }
+    }
+
+    async fn send_pending_request(
+        connection: &Connection,
+        response_queue: &ResponseQueue,
+        request: PendingRequest,
+        shared: &Shared,
+    ) -> Result<(), EXOlineError> {
+        if request.sender.is_closed() {
+            return Ok(()); // Cancelled before it was sent
+        }
+
+        let data = request.data.clone();
+        let (address, command_id) = (request.address, request.command_id);
+
+        response_queue.lock().await.push_back(ResponseSlot {
+            given_up: None,
+            sent: Instant::now(),
+            request,
+        });
+
+        connection.write_request(&data).await.map_err(|error| EXOlineError::IO(error.into()))?;
+
+        shared.record(MetricsEvent::RequestSent {
+            address,
+            command_id,
+            bytes: data.len() + 2,
+        });
+        if let Some(observer) = shared.frame_observer() {
+            observer.on_frame(&Frame::outgoing(&data));
+        }
+        Ok(())
+    }
+
+    async fn receive_responses(connection: &Connection, response_queue: &ResponseQueue, shared: &Shared) -> ConnectionEnd {
+        loop {
+            let msg = match connection.read_response().await {
+                Ok(Some(msg)) => msg,
+                Ok(None) => return ConnectionEnd::Lost(DisconnectReason::Closed),
+                Err(ReadError::IO(error)) => return ConnectionEnd::Lost(DisconnectReason::Error(EXOlineError::IO(error.into()))),
+                Err(ReadError::InvalidData(received)) => {
+                    shared.record(MetricsEvent::Discarded { bytes: received.len() });
+                    if let Some(observer) = shared.frame_observer() {
+                        observer.on_frame(&Frame::invalid(&received));
                     }
-                    VariableKind::String => {
-                        Err(EXOlineError::InvalidArguments("Can't read a string from a BPac"))
+                    // A response may have been lost in the invalid data.
+                    if let Err(end) = Self::resync(connection, response_queue.lock().await, shared).await {
+                        return end;
                     }
+                    continue;
                 }
-            }
-            FileKind::Text => match variable_kind {
-                VariableKind::String => {
-                    let request = ReadRequest {
-                        kind: CommandFileKind::VPac,
-                        load_number,
-                        offset,
-                    };
-                    let response_data = self.send_request(address, CommandId::ReadString, &request).await?;
-                    let response = ReadStringResponse::decode_from_bytes(&response_data)?;
-                    Ok(Variant::String(response.value.to_string()))
+            };
+
+            let mut queue = response_queue.lock().await;
+            let Some(slot) = queue.front() else {
+                // Nothing is waiting for a response, so the response is thrown away.
+                drop(queue);
+                shared.record(MetricsEvent::Discarded { bytes: msg.len() + 2 });
+                if let Some(observer) = shared.frame_observer() {
+                    observer.on_frame(&Frame::incoming(&msg, None));
                 }
-                _ => {
-                    Err(EXOlineError::InvalidArguments("Can only read strings from text files"))
+                continue;
+            };
+
+            if !response_fits(&msg, slot.request.response_shape()) {
+                shared.record(MetricsEvent::Discarded { bytes: msg.len() + 2 });
+                if let Some(observer) = shared.frame_observer() {
+                    observer.on_frame(&Frame::mismatched(&msg, (&slot.request.data, slot.sent)));
                 }
-            },
+                if let Err(end) = Self::resync(connection, queue, shared).await {
+                    return end;
+                }
+                continue;
+            }
+
+            let slot = queue.pop_front().unwrap();
+            drop(queue);
+            shared.slot_freed.notify_one();
+            if slot.request.command_id == CommandId::Login {
+                shared.record_login(slot.request.address, &msg);
+            }
+            shared.record(MetricsEvent::ResponseReceived {
+                address: slot.request.address,
+                command_id: slot.request.command_id,
+                latency: slot.sent.elapsed(),
+                bytes: msg.len() + 2,
+                outcome: response_outcome(&msg),
+            });
+            if let Some(observer) = shared.frame_observer() {
+                observer.on_frame(&Frame::incoming(&msg, Some((&slot.request.data, slot.sent))));
+            }
+            _ = slot.request.sender.send(Ok(msg)); // Thrown away if the caller has given up
         }
     }
 
-    /// Write a variable
-    pub async fn write_variable(&self, address: (u8, u8), variable: &Variable, value: &Variant) -> Result<(), EXOlineError> {
-        self.write_variable_raw(
-            address,
-            variable.file_kind(),
-            variable.load_number(),
-            variable.kind(),
-            variable.offset(),
-            value,
-        )
-        .await
+    /// Gets back in sync after the responses stopped matching the requests.
+    ///
+    /// There is no telling which requests the responses in transit belong to, so every request in flight is failed.
+    /// Responses are then thrown away until the connection has been quiet for [RESYNC_QUIET_TIME].
+    /// The queue stays locked meanwhile, so no new requests are sent.
+    async fn resync(
+        connection: &Connection,
+        mut queue: MutexGuard<'_, VecDeque<ResponseSlot>>,
+        shared: &Shared,
+    ) -> Result<(), ConnectionEnd> {
+        while let Some(slot) = queue.pop_front() {
+            _ = slot.request.sender.send(Err(EXOlineError::OutOfSync));
+        }
+        shared.slot_freed.notify_one();
+
+        loop {
+            // Reading is cancel safe, so a frame that is still arriving when the time is up is read whole later on.
+            let (frame, bytes) = match tokio::time::timeout(RESYNC_QUIET_TIME, connection.read_response()).await {
+                Err(_) => return Ok(()),
+                Ok(Ok(Some(msg))) => (Frame::incoming(&msg, None), msg.len() + 2),
+                Ok(Ok(None)) => return Err(ConnectionEnd::Lost(DisconnectReason::Closed)),
+                Ok(Err(ReadError::IO(error))) => return Err(ConnectionEnd::Lost(DisconnectReason::Error(EXOlineError::IO(error.into())))),
+                Ok(Err(ReadError::InvalidData(received))) => (Frame::invalid(&received), received.len()),
+            };
+            shared.record(MetricsEvent::Discarded { bytes });
+            if let Some(observer) = shared.frame_observer() {
+                observer.on_frame(&frame);
+            }
+        }
     }
 
-    /// Write a variable by manually providing the parameters
-    pub async fn write_variable_raw(
+    async fn fail_all(response_queue: &ResponseQueue, error: &EXOlineError) {
+        let mut response_queue = response_queue.lock().await;
+        while let Some(slot) = response_queue.pop_front() {
+            _ = slot.request.sender.send(Err(error.clone()));
+        }
+    }
+
+    /// Removes the requests that are still waiting for a response, so that they can be sent again.
+    async fn take_unanswered(response_queue: &ResponseQueue) -> Vec<PendingRequest> {
+        let mut response_queue = response_queue.lock().await;
+        response_queue
+            .drain(..)
+            .map(|slot| slot.request)
+            .filter(|request| !request.sender.is_closed())
+            .collect()
+    }
+}
+
+impl EXOlineTCPClient {
+    /// Reads the EXOline address of the connected controller.
+    pub async fn read_exoline_address(&self) -> Result<(u8, u8), EXOlineError> {
+        let pla = self.read_variable_raw((255, 30), FileKind::VPac, 0xF1, VariableKind::Index, 0).await?;
+        let ela = self.read_variable_raw((255, 30), FileKind::VPac, 0xF1, VariableKind::Index, 1).await?;
+        let address = (pla.index().unwrap(), ela.index().unwrap());
+        Ok(address)
+    }
+
+    /// Reads a page from a DPac. Strings are not read, see [read_dpac_page_with_strings](Self::read_dpac_page_with_strings).
+    pub async fn read_dpac_page(&self, address: (u8, u8), file: &File, page: u8) -> Result<HashMap<Variable, Variant>, EXOlineError> {
+        self.read_dpac_internal(address, file, Some(page)).await
+    }
+
+    /// Reads an entire DPac. Strings are not read, see [read_dpac_with_strings](Self::read_dpac_with_strings).
+    pub async fn read_dpac(&self, address: (u8, u8), file: &File) -> Result<HashMap<Variable, Variant>, EXOlineError> {
+        self.read_dpac_internal(address, file, None).await
+    }
+
+    /// Reads a page from a DPac, including strings.
+    ///
+    /// Strings are read with pipelined requests. An error reading a string is reported for that variable only.
+    pub async fn read_dpac_page_with_strings(
         &self,
         address: (u8, u8),
-        file_kind: FileKind,
-        load_number: u8,
-        variable_kind: VariableKind,
-        offset: u32,
-        value: &Variant,
-    ) -> Result<(), EXOlineError> {
-        match file_kind {
-            FileKind::Task => match variable_kind {
-                VariableKind::Huge => {
-                    let Some(value) = value.huge() else {
-                        return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
-                    };
-                    let request = WriteHugeRequest {
-                        kind: CommandFileKind::Task,
-                        load_number,
-                        offset,
-                        value,
-                    };
-                    self.send_request(address, CommandId::WriteHuge, &request).await?;
-                    Ok(())
+        file: &File,
+        page: u8,
+    ) -> Result<HashMap<Variable, Result<Variant, EXOlineError>>, EXOlineError> {
+        let values = self.read_dpac_page(address, file, page).await?;
+        let strings = file
+            .iter()
+            .filter(|variable| variable.kind() == VariableKind::String && variable.page() == page as u32)
+            .collect::<Vec<_>>();
+        Ok(self.merge_strings(address, values, &strings).await)
+    }
+
+    /// Reads an entire DPac, including strings.
+    ///
+    /// Strings are read with pipelined requests. An error reading a string is reported for that variable only.
+    pub async fn read_dpac_with_strings(
+        &self,
+        address: (u8, u8),
+        file: &File,
+    ) -> Result<HashMap<Variable, Result<Variant, EXOlineError>>, EXOlineError> {
+        let values = self.read_dpac(address, file).await?;
+        let strings = file.iter().filter(|variable| variable.kind() == VariableKind::String).collect::<Vec<_>>();
+        Ok(self.merge_strings(address, values, &strings).await)
+    }
+
+    async fn merge_strings(
+        &self,
+        address: (u8, u8),
+        values: HashMap<Variable, Variant>,
+        strings: &[Variable],
+    ) -> HashMap<Variable, Result<Variant, EXOlineError>> {
+        let mut result = values.into_iter().map(|(variable, value)| (variable, Ok(value))).collect::<HashMap<_, _>>();
+        result.extend(self.read_many(address, strings).await);
+        result
+    }
+
+    /// Reads all locals of a task. Strings are not read.
+    pub async fn read_task(&self, address: (u8, u8), file: &File) -> Result<HashMap<Variable, Variant>, EXOlineError> {
+        match file.kind() {
+            FileKind::Task => self.read_pages_internal(address, file, None).await,
+            _ => Err(EXOlineError::InvalidArguments("Can only read locals from tasks")),
+        }
+    }
+
+    /// Reads every string in a text file.
+    ///
+    /// The strings are read one by one with pipelined requests, like in [read_many](Self::read_many).
+    pub async fn read_texts(&self, address: (u8, u8), file: &File) -> Result<HashMap<Variable, Variant>, EXOlineError> {
+        if file.kind() != FileKind::Text {
+            return Err(EXOlineError::InvalidArguments("Can only read texts from text files"));
+        }
+
+        let variables = file.iter().collect::<Vec<_>>();
+        self.read_many(address, &variables)
+            .await
+            .into_iter()
+            .map(|(variable, value)| Ok((variable, value?)))
+            .collect()
+    }
+
+    /// Reads many variables, possibly from different files.
+    ///
+    /// Variables that share a DPac or task page are read with a single page read, the rest are read one by one.
+    /// The requests are pipelined, see [set_concurrency](Self::set_concurrency) and [set_in_flight_window](Self::set_in_flight_window).
+    /// Errors are reported per variable.
+    pub async fn read_many(&self, address: (u8, u8), variables: &[Variable]) -> HashMap<Variable, Result<Variant, EXOlineError>> {
+        let mut pages: HashMap<(FileKind, u8, u8), Vec<PageSlot>> = HashMap::new();
+        let mut jobs = Vec::new();
+
+        for variable in variables {
+            match page_slot(variable) {
+                Some((page, slot)) => {
+                    let key = (variable.file_kind(), variable.load_number(), page);
+                    pages.entry(key).or_default().push((variable.clone(), slot));
                 }
-                VariableKind::Index => {
-                    let Some(value) = value.index() else {
-                        return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
-                    };
-                    let request = WriteIndexRequest {
-                        kind: CommandFileKind::Task,
-                        load_number,
-                        offset,
-                        value,
-                    };
-                    self.send_request(address, CommandId::WriteIndex, &request).await?;
-                    Ok(())
+                None => jobs.push(ReadJob::Single(variable.clone())),
+            }
+        }
+
+        for ((file_kind, load_number, page), mut slots) in pages {
+            match slots.len() {
+                1 => jobs.push(ReadJob::Single(slots.pop().unwrap().0)),
+                _ => jobs.push(ReadJob::Page {
+                    file_kind,
+                    load_number,
+                    page,
+                    slots,
+                }),
+            }
+        }
+
+        let bulk = self.bulk();
+        let mut result = HashMap::with_capacity(variables.len());
+        let mut jobs = jobs.into_iter();
+        let mut join_set = JoinSet::new();
+
+        loop {
+            while join_set.len() < self.concurrency {
+                let Some(job) = jobs.next() else {
+                    break;
+                };
+                let client = bulk.clone();
+                join_set.spawn(async move { client.run_read_job(address, job).await });
+            }
+
+            match join_set.join_next().await {
+                None => break,
+                Some(Ok(values)) => result.extend(values),
+                Some(Err(error)) => resume_panic(error),
+            }
+        }
+
+        // The reads of a cancelled task are lost.
+        for variable in variables {
+            result.entry(variable.clone()).or_insert_with(|| Err(cancelled()));
+        }
+        result
+    }
+
+    /// Polls the variables every `period` and reports changes as a [Stream](futures_core::Stream) of [SubscriptionEvent](super::SubscriptionEvent)s.
+    ///
+    /// Variables are read with [read_many](Self::read_many), so variables that share a DPac page are read together.
+    pub fn subscribe<I>(&self, address: (u8, u8), variables: I, period: Duration) -> Subscription
+    where
+        I: IntoIterator,
+        I::Item: Into<SubscribedVariable>,
+    {
+        Subscription::spawn(self.clone(), address, variables.into_iter().map(Into::into).collect(), period)
+    }
+
+    async fn run_read_job(&self, address: (u8, u8), job: ReadJob) -> Vec<(Variable, Result<Variant, EXOlineError>)> {
+        match job {
+            ReadJob::Single(variable) => {
+                let value = self.read_variable(address, &variable).await;
+                vec![(variable, value)]
+            }
+            ReadJob::Page {
+                file_kind,
+                load_number,
+                page,
+                slots,
+            } => {
+                let data = match file_kind {
+                    FileKind::Task => self.read_task_page_raw(address, load_number, page).await,
+                    _ => self.read_dpac_page_raw(address, file_kind, load_number, page).await,
+                };
+                match data {
+                    Ok(data) => slots
+                        .into_iter()
+                        .map(|(variable, slot)| {
+                            let value = match data.get(slot) {
+                                Some(bytes) => Ok(decode_page_slot(file_kind, variable.kind(), bytes)),
+                                None => Err(EXOlineError::InvalidResponse("The page is too short")),
+                            };
+                            (variable, value)
+                        })
+                        .collect(),
+                    Err(error) => slots.into_iter().map(|(variable, _)| (variable, Err(error.clone()))).collect(),
                 }
-                VariableKind::Integer => {
-                    let Some(value) = value.integer() else {
-                        return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
-                    };
-                    let request = WriteIntegerRequest {
-                        kind: CommandFileKind::Task,
-                        load_number,
-                        offset,
-                        value,
-                    };
-                    self.send_request(address, CommandId::WriteInteger, &request).await?;
-                    Ok(())
+            }
+        }
+    }
+
+    async fn read_dpac_internal(&self, address: (u8, u8), file: &File, only_page: Option<u8>) -> Result<HashMap<Variable, Variant>, EXOlineError> {
+        match file.kind() {
+            FileKind::BPac | FileKind::VPac => self.read_pages_internal(address, file, only_page).await,
+            _ => Err(EXOlineError::InvalidArguments("Can only read pages from DPac's")),
+        }
+    }
+
+    /// Reads the pages of a DPac or the locals of a task and decodes the variables.
+    async fn read_pages_internal(&self, address: (u8, u8), file: &File, only_page: Option<u8>) -> Result<HashMap<Variable, Variant>, EXOlineError> {
+        // Reading a single page is not a sweep.
+        let client = match only_page {
+            None => self.bulk(),
+            Some(_) => self.clone(),
+        };
+
+        let mut result = HashMap::with_capacity(only_page.map(|_| 60).unwrap_or_else(|| file.len()));
+
+        let mut data = Vec::new();
+        let mut page: i32 = -1;
+
+        // The pages the variables of the file are on are requested together.
+        // Anything after them is read one by one until the controller answers AddressOutsideRange.
+        let pages_needed = file.iter().filter_map(|variable| page_slot(&variable)).map(|(page, _)| usize::from(page) + 1).max().unwrap_or(0);
+        if matches!(file.kind(), FileKind::BPac | FileKind::VPac) && only_page.is_none() && pages_needed > 1 {
+            let pages = client.read_dpac_pages(address, file.kind(), file.load_number(), pages_needed);
+            for next_data in pages.await {
+                match next_data {
+                    Ok(mut next_data) => {
+                        next_data.resize(120, 0); // in case
+                        data.extend(next_data);
+                        page += 1;
+                    }
+                    Err(EXOlineError::ExolineException(EXOlineException::AddressOutsideRange)) => {
+                        page = 0xFF; // No more pages.
+                        break;
+                    }
+                    Err(err) => return Err(err),
                 }
-                VariableKind::Logic => {
-                    let Some(value) = value.logic() else {
-                        return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
-                    };
-                    let request = WriteLogicRequest {
-                        kind: CommandFileKind::Task,
-                        load_number,
-                        offset,
-                        value,
-                    };
-                    self.send_request(address, CommandId::WriteLogic, &request).await?;
-                    Ok(())
+            }
+        }
+
+        for variable in file.iter() {
+            if variable.kind() == VariableKind::String {
+                continue;
+            }
+
+            let file_offset = variable.offset() as usize;
+
+            let (page_size, page_offset) = match file.kind() {
+                FileKind::BPac => (variable.kind().page_size_of_bpac_variable() as usize, file_offset),
+                FileKind::VPac | FileKind::Task => (variable.kind().page_size_of_vpac_variable() as usize, file_offset * 2),
+                _ => unreachable!(),
+            };
+
+            if let Some(only_page) = only_page {
+                if page_offset / 120 != only_page as usize {
+                    continue;
                 }
-                VariableKind::Real => {
-                    let Some(value) = value.real() else {
-                        return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
-                    };
-                    let request = WriteRealRequest {
-                        kind: CommandFileKind::Task,
-                        load_number,
-                        offset,
-                        value,
-                    };
-                    self.send_request(address, CommandId::WriteReal, &request).await?;
-                    Ok(())
+            }
+
+            let mut bytes = None;
+            loop {
+                let data_offset = match only_page {
+                    None => page_offset,
+                    Some(_) => page_offset % 120,
+                };
+                match data.get(data_offset..data_offset + page_size).map(|b| b.to_owned()) {
+                    Some(b) => {
+                        bytes = Some(b);
+                        break;
+                    }
+                    None => {
+                        if page >= 0xFF {
+                            break;
+                        }
+                        page += 1;
+                        let page_to_read = only_page.unwrap_or(page as u8);
+                        let result = match file.kind() {
+                            FileKind::Task => client.read_task_page_raw(address, file.load_number(), page_to_read).await,
+                            _ => client.read_dpac_page_raw(address, file.kind(), file.load_number(), page_to_read).await,
+                        };
+                        match result {
+                            Ok(mut next_data) => {
+                                next_data.resize(120, 0); // in case
+                                data.extend(next_data);
+                                if only_page.is_some() {
+                                    page = 0xFF;
+                                }
+                            }
+                            Err(EXOlineError::ExolineException(EXOlineException::AddressOutsideRange)) => {
+                                page = 0xFF; // No more pages.
+                                break;
+                            }
+                            Err(err) => return Err(err),
+                        }
+                    }
                 }
+            }
+
+            let bytes = match bytes {
+                None => continue,
+                Some(bytes) => bytes,
+            };
+
+            let variant = decode_page_slot(file.kind(), variable.kind(), &bytes);
+
+            result.insert(variable, variant);
+        }
+
+        Ok(result)
+    }
+
+    /// Reads the first `count` pages of a DPac with pipelined requests, and returns the results in page order.
+    async fn read_dpac_pages(&self, address: (u8, u8), file_kind: FileKind, load_number: u8, count: usize) -> Vec<Result<Vec<u8>, EXOlineError>> {
+        let mut results = BTreeMap::new();
+        let mut pages = (0..=u8::MAX).take(count);
+        let mut join_set = JoinSet::new();
+
+        loop {
+            while join_set.len() < self.concurrency {
+                let Some(page) = pages.next() else {
+                    break;
+                };
+                let client = self.clone();
+                join_set.spawn(async move { (page, client.read_dpac_page_raw(address, file_kind, load_number, page).await) });
+            }
+
+            match join_set.join_next().await {
+                None => break,
+                Some(Ok((page, data))) => _ = results.insert(page, data),
+                Some(Err(error)) => resume_panic(error),
+            }
+        }
+
+        (0..=u8::MAX).take(count).map(|page| results.remove(&page).unwrap_or_else(|| Err(cancelled()))).collect()
+    }
+
+    /// Read a page from a DPac by manually providing the parameters
+    pub async fn read_dpac_page_raw(&self, address: (u8, u8), file_kind: FileKind, load_number: u8, page: u8) -> Result<Vec<u8>, EXOlineError> {
+        match file_kind {
+            FileKind::BPac | FileKind::VPac => {}
+            _ => {
+                return Err(EXOlineError::InvalidArguments("Can only read pages from DPac's"));
+            }
+        }
+        let response_data = self
+            .send_request(address, CommandId::ReadDPacPage, &ReadDPacPageRequest { load_number, page })
+            .await?;
+        let response = ReadDPacPageResponse::decode_from_bytes(&response_data)?;
+        Ok(response.data.into())
+    }
+
+    /// Read an entire DPac by manually providing the parameters
+    pub async fn read_dpac_raw(&self, address: (u8, u8), file_kind: FileKind, load_number: u8) -> Result<Vec<u8>, EXOlineError> {
+        let client = self.bulk();
+        let mut data = Vec::new();
+
+        for page in 0..=0xFF {
+            match client.read_dpac_page_raw(address, file_kind, load_number, page).await {
+                Ok(bytes) => data.extend(bytes),
+                Err(EXOlineError::ExolineException(EXOlineException::AddressOutsideRange)) => break,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Read a page from the locals of a task by manually providing the parameters
+    pub async fn read_task_page_raw(&self, address: (u8, u8), load_number: u8, page: u8) -> Result<Vec<u8>, EXOlineError> {
+        let response_data = self
+            .send_request(address, CommandId::ReadTaskPage, &ReadTaskPageRequest { load_number, page })
+            .await?;
+        let response = ReadDPacPageResponse::decode_from_bytes(&response_data)?;
+        Ok(response.data.into())
+    }
+
+    /// Read a variable
+    pub async fn read_variable(&self, address: (u8, u8), variable: &Variable) -> Result<Variant, EXOlineError> {
+        self.read_variable_raw(address, variable.file_kind(), variable.load_number(), variable.kind(), variable.offset())
+            .await
+    }
+
+    /// Read a variable by manually providing the parameters
+    pub async fn read_variable_raw(
+        &self,
+        address: (u8, u8),
+        file_kind: FileKind,
+        load_number: u8,
+        variable_kind: VariableKind,
+        offset: u32,
+    ) -> Result<Variant, EXOlineError> {
+        let future = self.read_variable_untraced(address, file_kind, load_number, variable_kind, offset);
+        #[cfg(feature = "tracing")]
+        let future = tracing::Instrument::instrument(
+            future,
+            tracing::debug_span!("exoline_read_variable", ?file_kind, load_number, ?variable_kind, offset),
+        );
+        future.await
+    }
+
+    async fn read_variable_untraced(
+        &self,
+        address: (u8, u8),
+        file_kind: FileKind,
+        load_number: u8,
+        variable_kind: VariableKind,
+        offset: u32,
+    ) -> Result<Variant, EXOlineError> {
+        match file_kind {
+            FileKind::Task => {
+                if self.falls_back_to_specific_commands(address, file_kind, variable_kind) {
+                    return self.read_task_variable(address, load_number, variable_kind, offset).await;
+                }
+                let request = ReadRequest {
+                    kind: CommandFileKind::Task,
+                    load_number,
+                    offset,
+                };
+                let result: Result<Variant, EXOlineError> = async {
+                    match variable_kind {
+                        VariableKind::Huge => {
+                            let response_data = self.send_request(address, CommandId::ReadHuge, &request).await?;
+                            let response = ReadHugeResponse::decode_from_bytes(&response_data)?;
+                            Ok(Variant::Huge(response.value))
+                        }
+                        VariableKind::Index => {
+                            let response_data = self.send_request(address, CommandId::ReadIndex, &request).await?;
+                            let response = ReadIndexResponse::decode_from_bytes(&response_data)?;
+                            Ok(Variant::Index(response.value))
+                        }
+                        VariableKind::Integer => {
+                            let response_data = self.send_request(address, CommandId::ReadInteger, &request).await?;
+                            let response = ReadIntegerResponse::decode_from_bytes(&response_data)?;
+                            Ok(Variant::Integer(response.value))
+                        }
+                        VariableKind::Logic => {
+                            let response_data = self.send_request(address, CommandId::ReadLogic, &request).await?;
+                            let response = ReadLogicResponse::decode_from_bytes(&response_data)?;
+                            Ok(Variant::Logic(response.value))
+                        }
+                        VariableKind::Real => {
+                            let response_data = self.send_request(address, CommandId::ReadReal, &request).await?;
+                            let response = ReadRealResponse::decode_from_bytes(&response_data)?;
+                            Ok(Variant::Real(response.value))
+                        }
+                        VariableKind::String => {
+                            let response_data = self.send_request(address, CommandId::ReadString, &request).await?;
+                            let response = ReadStringResponse::decode_from_bytes(&response_data)?;
+                            Ok(Variant::String(response.value.to_string()))
+                        }
+                    }
+                }
+                .await;
+                match result {
+                    Err(EXOlineError::ExolineException(EXOlineException::IllegalCommand)) if self.specific_commands && has_specific_command(variable_kind) => {
+                        self.set_uses_specific_commands(address, file_kind);
+                        self.read_task_variable(address, load_number, variable_kind, offset).await
+                    }
+                    result => result,
+                }
+            }
+            FileKind::VPac => {
+                let request = ReadRequest {
+                    kind: CommandFileKind::VPac,
+                    load_number,
+                    offset,
+                };
+                match variable_kind {
+                    VariableKind::Huge => {
+                        let response_data = self.send_request(address, CommandId::ReadHuge, &request).await?;
+                        let response = ReadHugeResponse::decode_from_bytes(&response_data)?;
+                        Ok(Variant::Huge(response.value))
+                    }
+                    VariableKind::Index => {
+                        let response_data = self.send_request(address, CommandId::ReadIndex, &request).await?;
+                        let response = ReadIndexResponse::decode_from_bytes(&response_data)?;
+                        Ok(Variant::Index(response.value))
+                    }
+                    VariableKind::Integer => {
+                        let response_data = self.send_request(address, CommandId::ReadInteger, &request).await?;
+                        let response = ReadIntegerResponse::decode_from_bytes(&response_data)?;
+                        Ok(Variant::Integer(response.value))
+                    }
+                    VariableKind::Logic => {
+                        let response_data = self.send_request(address, CommandId::ReadLogic, &request).await?;
+                        let response = ReadLogicResponse::decode_from_bytes(&response_data)?;
+                        Ok(Variant::Logic(response.value))
+                    }
+                    VariableKind::Real => {
+                        let response_data = self.send_request(address, CommandId::ReadReal, &request).await?;
+                        let response = ReadRealResponse::decode_from_bytes(&response_data)?;
+                        Ok(Variant::Real(response.value))
+                    }
+                    VariableKind::String => {
+                        let response_data = self.send_request(address, CommandId::ReadString, &request).await?;
+                        let response = ReadStringResponse::decode_from_bytes(&response_data)?;
+                        Ok(Variant::String(response.value.to_string()))
+                    }
+                }
+            }
+            FileKind::BPac => {
+                if self.falls_back_to_specific_commands(address, file_kind, variable_kind) {
+                    return self.read_bpac_variable(address, load_number, variable_kind, offset).await;
+                }
+                let request = ReadRequest {
+                    kind: CommandFileKind::BPac,
+                    load_number,
+                    offset,
+                };
+                let result: Result<Variant, EXOlineError> = async {
+                    match variable_kind {
+                        VariableKind::Huge => {
+                            let response_data = self.send_request(address, CommandId::ReadHuge, &request).await?;
+                            let response = ReadHugeResponse::decode_from_bytes(&response_data)?;
+                            Ok(Variant::Huge(response.value))
+                        }
+                        VariableKind::Index => {
+                            let response_data = self.send_request(address, CommandId::ReadIndex, &request).await?;
+                            let response = ReadIndexResponse::decode_from_bytes(&response_data)?;
+                            Ok(Variant::Index(response.value))
+                        }
+                        VariableKind::Integer => {
+                            let response_data = self.send_request(address, CommandId::ReadInteger, &request).await?;
+                            let response = ReadIntegerResponse::decode_from_bytes(&response_data)?;
+                            Ok(Variant::Integer(response.value))
+                        }
+                        VariableKind::Logic => {
+                            let response_data = self.send_request(address, CommandId::ReadLogic, &request).await?;
+                            let response = ReadLogicResponse::decode_from_bytes(&response_data)?;
+                            Ok(Variant::Logic(response.value))
+                        }
+                        VariableKind::Real => {
+                            let response_data = self.send_request(address, CommandId::ReadReal, &request).await?;
+                            let response = ReadRealResponse::decode_from_bytes(&response_data)?;
+                            Ok(Variant::Real(response.value))
+                        }
+                        VariableKind::String => {
+                            Err(EXOlineError::InvalidArguments("Can't read a string from a BPac"))
+                        }
+                    }
+                }
+                .await;
+                match result {
+                    Err(EXOlineError::ExolineException(EXOlineException::IllegalCommand)) if self.specific_commands && has_specific_command(variable_kind) => {
+                        self.set_uses_specific_commands(address, file_kind);
+                        self.read_bpac_variable(address, load_number, variable_kind, offset).await
+                    }
+                    result => result,
+                }
+            }
+            FileKind::Text => match variable_kind {
                 VariableKind::String => {
-                    let Some(value) = value.string() else {
-                        return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
-                    };
-                    let request = WriteStringRequest {
-                        kind: CommandFileKind::Task,
-                        load_number,
-                        offset,
-                        value: value.into(),
-                    };
-                    self.send_request(address, CommandId::WriteString, &request).await?;
-                    Ok(())
+                    let request = ReadTextRequest { load_number, offset };
+                    let response_data = self.send_request(address, CommandId::ReadText, &request).await?;
+                    let response = ReadStringResponse::decode_from_bytes(&response_data)?;
+                    Ok(Variant::String(response.value.to_string()))
+                }
+                _ => {
+                    Err(EXOlineError::InvalidArguments("Can only read strings from text files"))
                 }
             },
+        }
+    }
+
+    /// Write a variable
+    pub async fn write_variable(&self, address: (u8, u8), variable: &Variable, value: &Variant) -> Result<(), EXOlineError> {
+        self.write_variable_raw(
+            address,
+            variable.file_kind(),
+            variable.load_number(),
+            variable.kind(),
+            variable.offset(),
+            value,
+        )
+        .await
+    }
+
+    /// Write a variable by manually providing the parameters
+    pub async fn write_variable_raw(
+        &self,
+        address: (u8, u8),
+        file_kind: FileKind,
+        load_number: u8,
+        variable_kind: VariableKind,
+        offset: u32,
+        value: &Variant,
+    ) -> Result<(), EXOlineError> {
+        let future = self.write_variable_untraced(address, file_kind, load_number, variable_kind, offset, value);
+        #[cfg(feature = "tracing")]
+        let future = tracing::Instrument::instrument(
+            future,
+            tracing::debug_span!("exoline_write_variable", ?file_kind, load_number, ?variable_kind, offset),
+        );
+        future.await
+    }
+
+    async fn write_variable_untraced(
+        &self,
+        address: (u8, u8),
+        file_kind: FileKind,
+        load_number: u8,
+        variable_kind: VariableKind,
+        offset: u32,
+        value: &Variant,
+    ) -> Result<(), EXOlineError> {
+        match file_kind {
+            FileKind::Task => {
+                if self.falls_back_to_specific_commands(address, file_kind, variable_kind) {
+                    return self.write_task_variable(address, load_number, variable_kind, offset, value).await;
+                }
+                let result: Result<(), EXOlineError> = async {
+                    match variable_kind {
+                        VariableKind::Huge => {
+                            let Some(value) = value.huge() else {
+                                return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                            };
+                            let request = WriteHugeRequest {
+                                kind: CommandFileKind::Task,
+                                load_number,
+                                offset,
+                                value,
+                            };
+                            self.send_write(address, CommandId::WriteHuge, &request).await?;
+                            Ok(())
+                        }
+                        VariableKind::Index => {
+                            let Some(value) = value.index() else {
+                                return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                            };
+                            let request = WriteIndexRequest {
+                                kind: CommandFileKind::Task,
+                                load_number,
+                                offset,
+                                value,
+                            };
+                            self.send_write(address, CommandId::WriteIndex, &request).await?;
+                            Ok(())
+                        }
+                        VariableKind::Integer => {
+                            let Some(value) = value.integer() else {
+                                return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                            };
+                            let request = WriteIntegerRequest {
+                                kind: CommandFileKind::Task,
+                                load_number,
+                                offset,
+                                value,
+                            };
+                            self.send_write(address, CommandId::WriteInteger, &request).await?;
+                            Ok(())
+                        }
+                        VariableKind::Logic => {
+                            let Some(value) = value.logic() else {
+                                return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                            };
+                            let request = WriteLogicRequest {
+                                kind: CommandFileKind::Task,
+                                load_number,
+                                offset,
+                                value,
+                            };
+                            self.send_write(address, CommandId::WriteLogic, &request).await?;
+                            Ok(())
+                        }
+                        VariableKind::Real => {
+                            let Some(value) = value.real() else {
+                                return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                            };
+                            let request = WriteRealRequest {
+                                kind: CommandFileKind::Task,
+                                load_number,
+                                offset,
+                                value,
+                            };
+                            self.send_write(address, CommandId::WriteReal, &request).await?;
+                            Ok(())
+                        }
+                        VariableKind::String => {
+                            let Some(value) = value.string() else {
+                                return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                            };
+                            let request = WriteStringRequest {
+                                kind: CommandFileKind::Task,
+                                load_number,
+                                offset,
+                                value: value.into(),
+                            };
+                            self.send_write(address, CommandId::WriteString, &request).await?;
+                            Ok(())
+                        }
+                    }
+                }
+                .await;
+                match result {
+                    Err(EXOlineError::ExolineException(EXOlineException::IllegalCommand)) if self.specific_commands && has_specific_command(variable_kind) => {
+                        self.set_uses_specific_commands(address, file_kind);
+                        self.write_task_variable(address, load_number, variable_kind, offset, value).await
+                    }
+                    result => result,
+                }
+            }
             FileKind::VPac => match variable_kind {
                 VariableKind::Huge => {
                     let Some(value) = value.huge() else {
@@ -512,7 +1620,7 @@ impl EXOlineTCPClient {
                         offset,
                         value,
                     };
-                    self.send_request(address, CommandId::WriteHuge, &request).await?;
+                    self.send_write(address, CommandId::WriteHuge, &request).await?;
                     Ok(())
                 }
                 VariableKind::Index => {
@@ -525,7 +1633,7 @@ impl EXOlineTCPClient {
                         offset,
                         value,
                     };
-                    self.send_request(address, CommandId::WriteIndex, &request).await?;
+                    self.send_write(address, CommandId::WriteIndex, &request).await?;
                     Ok(())
                 }
                 VariableKind::Integer => {
@@ -538,7 +1646,7 @@ impl EXOlineTCPClient {
                         offset,
                         value,
                     };
-                    self.send_request(address, CommandId::WriteInteger, &request).await?;
+                    self.send_write(address, CommandId::WriteInteger, &request).await?;
                     Ok(())
                 }
                 VariableKind::Logic => {
@@ -551,7 +1659,7 @@ impl EXOlineTCPClient {
                         offset,
                         value,
                     };
-                    self.send_request(address, CommandId::WriteLogic, &request).await?;
+                    self.send_write(address, CommandId::WriteLogic, &request).await?;
                     Ok(())
                 }
                 VariableKind::Real => {
@@ -564,7 +1672,7 @@ impl EXOlineTCPClient {
                         offset,
                         value,
                     };
-                    self.send_request(address, CommandId::WriteReal, &request).await?;
+                    self.send_write(address, CommandId::WriteReal, &request).await?;
                     Ok(())
                 }
                 VariableKind::String => {
@@ -577,200 +1685,1629 @@ impl EXOlineTCPClient {
                         offset,
                         value: value.into(),
                     };
-                    self.send_request(address, CommandId::WriteString, &request).await?;
+                    self.send_write(address, CommandId::WriteString, &request).await?;
                     Ok(())
                 }
             },
-            FileKind::BPac => match variable_kind {
-                VariableKind::Huge => {
-                    let Some(value) = value.huge() else {
-                        return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
-                    };
-                    let request = WriteHugeRequest {
-                        kind: CommandFileKind::BPac,
-                        load_number,
-                        offset,
-                        value,
-                    };
-                    self.send_request(address, CommandId::WriteHuge, &request).await?;
-                    Ok(())
-                }
-                VariableKind::Index => {
-                    let Some(value) = value.index() else {
-                        return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
-                    };
-                    let request = WriteIndexRequest {
-                        kind: CommandFileKind::BPac,
-                        load_number,
-                        offset,
-                        value,
-                    };
-                    self.send_request(address, CommandId::WriteIndex, &request).await?;
-                    Ok(())
-                }
-                VariableKind::Integer => {
-                    let Some(value) = value.integer() else {
-                        return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
-                    };
-                    let request = WriteIntegerRequest {
-                        kind: CommandFileKind::BPac,
-                        load_number,
-                        offset,
-                        value,
-                    };
-                    self.send_request(address, CommandId::WriteInteger, &request).await?;
-                    Ok(())
-                }
-                VariableKind::Logic => {
-                    let Some(value) = value.logic() else {
-                        return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
-                    };
-                    let request = WriteLogicRequest {
-                        kind: CommandFileKind::BPac,
-                        load_number,
-                        offset,
-                        value,
-                    };
-                    self.send_request(address, CommandId::WriteLogic, &request).await?;
-                    Ok(())
+            FileKind::BPac => {
+                if self.falls_back_to_specific_commands(address, file_kind, variable_kind) {
+                    return self.write_bpac_variable(address, load_number, variable_kind, offset, value).await;
                 }
-                VariableKind::Real => {
-                    let Some(value) = value.real() else {
-                        return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
-                    };
-                    let request = WriteRealRequest {
-                        kind: CommandFileKind::BPac,
-                        load_number,
-                        offset,
-                        value,
-                    };
-                    self.send_request(address, CommandId::WriteReal, &request).await?;
-                    Ok(())
+                let result: Result<(), EXOlineError> = async {
+                    match variable_kind {
+                        VariableKind::Huge => {
+                            let Some(value) = value.huge() else {
+                                return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                            };
+                            let request = WriteHugeRequest {
+                                kind: CommandFileKind::BPac,
+                                load_number,
+                                offset,
+                                value,
+                            };
+                            self.send_write(address, CommandId::WriteHuge, &request).await?;
+                            Ok(())
+                        }
+                        VariableKind::Index => {
+                            let Some(value) = value.index() else {
+                                return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                            };
+                            let request = WriteIndexRequest {
+                                kind: CommandFileKind::BPac,
+                                load_number,
+                                offset,
+                                value,
+                            };
+                            self.send_write(address, CommandId::WriteIndex, &request).await?;
+                            Ok(())
+                        }
+                        VariableKind::Integer => {
+                            let Some(value) = value.integer() else {
+                                return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                            };
+                            let request = WriteIntegerRequest {
+                                kind: CommandFileKind::BPac,
+                                load_number,
+                                offset,
+                                value,
+                            };
+                            self.send_write(address, CommandId::WriteInteger, &request).await?;
+                            Ok(())
+                        }
+                        VariableKind::Logic => {
+                            let Some(value) = value.logic() else {
+                                return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                            };
+                            let request = WriteLogicRequest {
+                                kind: CommandFileKind::BPac,
+                                load_number,
+                                offset,
+                                value,
+                            };
+                            self.send_write(address, CommandId::WriteLogic, &request).await?;
+                            Ok(())
+                        }
+                        VariableKind::Real => {
+                            let Some(value) = value.real() else {
+                                return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                            };
+                            let request = WriteRealRequest {
+                                kind: CommandFileKind::BPac,
+                                load_number,
+                                offset,
+                                value,
+                            };
+                            self.send_write(address, CommandId::WriteReal, &request).await?;
+                            Ok(())
+                        }
+                        VariableKind::String => {
+                            Err(EXOlineError::InvalidArguments("Can't write a string to a BPac"))
+                        }
+                    }
                 }
-                VariableKind::String => {
-                    Err(EXOlineError::InvalidArguments("Can't write a string to a BPac"))
+                .await;
+                match result {
+                    Err(EXOlineError::ExolineException(EXOlineException::IllegalCommand)) if self.specific_commands && has_specific_command(variable_kind) => {
+                        self.set_uses_specific_commands(address, file_kind);
+                        self.write_bpac_variable(address, load_number, variable_kind, offset, value).await
+                    }
+                    result => result,
                 }
-            },
+            }
             FileKind::Text => match variable_kind {
                 VariableKind::String => {
                     let Some(value) = value.string() else {
                         return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
                     };
-                    let request = WriteStringRequest {
-                        kind: CommandFileKind::VPac,
+                    let request = WriteTextRequest {
                         load_number,
                         offset,
                         value: value.into(),
                     };
-                    self.send_request(address, CommandId::ReadString, &request).await?;
+                    self.send_write(address, CommandId::WriteText, &request).await?;
                     Ok(())
                 }
-                _ => {
-                    Err(EXOlineError::InvalidArguments("Can only write strings to text files"))
+                _ => {
+                    Err(EXOlineError::InvalidArguments("Can only write strings to text files"))
+                }
+            },
+        }
+    }
+
+    /// Whether the variable is read and written with the specific commands, because they are enabled and the
+    /// controller has rejected the generic commands for this kind of file.
+    fn falls_back_to_specific_commands(&self, address: (u8, u8), file_kind: FileKind, variable_kind: VariableKind) -> bool {
+        self.specific_commands && has_specific_command(variable_kind) && self.uses_specific_commands(address, file_kind)
+    }
+
+    /// Whether the controller has rejected the generic commands for this kind of file.
+    fn uses_specific_commands(&self, address: (u8, u8), file_kind: FileKind) -> bool {
+        self.inner.shared.capabilities.lock().unwrap().specific_command_files.contains(&(address, file_kind))
+    }
+
+    fn set_uses_specific_commands(&self, address: (u8, u8), file_kind: FileKind) {
+        self.inner.shared.capabilities.lock().unwrap().specific_command_files.insert((address, file_kind));
+    }
+
+    async fn read_bpac_variable(&self, address: (u8, u8), load_number: u8, variable_kind: VariableKind, offset: u32) -> Result<Variant, EXOlineError> {
+        let request = ReadBPacRequest { load_number, offset };
+        match variable_kind {
+            VariableKind::Index => {
+                let response_data = self.send_request(address, CommandId::ReadBPacIndex, &request).await?;
+                let response = ReadIndexResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Index(response.value))
+            }
+            VariableKind::Integer => {
+                let response_data = self.send_request(address, CommandId::ReadBPacInteger, &request).await?;
+                let response = ReadIntegerResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Integer(response.value))
+            }
+            VariableKind::Logic => {
+                let response_data = self.send_request(address, CommandId::ReadBPacLogic, &request).await?;
+                let response = ReadLogicResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Logic(response.value))
+            }
+            VariableKind::Real => {
+                let response_data = self.send_request(address, CommandId::ReadBPacReal, &request).await?;
+                let response = ReadRealResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Real(response.value))
+            }
+            VariableKind::Huge | VariableKind::String => Err(EXOlineError::Internal("No BPac command for this kind")),
+        }
+    }
+
+    async fn write_bpac_variable(
+        &self,
+        address: (u8, u8),
+        load_number: u8,
+        variable_kind: VariableKind,
+        offset: u32,
+        value: &Variant,
+    ) -> Result<(), EXOlineError> {
+        match variable_kind {
+            VariableKind::Index => {
+                let Some(value) = value.index() else {
+                    return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                };
+                let request = WriteBPacIndexRequest { load_number, offset, value };
+                self.send_write(address, CommandId::WriteBPacIndex, &request).await?;
+                Ok(())
+            }
+            VariableKind::Integer => {
+                let Some(value) = value.integer() else {
+                    return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                };
+                let request = WriteBPacIntegerRequest { load_number, offset, value };
+                self.send_write(address, CommandId::WriteBPacInteger, &request).await?;
+                Ok(())
+            }
+            VariableKind::Logic => {
+                let Some(value) = value.logic() else {
+                    return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                };
+                let request = WriteBPacLogicRequest { load_number, offset, value };
+                self.send_write(address, CommandId::WriteBPacLogic, &request).await?;
+                Ok(())
+            }
+            VariableKind::Real => {
+                let Some(value) = value.real() else {
+                    return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                };
+                let request = WriteBPacRealRequest { load_number, offset, value };
+                self.send_write(address, CommandId::WriteBPacReal, &request).await?;
+                Ok(())
+            }
+            VariableKind::Huge | VariableKind::String => Err(EXOlineError::Internal("No BPac command for this kind")),
+        }
+    }
+
+    async fn read_task_variable(&self, address: (u8, u8), load_number: u8, variable_kind: VariableKind, offset: u32) -> Result<Variant, EXOlineError> {
+        let request = ReadTaskRequest { load_number, offset };
+        match variable_kind {
+            VariableKind::Index => {
+                let response_data = self.send_request(address, CommandId::ReadTaskIndex, &request).await?;
+                let response = ReadIndexResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Index(response.value))
+            }
+            VariableKind::Integer => {
+                let response_data = self.send_request(address, CommandId::ReadTaskInteger, &request).await?;
+                let response = ReadIntegerResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Integer(response.value))
+            }
+            VariableKind::Logic => {
+                let response_data = self.send_request(address, CommandId::ReadTaskLogic, &request).await?;
+                let response = ReadLogicResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Logic(response.value))
+            }
+            VariableKind::Real => {
+                let response_data = self.send_request(address, CommandId::ReadTaskReal, &request).await?;
+                let response = ReadRealResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Real(response.value))
+            }
+            VariableKind::Huge | VariableKind::String => Err(EXOlineError::Internal("No task command for this kind")),
+        }
+    }
+
+    async fn write_task_variable(
+        &self,
+        address: (u8, u8),
+        load_number: u8,
+        variable_kind: VariableKind,
+        offset: u32,
+        value: &Variant,
+    ) -> Result<(), EXOlineError> {
+        match variable_kind {
+            VariableKind::Index => {
+                let Some(value) = value.index() else {
+                    return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                };
+                let request = WriteTaskIndexRequest { load_number, offset, value };
+                self.send_write(address, CommandId::WriteTaskIndex, &request).await?;
+                Ok(())
+            }
+            VariableKind::Integer => {
+                let Some(value) = value.integer() else {
+                    return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                };
+                let request = WriteTaskIntegerRequest { load_number, offset, value };
+                self.send_write(address, CommandId::WriteTaskInteger, &request).await?;
+                Ok(())
+            }
+            VariableKind::Logic => {
+                let Some(value) = value.logic() else {
+                    return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                };
+                let request = WriteTaskLogicRequest { load_number, offset, value };
+                self.send_write(address, CommandId::WriteTaskLogic, &request).await?;
+                Ok(())
+            }
+            VariableKind::Real => {
+                let Some(value) = value.real() else {
+                    return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                };
+                let request = WriteTaskRealRequest { load_number, offset, value };
+                self.send_write(address, CommandId::WriteTaskReal, &request).await?;
+                Ok(())
+            }
+            VariableKind::Huge | VariableKind::String => Err(EXOlineError::Internal("No task command for this kind")),
+        }
+    }
+
+    /// Reads a variable from a VPac with the segmented commands.
+    /// Some older controllers only support these commands.
+    pub async fn read_variable_segmented(&self, address: (u8, u8), variable: &Variable) -> Result<Variant, EXOlineError> {
+        let (segment, offset) = segment_of_variable(variable)?;
+        self.read_vpac_segmented_raw(address, variable.load_number(), variable.kind(), segment, offset).await
+    }
+
+    /// Reads a value from a VPac with the segmented commands by manually providing the parameters.
+    /// `offset` is the offset within the segment. Huge and String values can't be read this way.
+    pub async fn read_vpac_segmented_raw(
+        &self,
+        address: (u8, u8),
+        load_number: u8,
+        variable_kind: VariableKind,
+        segment: u8,
+        offset: u8,
+    ) -> Result<Variant, EXOlineError> {
+        if offset >= 60 {
+            return Err(EXOlineError::InvalidArguments("Offset must be less than 60"));
+        }
+
+        let request = ReadVPacSegmentedRequest {
+            load_number,
+            segment,
+            offset,
+        };
+        match variable_kind {
+            VariableKind::Index => {
+                let response_data = self.send_request(address, CommandId::ReadVPacIndexSegmented, &request).await?;
+                let response = ReadIndexResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Index(response.value))
+            }
+            VariableKind::Integer => {
+                let response_data = self.send_request(address, CommandId::ReadVPacIntegerSegmented, &request).await?;
+                let response = ReadIntegerResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Integer(response.value))
+            }
+            VariableKind::Logic => {
+                let response_data = self.send_request(address, CommandId::ReadVPacLogicSegmented, &request).await?;
+                let response = ReadLogicResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Logic(response.value))
+            }
+            VariableKind::Real => {
+                let response_data = self.send_request(address, CommandId::ReadVPacRealSegmented, &request).await?;
+                let response = ReadRealResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Real(response.value))
+            }
+            VariableKind::Huge | VariableKind::String => {
+                Err(EXOlineError::InvalidArguments("Segmented commands only support Index, Integer, Logic and Real"))
+            }
+        }
+    }
+
+    /// Writes a variable in a VPac with the segmented commands.
+    /// Some older controllers only support these commands.
+    pub async fn write_variable_segmented(&self, address: (u8, u8), variable: &Variable, value: &Variant) -> Result<(), EXOlineError> {
+        let (segment, offset) = segment_of_variable(variable)?;
+        self.write_vpac_segmented_raw(address, variable.load_number(), variable.kind(), segment, offset, value)
+            .await
+    }
+
+    /// Writes a value in a VPac with the segmented commands by manually providing the parameters.
+    /// `offset` is the offset within the segment. Huge and String values can't be written this way.
+    pub async fn write_vpac_segmented_raw(
+        &self,
+        address: (u8, u8),
+        load_number: u8,
+        variable_kind: VariableKind,
+        segment: u8,
+        offset: u8,
+        value: &Variant,
+    ) -> Result<(), EXOlineError> {
+        if offset >= 60 {
+            return Err(EXOlineError::InvalidArguments("Offset must be less than 60"));
+        }
+
+        match variable_kind {
+            VariableKind::Index => {
+                let Some(value) = value.index() else {
+                    return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                };
+                let request = WriteVPacIndexSegmentedRequest {
+                    load_number,
+                    segment,
+                    offset,
+                    value,
+                };
+                self.send_write(address, CommandId::WriteVPacIndexSegmented, &request).await?;
+                Ok(())
+            }
+            VariableKind::Integer => {
+                let Some(value) = value.integer() else {
+                    return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                };
+                let request = WriteVPacIntegerSegmentedRequest {
+                    load_number,
+                    segment,
+                    offset,
+                    value,
+                };
+                self.send_write(address, CommandId::WriteVPacIntegerSegmented, &request).await?;
+                Ok(())
+            }
+            VariableKind::Logic => {
+                let Some(value) = value.logic() else {
+                    return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                };
+                let request = WriteVPacLogicSegmentedRequest {
+                    load_number,
+                    segment,
+                    offset,
+                    value,
+                };
+                self.send_write(address, CommandId::WriteVPacLogicSegmented, &request).await?;
+                Ok(())
+            }
+            VariableKind::Real => {
+                let Some(value) = value.real() else {
+                    return Err(EXOlineError::InvalidArguments("The variable and value kind doesn't match"));
+                };
+                let request = WriteVPacRealSegmentedRequest {
+                    load_number,
+                    segment,
+                    offset,
+                    value,
+                };
+                self.send_write(address, CommandId::WriteVPacRealSegmented, &request).await?;
+                Ok(())
+            }
+            VariableKind::Huge | VariableKind::String => {
+                Err(EXOlineError::InvalidArguments("Segmented commands only support Index, Integer, Logic and Real"))
+            }
+        }
+    }
+
+    /// Logs in to a controller with a password, and returns the access level it gives.
+    ///
+    /// The password is remembered, and a client that reconnects logs in again on every new connection.
+    /// A password the controller rejects is forgotten.
+    /// The layout of the login is not publicly documented, it is only tested against the emulator.
+    pub async fn log_in(&self, address: (u8, u8), password: &str) -> Result<u8, EXOlineError> {
+        let request = LoginRequest { password: password.into() };
+        let result = self
+            .send_request(address, CommandId::Login, &request)
+            .await
+            .and_then(|response_data| Ok(LoginResponse::decode_from_bytes(&response_data)?.access_level));
+
+        let mut logins = self.inner.shared.logins.lock().unwrap();
+        match &result {
+            Ok(_) => {
+                logins.passwords.insert(address, password.into());
+            }
+            Err(EXOlineError::ExolineException(_)) => {
+                logins.passwords.remove(&address);
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    /// Logs in with the system password of `controller` if it requires a password, like
+    /// [connect_to_controller](Self::connect_to_controller) does. Returns the access level, or `None` if no password is required.
+    pub async fn log_in_to(&self, controller: &Controller) -> Result<Option<u8>, EXOlineError> {
+        match Logins::for_controller(controller).passwords.remove(&controller.address) {
+            Some(password) => self.log_in(controller.address, &password).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The access level given by the last login to a controller on the current connection,
+    /// or `None` if the client hasn't logged in since it connected.
+    pub fn access_level(&self, address: (u8, u8)) -> Option<u8> {
+        self.inner.shared.logins.lock().unwrap().access_levels.get(&address).copied()
+    }
+
+    /// Reads the controller model and version as a string
+    pub async fn read_controller_id(&self, address: (u8, u8)) -> Result<String, EXOlineError> {
+        let response_data = self.send_request(address, CommandId::GetControllerId, &GetControllerIdRequest).await?;
+        let response = GetControllerIdResponse::decode_from_bytes(&response_data)?;
+        Ok(response.id.into())
+    }
+
+    /// Reads the memory usage and uptime of the controller.
+    ///
+    /// The layout of the statistics responses is not publicly documented, and has only been tested against the [emulator](crate::emulator).
+    /// Needs the `unstable-stats` feature.
+    #[cfg(feature = "unstable-stats")]
+    pub async fn read_controller_stats(&self, address: (u8, u8)) -> Result<ControllerStats, EXOlineError> {
+        let response_data = self.send_request(address, CommandId::GetControllerStats, &GetControllerStatsRequest).await?;
+        let response = GetControllerStatsResponse::decode_from_bytes(&response_data)?;
+        Ok(ControllerStats {
+            total_memory: response.total_memory,
+            free_memory: response.free_memory,
+            dpac_count: response.dpac_count,
+            task_count: response.task_count,
+            uptime: Duration::from_secs(response.uptime as u64),
+        })
+    }
+
+    /// Reads the size and page count of a DPac.
+    /// See [read_controller_stats](Self::read_controller_stats) about the layout of the response.
+    #[cfg(feature = "unstable-stats")]
+    pub async fn read_dpac_stats(&self, address: (u8, u8), load_number: u8) -> Result<DPacStats, EXOlineError> {
+        let response_data = self
+            .send_request(address, CommandId::GetDPacStats, &GetDPacStatsRequest { load_number })
+            .await?;
+        let response = GetDPacStatsResponse::decode_from_bytes(&response_data)?;
+        Ok(DPacStats {
+            size: response.size,
+            page_count: response.page_count,
+        })
+    }
+
+    /// Reads the run state and cycle times of a task.
+    /// See [read_controller_stats](Self::read_controller_stats) about the layout of the response.
+    #[cfg(feature = "unstable-stats")]
+    pub async fn read_task_stats(&self, address: (u8, u8), load_number: u8) -> Result<TaskStats, EXOlineError> {
+        let response_data = self
+            .send_request(address, CommandId::GetTaskStats, &GetTaskStatsRequest { load_number })
+            .await?;
+        let response = GetTaskStatsResponse::decode_from_bytes(&response_data)?;
+        Ok(TaskStats {
+            running: response.running,
+            cycle_time: Duration::from_millis(response.cycle_time as u64),
+            execution_time: Duration::from_millis(response.execution_time as u64),
+            max_execution_time: Duration::from_millis(response.max_execution_time as u64),
+        })
+    }
+
+    /// Read a partition attribute by manually providing the parameters
+    pub async fn read_partition_attribute(
+        &self,
+        address: (u8, u8),
+        partition: u8,
+        attribute_kind: VariableKind,
+        attribute_id: u16,
+    ) -> Result<Variant, EXOlineError> {
+        match attribute_kind {
+            VariableKind::Huge => {
+                let request = ReadPartAttrHeader {
+                    kind: PartAttrHeaderKind::Huge,
+                    part_no: partition,
+                    attr: attribute_id,
+                };
+                let response_data = self.send_request(address, CommandId::ReadPartAttrHeader, &request).await?;
+                let response = ReadHugeResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Huge(response.value))
+            }
+            VariableKind::Real => {
+                let request = ReadPartAttrHeader {
+                    kind: PartAttrHeaderKind::Real,
+                    part_no: partition,
+                    attr: attribute_id,
+                };
+                let response_data = self.send_request(address, CommandId::ReadPartAttrHeader, &request).await?;
+                let response = ReadRealResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::Real(response.value))
+            }
+            VariableKind::String => {
+                let request = ReadPartAttrHeader {
+                    kind: PartAttrHeaderKind::String,
+                    part_no: partition,
+                    attr: attribute_id,
+                };
+                let response_data = self.send_request(address, CommandId::ReadPartAttrHeader, &request).await?;
+                let response = ReadStringResponse::decode_from_bytes(&response_data)?;
+                Ok(Variant::String(response.value.to_string()))
+            }
+            _ => {
+                Err(EXOlineError::InvalidArguments("Attribute kind is not valid for a partition header"))
+            }
+        }
+    }
+
+    /// Sends a write, and checks that it is answered with a [WriteResponse].
+    async fn send_write<T>(&self, address: (u8, u8), command_id: CommandId, request: &T) -> Result<(), EXOlineError>
+    where
+        T: Encodable,
+    {
+        let response_data = self.send_request(address, command_id, request).await?;
+        WriteResponse::decode_from_bytes(&response_data)?;
+        Ok(())
+    }
+
+    async fn send_request<T>(&self, address: (u8, u8), command_id: CommandId, request: &T) -> Result<Vec<u8>, EXOlineError>
+    where
+        T: Encodable,
+    {
+        let request_data = encode_request(address, command_id, request)?;
+
+        let future = self.send_with_retries(address, command_id, request_data);
+        #[cfg(feature = "tracing")]
+        let future = {
+            let span = tracing::debug_span!(
+                "exoline_request",
+                address = %format_args!("{}:{}", address.0, address.1),
+                command = ?command_id,
+                load_number = tracing::field::Empty,
+                offset = tracing::field::Empty,
+                attempts = tracing::field::Empty,
+                outcome = tracing::field::Empty,
+            );
+            if !span.is_disabled() {
+                record_target(&span, command_id, request);
+            }
+            tracing::Instrument::instrument(future, span)
+        };
+        future.await
+    }
+
+    async fn send_with_retries(&self, address: (u8, u8), command_id: CommandId, request_data: Vec<u8>) -> Result<Vec<u8>, EXOlineError> {
+        let mut attempt = 1;
+        loop {
+            let result = self.send_encoded_request(address, command_id, request_data.clone()).await;
+            let Err(error) = &result else {
+                #[cfg(feature = "tracing")]
+                {
+                    tracing::Span::current().record("attempts", attempt).record("outcome", "ok");
+                    tracing::debug!(outcome = "ok", "Request succeeded");
+                }
+                return result;
+            };
+            self.inner.shared.record(MetricsEvent::RequestFailed {
+                address,
+                command_id,
+                error: error.clone(),
+            });
+
+            match &self.retry_policy {
+                Some(retry_policy) if attempt < retry_policy.max_attempts && self.should_retry(command_id, error) => {
+                    let backoff = retry_policy.backoff(attempt);
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(attempt, %error, ?backoff, "Retrying request");
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                _ => {
+                    #[cfg(feature = "tracing")]
+                    {
+                        tracing::Span::current().record("attempts", attempt).record("outcome", "error");
+                        tracing::warn!(outcome = "error", %error, "Request failed");
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+
+    /// Whether a request that failed with `error` is sent again.
+    fn should_retry(&self, command_id: CommandId, error: &EXOlineError) -> bool {
+        if command_id.is_write() && !self.idempotent_writes {
+            return false;
+        }
+        match error {
+            // Only worth it if there will be a new connection to send it on.
+            EXOlineError::IO(_) => self.inner.reconnects && !self.inner.request_sender.is_closed(),
+            error => error.is_transient(),
+        }
+    }
+
+    async fn send_encoded_request(&self, address: (u8, u8), command_id: CommandId, request_data: Vec<u8>) -> Result<Vec<u8>, EXOlineError> {
+        let (sender, receiver) = oneshot::channel::<ResponseResult>();
+
+        let priority = self.priority.unwrap_or(match command_id.is_write() {
+            true => Priority::Interactive,
+            false => Priority::Normal,
+        });
+        let request = PendingRequest {
+            data: request_data,
+            address,
+            command_id,
+            priority,
+            idempotent: !command_id.is_write() || self.idempotent_writes,
+            sender,
+        };
+        if self.inner.request_sender.send(request).is_err() {
+            return Err(not_connected());
+        }
+        let _guard = WaitGuard(&self.inner.shared);
+
+        let response = match self.timeout {
+            None => receiver.await,
+            Some(timeout) => tokio::time::timeout(timeout, receiver).await.map_err(|_| EXOlineError::Timeout)?,
+        };
+
+        let response_data = match response {
+            Ok(Ok(data)) => data,
+            Ok(Err(error)) => return Err(error),
+            Err(_) => return Err(not_connected()),
+        };
+
+        decode_response(&response_data)
+    }
+}
+
+/// Connects to `addr` over TCP every time it is called.
+#[cfg(feature = "tokio")]
+fn tcp_connector(addr: String) -> impl FnMut() -> std::pin::Pin<Box<dyn Future<Output = std::io::Result<TcpStream>> + Send>> + Send + 'static {
+    move || Box::pin(TcpStream::connect(addr.clone()))
+}
+
+/// Creates the escaped message for a request, without the begin and end markers.
+fn encode_request<T>(address: (u8, u8), command_id: CommandId, request: &T) -> Result<Vec<u8>, EXOlineError>
+where
+    T: Encodable,
+{
+    let (pla, ela) = address;
+    let mut encoder = Encoder::new();
+    encoder.write_u8(pla);
+    encoder.write_u8(ela);
+    encoder.write_u8(command_id.into());
+    encoder
+        .write_type(request)
+        .map_err(|_| EXOlineError::InvalidArguments("Error encoding message"))?;
+
+    let mut request_data = encoder.finish();
+    append_crc(&mut request_data);
+    Ok(escape(&request_data).into_owned())
+}
+
+/// Records the load number and the offset a request addresses in its span.
+///
+/// The request is decoded again, so the span is filled in by the same `target` the dissector uses.
+#[cfg(feature = "tracing")]
+fn record_target<T>(span: &tracing::Span, command_id: CommandId, request: &T)
+where
+    T: Encodable,
+{
+    let mut encoder = Encoder::new();
+    if encoder.write_type(request).is_err() {
+        return;
+    }
+    let Ok(request) = crate::server::EXOlineRequest::decode(command_id, &encoder.finish()) else {
+        return;
+    };
+    match crate::protocol::target(&request) {
+        Some(crate::protocol::Target::Variable(_, load_number, offset)) => {
+            span.record("load_number", load_number).record("offset", offset);
+        }
+        Some(crate::protocol::Target::File(_, load_number)) => {
+            span.record("load_number", load_number);
+        }
+        None => {}
+    }
+}
+
+/// Checks a response for exceptions and the CRC, and returns the payload.
+fn decode_response(response_data: &[u8]) -> Result<Vec<u8>, EXOlineError> {
+    if response_data.len() == 1 {
+        return Err(EXOlineError::ExolineException(response_data[0].into()));
+    }
+
+    let response_data = unescape(response_data);
+    let response_data = verify_and_remove_crc(&response_data).ok_or(EXOlineError::InvalidResponse("CRC mismatch"))?;
+
+    Ok(response_data.into())
+}
+
+/// Whether a response can be the answer to a request that expects a payload of `shape`.
+/// Exceptions and responses with a bad CRC are passed on, and fail in [decode_response].
+fn response_fits(response_data: &[u8], shape: ResponseShape) -> bool {
+    if response_data.len() == 1 {
+        return true;
+    }
+    match verify_and_remove_crc(&unescape(response_data)) {
+        Some(payload) => shape.fits(payload.len()),
+        None => true,
+    }
+}
+
+fn response_outcome(response_data: &[u8]) -> ResponseOutcome {
+    if response_data.len() == 1 {
+        return ResponseOutcome::Exception(response_data[0].into());
+    }
+    match verify_and_remove_crc(&unescape(response_data)) {
+        Some(_) => ResponseOutcome::Ok,
+        None => ResponseOutcome::CrcMismatch,
+    }
+}
+
+/// Passes on the panic of a task. Tasks are also cancelled, for example when the runtime shuts down.
+pub(crate) fn resume_panic(error: JoinError) {
+    if error.is_panic() {
+        std::panic::resume_unwind(error.into_panic());
+    }
+}
+
+fn cancelled() -> EXOlineError {
+    EXOlineError::Internal("The task was cancelled")
+}
+
+fn not_connected() -> EXOlineError {
+    EXOlineError::IO(Arc::new(std::io::ErrorKind::NotConnected.into()))
+}
+
+/// Decodes a value from the bytes of its slot in a page.
+fn decode_page_slot(file_kind: FileKind, variable_kind: VariableKind, bytes: &[u8]) -> Variant {
+    match file_kind {
+        FileKind::VPac | FileKind::Task => match variable_kind {
+            VariableKind::Huge => Variant::Huge(i32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]])),
+            VariableKind::Index => Variant::Index(bytes[1]),
+            VariableKind::Integer => Variant::Integer(i16::from_le_bytes([bytes[1], bytes[2]])),
+            VariableKind::Logic => Variant::Logic(bytes[1] != 0),
+            VariableKind::Real => Variant::Real(f32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]])),
+            VariableKind::String => unreachable!(),
+        },
+        FileKind::BPac => match variable_kind {
+            VariableKind::Huge => Variant::Huge(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            VariableKind::Index => Variant::Index(bytes[0]),
+            VariableKind::Integer => Variant::Integer(i16::from_le_bytes([bytes[0], bytes[1]])),
+            VariableKind::Logic => Variant::Logic(bytes[0] != 0),
+            VariableKind::Real => Variant::Real(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            VariableKind::String => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// The bytes a variable occupies in a page, or `None` if it can't be read from a page.
+fn page_slot(variable: &Variable) -> Option<(u8, Range<usize>)> {
+    let (start, size) = match (variable.file_kind(), variable.kind()) {
+        (_, VariableKind::String) => return None,
+        (FileKind::BPac, kind) => (variable.offset() as usize, kind.page_size_of_bpac_variable() as usize),
+        (FileKind::VPac | FileKind::Task, kind) => (variable.offset() as usize * 2, kind.page_size_of_vpac_variable() as usize),
+        (FileKind::Text, _) => return None,
+    };
+    let page = u8::try_from(start / 120).ok()?;
+    let start = start % 120;
+    match start + size <= 120 {
+        true => Some((page, start..start + size)),
+        false => None,
+    }
+}
+
+/// Whether there are BPac and task specific commands for the kind.
+fn has_specific_command(variable_kind: VariableKind) -> bool {
+    matches!(
+        variable_kind,
+        VariableKind::Index | VariableKind::Integer | VariableKind::Logic | VariableKind::Real
+    )
+}
+
+/// Splits the offset of a VPac variable into a segment and an offset within the segment.
+fn segment_of_variable(variable: &Variable) -> Result<(u8, u8), EXOlineError> {
+    if variable.file_kind() != FileKind::VPac {
+        return Err(EXOlineError::InvalidArguments("Segmented commands can only address VPac's"));
+    }
+    let segment = u8::try_from(variable.offset() / SEGMENT_SIZE).map_err(|_| EXOlineError::InvalidArguments("Offset is out of range"))?;
+    Ok((segment, (variable.offset() % SEGMENT_SIZE) as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "tokio")]
+    use tokio::net::TcpListener;
+
+    use crate::server::{EXOlineRequest, EXOlineRequestHandler, EXOlineResponse, EXOlineTCPServer};
+    #[cfg(feature = "tokio")]
+    use crate::{
+        client::{FrameDirection, SubscriptionEvent},
+        emulator::VirtualController,
+        test_util::{connect_test_controller, test_controller, Frames},
+    };
+
+    use super::*;
+
+    /// Answers integer reads with the offset, after waiting for that many milliseconds.
+    struct SlowHandler;
+
+    impl EXOlineRequestHandler for SlowHandler {
+        async fn handle(&self, _address: (u8, u8), request: EXOlineRequest) -> Result<EXOlineResponse, EXOlineException> {
+            match request {
+                EXOlineRequest::ReadInteger(request) => {
+                    tokio::time::sleep(Duration::from_millis(request.offset as u64)).await;
+                    Ok(EXOlineResponse::Value(Variant::Integer(request.offset as i16)))
+                }
+                EXOlineRequest::WriteInteger(request) => {
+                    tokio::time::sleep(Duration::from_millis(request.offset as u64)).await;
+                    Ok(EXOlineResponse::Ack)
                 }
-            },
+                _ => Err(EXOlineException::IllegalCommand),
+            }
         }
     }
 
-    /// Reads the controller model and version as a string
-    pub async fn read_controller_id(&self, address: (u8, u8)) -> Result<String, EXOlineError> {
-        let response_data = self.send_request(address, CommandId::GetControllerId, &GetControllerIdRequest).await?;
-        let response = GetControllerIdResponse::decode_from_bytes(&response_data)?;
-        Ok(response.id.into())
+    #[cfg(feature = "tokio")]
+    async fn connect() -> EXOlineTCPClient {
+        let (addr, _) = EXOlineTCPServer::new(SlowHandler).listen("127.0.0.1:0").await.unwrap();
+        let (client, _) = EXOlineTCPClient::new(TcpStream::connect(addr).await.unwrap());
+        client
     }
 
-    /// Read a partition attribute by manually providing the parameters
-    pub async fn read_partition_attribute(
-        &self,
-        address: (u8, u8),
-        partition: u8,
-        attribute_kind: VariableKind,
-        attribute_id: u16,
-    ) -> Result<Variant, EXOlineError> {
-        match attribute_kind {
-            VariableKind::Huge => {
-                let request = ReadPartAttrHeader {
-                    kind: PartAttrHeaderKind::Huge,
-                    part_no: partition,
-                    attr: attribute_id,
+    async fn read(client: &EXOlineTCPClient, offset: u32) -> Result<Variant, EXOlineError> {
+        client.read_variable_raw((1, 1), FileKind::VPac, 1, VariableKind::Integer, offset).await
+    }
+
+    #[tokio::test]
+    async fn in_memory_transport() {
+        let (client_stream, server_stream) = tokio::io::duplex(256);
+        tokio::spawn(async move { EXOlineTCPServer::new(SlowHandler).handle_connection(server_stream).await });
+        let (client, _) = EXOlineTCPClient::new(client_stream);
+
+        assert_eq!(read(&client, 3).await.unwrap(), Variant::Integer(3));
+    }
+
+    #[tokio::test]
+    async fn duplicate_response() {
+        let (client_stream, server_stream) = tokio::io::duplex(256);
+        tokio::spawn(async move {
+            let connection = Connection::new(server_stream);
+            let mut duplicate: Option<Vec<u8>> = None;
+            let mut first = true;
+            while let Ok(Some(frame)) = connection.read_request().await {
+                // The first response is sent again ahead of the second one.
+                if let Some(duplicate) = duplicate.take() {
+                    connection.write_response(&duplicate).await.unwrap();
+                }
+                let mut data = match CommandId::from(unescape(&frame)[2]) {
+                    CommandId::ReadInteger => 7i16.to_le_bytes().to_vec(),
+                    _ => 1.5f32.to_le_bytes().to_vec(),
                 };
-                let response_data = self.send_request(address, CommandId::ReadPartAttrHeader, &request).await?;
-                let response = ReadHugeResponse::decode_from_bytes(&response_data)?;
-                Ok(Variant::Huge(response.value))
+                append_crc(&mut data);
+                let data = escape(&data);
+                if std::mem::take(&mut first) {
+                    duplicate = Some(data.to_vec());
+                }
+                connection.write_response(&data).await.unwrap();
             }
-            VariableKind::Real => {
-                let request = ReadPartAttrHeader {
-                    kind: PartAttrHeaderKind::Real,
-                    part_no: partition,
-                    attr: attribute_id,
+        });
+        let (client, _) = EXOlineTCPClient::new(client_stream);
+        let read_real = || client.read_variable_raw((1, 1), FileKind::VPac, 1, VariableKind::Real, 0);
+
+        assert_eq!(read(&client, 0).await.unwrap(), Variant::Integer(7));
+        assert!(matches!(read_real().await, Err(EXOlineError::OutOfSync)));
+        assert_eq!(read_real().await.unwrap(), Variant::Real(1.5));
+    }
+
+    #[tokio::test]
+    async fn response_shapes() {
+        let (client_stream, server_stream) = tokio::io::duplex(1024);
+        tokio::spawn(async move {
+            let connection = Connection::new(server_stream);
+            while let Ok(Some(frame)) = connection.read_request().await {
+                let request = unescape(&frame);
+                // Every request is answered with a string, or with a page that is too long.
+                let mut data = match CommandId::from(request[2]) {
+                    CommandId::ReadDPacPage => vec![0; 121],
+                    CommandId::ReadString => vec![b'a'; 128],
+                    _ => b"Hello".to_vec(),
                 };
-                let response_data = self.send_request(address, CommandId::ReadPartAttrHeader, &request).await?;
-                let response = ReadRealResponse::decode_from_bytes(&response_data)?;
-                Ok(Variant::Real(response.value))
+                append_crc(&mut data);
+                connection.write_response(&escape(&data)).await.unwrap();
             }
-            VariableKind::String => {
-                let request = ReadPartAttrHeader {
-                    kind: PartAttrHeaderKind::String,
-                    part_no: partition,
-                    attr: attribute_id,
+        });
+        let (client, _) = EXOlineTCPClient::new(client_stream);
+
+        let result = client.read_variable_raw((1, 1), FileKind::VPac, 1, VariableKind::String, 0).await;
+        assert!(matches!(result, Err(EXOlineError::OutOfSync)), "{result:?}");
+        let result = client.read_dpac_page_raw((1, 1), FileKind::VPac, 1, 0).await;
+        assert!(matches!(result, Err(EXOlineError::OutOfSync)), "{result:?}");
+        let result = client.read_partition_attribute((1, 1), 0, VariableKind::Huge, 1).await;
+        assert!(matches!(result, Err(EXOlineError::OutOfSync)), "{result:?}");
+        let result = client.read_partition_attribute((1, 1), 0, VariableKind::String, 1).await;
+        assert_eq!(result.unwrap(), Variant::String("Hello".into()));
+        assert_eq!(client.read_controller_id((1, 1)).await.unwrap(), "Hello");
+        // Nothing tells what a write is answered with.
+        client.write_variable_raw((1, 1), FileKind::VPac, 1, VariableKind::Integer, 0, &Variant::Integer(1)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn retry_transient_errors() {
+        let (client_stream, server_stream) = tokio::io::duplex(256);
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            let connection = Connection::new(server_stream);
+            while let Ok(Some(frame)) = connection.read_request().await {
+                // Every other integer read, and every write, gets a response with a broken CRC.
+                let request = counter.fetch_add(1, Ordering::Relaxed);
+                let (mut data, broken) = match CommandId::from(unescape(&frame)[2]) {
+                    CommandId::ReadInteger => (7i16.to_le_bytes().to_vec(), request.is_multiple_of(2)),
+                    CommandId::WriteInteger => (vec![0], true),
+                    _ => {
+                        connection.write_response(&[EXOlineException::AddressOutsideRange.into()]).await.unwrap();
+                        continue;
+                    }
                 };
-                let response_data = self.send_request(address, CommandId::ReadPartAttrHeader, &request).await?;
-                let response = ReadStringResponse::decode_from_bytes(&response_data)?;
-                Ok(Variant::String(response.value.to_string()))
+                append_crc(&mut data);
+                if broken {
+                    *data.last_mut().unwrap() ^= 0xFF;
+                }
+                connection.write_response(&escape(&data)).await.unwrap();
             }
-            _ => {
-                Err(EXOlineError::InvalidArguments("Attribute kind is not valid for a partition header"))
+        });
+        let (client, _) = EXOlineTCPClient::new(client_stream);
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let client = client.with_retry_policy(policy);
+        async fn write(client: &EXOlineTCPClient) -> Result<(), EXOlineError> {
+            client.write_variable_raw((1, 1), FileKind::VPac, 1, VariableKind::Integer, 0, &Variant::Integer(1)).await
+        }
+
+        assert_eq!(read(&client, 0).await.unwrap(), Variant::Integer(7));
+        assert_eq!(requests.load(Ordering::Relaxed), 2);
+
+        assert!(matches!(write(&client).await, Err(EXOlineError::InvalidResponse(_))));
+        assert_eq!(requests.load(Ordering::Relaxed), 3);
+
+        let result = client.read_variable_raw((1, 1), FileKind::VPac, 1, VariableKind::Real, 0).await;
+        assert!(matches!(result, Err(EXOlineError::ExolineException(EXOlineException::AddressOutsideRange))));
+        assert_eq!(requests.load(Ordering::Relaxed), 4);
+
+        assert!(matches!(write(&client.with_idempotent_writes()).await, Err(EXOlineError::InvalidResponse(_))));
+        assert_eq!(requests.load(Ordering::Relaxed), 7);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn metrics() {
+        let client = connect().await;
+
+        read(&client, 1).await.unwrap();
+        read(&client, 2).await.unwrap();
+        let result = read(&client.with_timeout(Duration::from_millis(5)), 50).await;
+        assert!(matches!(result, Err(EXOlineError::Timeout)));
+
+        let metrics = client.metrics();
+        let reads = &metrics.commands[&CommandId::ReadInteger];
+        assert_eq!(reads.requests, 3);
+        assert_eq!((reads.failures, reads.timeouts), (1, 1));
+        assert_eq!(reads.latency.count, 2);
+        assert!(reads.latency.quantile(1.0).unwrap() >= Duration::from_millis(2));
+        assert_eq!(metrics.controllers[&(1, 1)].requests, 3);
+        // A read is 9 bytes, plus the bytes that begin and end the frame.
+        assert_eq!(metrics.bytes_sent, 3 * 11);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn late_response_is_discarded() {
+        let client = connect().await;
+        client.set_in_flight_window(2);
+
+        let result = read(&client.with_timeout(Duration::from_millis(20)), 100).await;
+        assert!(matches!(result, Err(EXOlineError::Timeout)));
+
+        assert_eq!(read(&client, 1).await.unwrap(), Variant::Integer(1));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn cancelled_request_is_discarded() {
+        let client = connect().await;
+
+        let result = tokio::time::timeout(Duration::from_millis(20), read(&client, 100)).await;
+        assert!(result.is_err());
+
+        assert_eq!(read(&client, 2).await.unwrap(), Variant::Integer(2));
+    }
+
+    #[tokio::test]
+    async fn unanswered_request_is_given_up() {
+        let (client_stream, server_stream) = tokio::io::duplex(256);
+        tokio::spawn(async move {
+            let connection = Connection::new(server_stream);
+            while let Ok(Some(frame)) = connection.read_request().await {
+                // Real reads are never answered.
+                if CommandId::from(unescape(&frame)[2]) == CommandId::ReadInteger {
+                    let mut data = 7i16.to_le_bytes().to_vec();
+                    append_crc(&mut data);
+                    connection.write_response(&escape(&data)).await.unwrap();
+                }
+            }
+        });
+        let (client, _) = EXOlineTCPClient::new(client_stream);
+
+        let read_real = client.read_variable_raw((1, 1), FileKind::VPac, 1, VariableKind::Real, 0);
+        assert!(tokio::time::timeout(Duration::from_millis(20), read_real).await.is_err());
+
+        // The next request waits for the grace period instead of getting stuck behind the cancelled one.
+        let start = Instant::now();
+        assert_eq!(read(&client, 0).await.unwrap(), Variant::Integer(7));
+        assert!(start.elapsed() >= LATE_RESPONSE_GRACE / 2);
+
+        // The grace period starts when the caller gives up, not when the next request is made.
+        let read_real = client.read_variable_raw((1, 1), FileKind::VPac, 1, VariableKind::Real, 0);
+        assert!(tokio::time::timeout(Duration::from_millis(20), read_real).await.is_err());
+        tokio::time::sleep(LATE_RESPONSE_GRACE).await;
+        let start = Instant::now();
+        assert_eq!(read(&client, 0).await.unwrap(), Variant::Integer(7));
+        assert!(start.elapsed() < LATE_RESPONSE_GRACE / 2);
+    }
+
+    #[tokio::test]
+    async fn cancelled_request_behind_live_one_is_given_up() {
+        let (client_stream, server_stream) = tokio::io::duplex(256);
+        tokio::spawn(async move {
+            let connection = Connection::new(server_stream);
+            let mut delays = [Duration::from_millis(500), Duration::from_millis(700)].into_iter();
+            while let Ok(Some(frame)) = connection.read_request().await {
+                // Integer reads are answered slowly, real reads never.
+                if CommandId::from(unescape(&frame)[2]) == CommandId::ReadInteger {
+                    tokio::time::sleep(delays.next().unwrap()).await;
+                    let mut data = 7i16.to_le_bytes().to_vec();
+                    append_crc(&mut data);
+                    connection.write_response(&escape(&data)).await.unwrap();
+                }
+            }
+        });
+        let (client, _) = EXOlineTCPClient::new(client_stream);
+        client.set_in_flight_window(2);
+
+        let live = tokio::spawn({
+            let client = client.clone();
+            async move { read(&client, 0).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let read_real = client.read_variable_raw((1, 1), FileKind::VPac, 1, VariableKind::Real, 0);
+        assert!(tokio::time::timeout(Duration::from_millis(20), read_real).await.is_err());
+        assert_eq!(live.await.unwrap().unwrap(), Variant::Integer(7));
+
+        // The cancelled request is given up on a second after it was cancelled, before this answer arrives,
+        // even though the request in front of it was still waiting for its answer then.
+        assert_eq!(read(&client, 0).await.unwrap(), Variant::Integer(7));
+    }
+
+    /// Tells when a request has been written.
+    #[cfg(feature = "tokio")]
+    #[derive(Default)]
+    struct RequestSent(Notify);
+
+    #[cfg(feature = "tokio")]
+    impl FrameObserver for RequestSent {
+        fn on_frame(&self, frame: &Frame) {
+            if frame.direction == FrameDirection::Outgoing {
+                self.0.notify_one();
             }
         }
     }
 
-    async fn send_request<T>(&self, address: (u8, u8), command_id: CommandId, request: &T) -> Result<Vec<u8>, EXOlineError>
-    where
-        T: Encodable,
-    {
-        let mut encoder = Encoder::new();
-        encoder.write_u8(address.0);
-        encoder.write_u8(address.1);
-        encoder.write_u8(command_id.into());
-        encoder
-            .write_type(request)
-            .map_err(|_| EXOlineError::InvalidArguments("Error encoding message"))?;
-
-        let mut request_data = encoder.finish();
-        append_crc(&mut request_data);
-        let request_data = escape(&request_data);
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn priorities() {
+        let client = connect().await;
+        let sent = Arc::new(RequestSent::default());
+        client.set_frame_observer(Some(sent.clone()));
+        let bulk = client.with_priority(Priority::Bulk);
+        let order = Arc::new(std::sync::Mutex::new(Vec::new()));
 
-        let (sender, receiver) = oneshot::channel::<ResponseResult>();
+        let mut join_set = JoinSet::new();
+        for offset in [20, 21, 22] {
+            let (bulk, order) = (bulk.clone(), order.clone());
+            join_set.spawn(async move {
+                read(&bulk, offset).await.unwrap();
+                order.lock().unwrap().push(offset);
+            });
+        }
+        // Only the first bulk read fits in the window, the others wait for its response.
+        sent.0.notified().await;
+        read(&client, 1).await.unwrap();
+        order.lock().unwrap().push(1);
+        join_set.join_all().await;
 
-        {
-            let mut map = self.response_queue.lock().await;
-            map.push_back(sender);
+        assert_eq!(*order.lock().unwrap(), [20, 1, 21, 22]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn reconnect_and_retry() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = ReconnectOptions {
+            initial_backoff: Duration::from_millis(10),
+            in_flight: InFlightPolicy::Retry,
+            ..Default::default()
+        };
+        let (client, _) = EXOlineTCPClient::connect_with_reconnect(listener.local_addr().unwrap().to_string(), options);
+        let mut events = client.connection_events();
+        let server = EXOlineTCPServer::new(SlowHandler);
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let connection = tokio::spawn({
+            let server = server.clone();
+            async move { server.handle_connection(stream).await }
+        });
+        assert_eq!(read(&client, 1).await.unwrap(), Variant::Integer(1));
+
+        // The connection is lost while the requests wait for their responses.
+        client.set_in_flight_window(3);
+        let request = tokio::spawn({
+            let client = client.clone();
+            async move { read(&client, 50).await }
+        });
+        async fn write(client: &EXOlineTCPClient, offset: u32) -> Result<(), EXOlineError> {
+            client.write_variable_raw((1, 1), FileKind::VPac, 1, VariableKind::Integer, offset, &Variant::Integer(1)).await
         }
+        let write_once = tokio::spawn({
+            let client = client.clone();
+            async move { write(&client, 50).await }
+        });
+        let idempotent_write = tokio::spawn({
+            let client = client.with_idempotent_writes();
+            async move { write(&client, 50).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        connection.abort();
+
+        let (stream, _) = listener.accept().await.unwrap();
+        tokio::spawn(async move { server.handle_connection(stream).await });
+        assert_eq!(request.await.unwrap().unwrap(), Variant::Integer(50));
+        assert!(matches!(write_once.await.unwrap(), Err(EXOlineError::IO(_))));
+        idempotent_write.await.unwrap().unwrap();
 
-        match self.connection.write_request(&request_data).await {
-            Ok(_) => {}
-            Err(e) => return Err(EXOlineError::IO(e.into())),
+        let mut states = Vec::new();
+        while let Ok(state) = events.try_recv() {
+            states.push(state);
         }
+        assert!(matches!(
+            states[..],
+            [
+                ConnectionState::Connecting,
+                ConnectionState::Connected,
+                ConnectionState::Disconnected(DisconnectReason::Closed),
+                ConnectionState::Connecting,
+                ConnectionState::Connected,
+            ]
+        ));
+    }
 
-        let response_data = match receiver.await {
-            Ok(Ok(data)) => data,
-            Ok(Err(error)) => return Err(error),
-            Err(_err) => unreachable!(),
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn segmented() {
+        let (controller, _, client) = connect_test_controller().await;
+
+        for (name, value) in [("Test.Integer", Variant::Integer(5)), ("Test.Values(60)", Variant::Integer(-60))] {
+            let variable = controller.lookup_variable(name).unwrap();
+            client.write_variable_segmented(controller.address, &variable, &value).await.unwrap();
+            assert_eq!(client.read_variable(controller.address, &variable).await.unwrap(), value);
+            assert_eq!(client.read_variable_segmented(controller.address, &variable).await.unwrap(), value);
+        }
+
+        let variable = controller.lookup_variable("Test.Huge").unwrap();
+        let result = client.read_variable_segmented(controller.address, &variable).await;
+        assert!(matches!(result, Err(EXOlineError::InvalidArguments(_))));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn bpac_commands() {
+        let (controller, device, client) = connect_test_controller().await;
+        device.set_reject_generic_commands(FileKind::BPac, true).await;
+
+        let variable = controller.lookup_variable("Table.Records(2).Flag").unwrap();
+        let result = client.write_variable(controller.address, &variable, &Variant::Index(3)).await;
+        assert!(matches!(result, Err(EXOlineError::ExolineException(EXOlineException::IllegalCommand))));
+        let result = client.read_variable(controller.address, &variable).await;
+        assert!(matches!(result, Err(EXOlineError::ExolineException(EXOlineException::IllegalCommand))));
+
+        let client = client.with_specific_commands();
+        client.write_variable(controller.address, &variable, &Variant::Index(3)).await.unwrap();
+        assert_eq!(client.read_variable(controller.address, &variable).await.unwrap(), Variant::Index(3));
+        assert_eq!(device.read_variable(&variable).await.unwrap(), Variant::Index(3));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn capabilities_are_forgotten_after_reconnect() {
+        let controller = test_controller();
+        let device = VirtualController::new(&controller);
+        device.set_reject_generic_commands(FileKind::BPac, true).await;
+        let variable = controller.lookup_variable("Table.Records(2).Flag").unwrap();
+        device.write_variable(&variable, &Variant::Index(3)).await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = ReconnectOptions {
+            initial_backoff: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let (client, _) = EXOlineTCPClient::connect_with_reconnect(listener.local_addr().unwrap().to_string(), options);
+        let client = client.with_specific_commands();
+        let requests = |command_id| client.metrics().commands.get(&command_id).map_or(0, |metrics| metrics.requests);
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let connection = tokio::spawn({
+            let device = device.clone();
+            async move { device.handle_connection(stream).await }
+        });
+        assert_eq!(client.read_variable(controller.address, &variable).await.unwrap(), Variant::Index(3));
+        assert_eq!((requests(CommandId::ReadIndex), requests(CommandId::ReadBPacIndex)), (1, 1));
+
+        // The device is updated to accept the generic commands while the connection is down.
+        let mut events = client.connection_events();
+        connection.abort();
+        device.set_reject_generic_commands(FileKind::BPac, false).await;
+
+        let (stream, _) = listener.accept().await.unwrap();
+        tokio::spawn(async move { device.handle_connection(stream).await });
+        while !matches!(events.recv().await.unwrap(), ConnectionState::Connected) {}
+        assert_eq!(client.read_variable(controller.address, &variable).await.unwrap(), Variant::Index(3));
+        assert_eq!((requests(CommandId::ReadIndex), requests(CommandId::ReadBPacIndex)), (2, 1));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn login() {
+        let (controller, device, client) = connect_test_controller().await;
+        device.set_password(Some("secret")).await;
+        let variable = controller.lookup_variable("Test.Integer").unwrap();
+
+        let result = client.read_variable(controller.address, &variable).await;
+        assert!(matches!(result, Err(EXOlineError::ExolineException(EXOlineException::NoAccess))));
+        assert_eq!(client.read_exoline_address().await.unwrap(), controller.address);
+
+        let result = client.log_in(controller.address, "wrong").await;
+        assert!(matches!(result, Err(EXOlineError::ExolineException(EXOlineException::WrongKey))));
+        assert_eq!(client.access_level(controller.address), None);
+
+        assert_eq!(client.log_in(controller.address, "secret").await.unwrap(), 3);
+        assert_eq!(client.access_level(controller.address), Some(3));
+        assert_eq!(client.read_variable(controller.address, &variable).await.unwrap(), Variant::Integer(0));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn login_on_every_connection() {
+        let mut controller = test_controller();
+        controller.require_password = true;
+        controller.system_password = Some("secret".into());
+        let device = VirtualController::new(&controller);
+        let variable = controller.lookup_variable("Test.Integer").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = ReconnectOptions {
+            initial_backoff: Duration::from_millis(10),
+            ..Default::default()
         };
+        let (client, _) = EXOlineTCPClient::connect_to_controller(listener.local_addr().unwrap().to_string(), &controller, options);
+        let requests = |command_id| client.metrics().commands.get(&command_id).map_or(0, |metrics| metrics.requests);
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let connection = tokio::spawn({
+            let device = device.clone();
+            async move { device.handle_connection(stream).await }
+        });
+        assert_eq!(client.read_variable(controller.address, &variable).await.unwrap(), Variant::Integer(0));
+        assert_eq!(client.access_level(controller.address), Some(3));
 
-        if response_data.len() == 1 {
-            return Err(EXOlineError::ExolineException(response_data[0].into()));
+        // The device forgets the login while the connection is down.
+        connection.abort();
+        device.set_password(Some("secret")).await;
+
+        let (stream, _) = listener.accept().await.unwrap();
+        tokio::spawn(async move { device.handle_connection(stream).await });
+        assert_eq!(client.read_variable(controller.address, &variable).await.unwrap(), Variant::Integer(0));
+        assert_eq!(client.access_level(controller.address), Some(3));
+        assert_eq!(requests(CommandId::Login), 2);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn task_commands() {
+        let (controller, device, client) = connect_test_controller().await;
+        device.set_reject_generic_commands(FileKind::Task, true).await;
+
+        let variable = controller.lookup_variable("Program.Counter").unwrap();
+        let result = client.write_variable(controller.address, &variable, &Variant::Integer(-7)).await;
+        assert!(matches!(result, Err(EXOlineError::ExolineException(EXOlineException::IllegalCommand))));
+
+        let client = client.with_specific_commands();
+
+        let values = [("Program.Counter", Variant::Integer(-7)), ("Program.Sum", Variant::Real(2.5)), ("Program.State", Variant::Index(4))];
+        for (name, value) in values.iter() {
+            let variable = controller.lookup_variable(name).unwrap();
+            client.write_variable(controller.address, &variable, value).await.unwrap();
+            assert_eq!(&client.read_variable(controller.address, &variable).await.unwrap(), value);
+        }
+
+        let file = controller.tasks().get("Program").unwrap();
+        let result = client.read_task(controller.address, &file).await.unwrap();
+        assert_eq!(result.len(), values.len());
+        for (name, value) in values.iter() {
+            assert_eq!(result.get(&controller.lookup_variable(name).unwrap()), Some(value));
         }
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn texts() {
+        let (controller, device, client) = connect_test_controller().await;
 
-        let response_data = unescape(&response_data);
-        let response_data = verify_and_remove_crc(&response_data).ok_or(EXOlineError::InvalidResponse("CRC mismatch"))?;
+        let values = [("Texts.Greeting", Variant::String("Hello".into())), ("Texts.Farewell", Variant::String("Bye".into()))];
+        for (name, value) in values.iter() {
+            let variable = controller.lookup_variable(name).unwrap();
+            client.write_variable(controller.address, &variable, value).await.unwrap();
+            assert_eq!(&device.read_variable(&variable).await.unwrap(), value);
+        }
 
-        Ok(response_data.into())
+        let file = controller.texts().get("Texts").unwrap();
+        let result = client.read_texts(controller.address, &file).await.unwrap();
+        assert_eq!(result.len(), values.len());
+        for (name, value) in values.iter() {
+            assert_eq!(result.get(&controller.lookup_variable(name).unwrap()), Some(value));
+        }
     }
-}
 
-impl Drop for EXOlineTCPClient {
-    fn drop(&mut self) {
-        self.abort_handle.abort();
+    #[cfg(all(feature = "tokio", feature = "unstable-stats"))]
+    #[tokio::test]
+    async fn stats() {
+        let (controller, device, client) = connect_test_controller().await;
+
+        let stats = client.read_controller_stats(controller.address).await.unwrap();
+        assert_eq!(stats.dpac_count, 3); // Including QSystem
+        assert_eq!(stats.task_count, 1);
+        assert!(stats.free_memory < stats.total_memory);
+
+        let file = controller.dpacs().get("Test").unwrap();
+        let stats = client.read_dpac_stats(controller.address, file.load_number()).await.unwrap();
+        assert_eq!(stats.page_count, 3);
+
+        let task_stats = TaskStats {
+            running: false,
+            cycle_time: Duration::from_millis(500),
+            execution_time: Duration::from_millis(3),
+            max_execution_time: Duration::from_millis(12),
+        };
+        device.set_task_stats(5, task_stats.clone()).await;
+        assert_eq!(client.read_task_stats(controller.address, 5).await.unwrap(), task_stats);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn read_dpac_pages() {
+        let (controller, device, client) = connect_test_controller().await;
+        let file = controller.dpacs().get("Test").unwrap();
+        let variable = controller.lookup_variable("Test.Values(68)").unwrap();
+        device.write_variable(&variable, &Variant::Integer(68)).await.unwrap();
+
+        let frames = Arc::new(Frames::default());
+        client.set_frame_observer(Some(frames.clone()));
+        let result = client.read_dpac(controller.address, &file).await.unwrap();
+        assert_eq!(result.len(), file.len() - 1); // Without the string
+        assert_eq!(result.get(&variable), Some(&Variant::Integer(68)));
+
+        // The number of pages comes from the file, the controller is only asked for the pages.
+        assert_eq!(frames.requests(CommandId::ReadDPacPage), 3);
+        assert_eq!(frames.0.lock().unwrap().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn cancelled_task() {
+        let task = tokio::spawn(std::future::pending::<()>());
+        task.abort();
+        let error = task.await.unwrap_err();
+        assert!(error.is_cancelled());
+        resume_panic(error);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn read_many() {
+        let (controller, device, client) = connect_test_controller().await;
+
+        let values = [
+            ("Test.Huge", Variant::Huge(1)),
+            ("Test.Real", Variant::Real(2.0)),
+            ("Test.Text", Variant::String("Three".into())),
+            ("Test.Values(60)", Variant::Integer(4)),
+            ("Table.Records(0).Value", Variant::Real(5.0)),
+            ("Table.Records(0).Flag", Variant::Index(6)),
+            ("Program.Counter", Variant::Integer(7)),
+            ("Program.Sum", Variant::Real(8.0)),
+        ];
+        let mut variables = Vec::new();
+        for (name, value) in values.iter() {
+            let variable = controller.lookup_variable(name).unwrap();
+            device.write_variable(&variable, value).await.unwrap();
+            variables.push(variable);
+        }
+
+        let frames = Arc::new(Frames::default());
+        client.set_frame_observer(Some(frames.clone()));
+        let result = client.read_many(controller.address, &variables).await;
+        assert_eq!(result.len(), values.len());
+        for (variable, (_, value)) in variables.iter().zip(values.iter()) {
+            assert_eq!(result.get(variable).unwrap().as_ref().unwrap(), value);
+        }
+
+        // One page for Huge and Real, one for the table row and one for the task locals.
+        assert_eq!(frames.requests(CommandId::ReadDPacPage), 2);
+        assert_eq!(frames.requests(CommandId::ReadTaskPage), 1);
+        assert_eq!(frames.requests(CommandId::ReadString), 1);
+        assert_eq!(frames.requests(CommandId::ReadInteger), 1);
+        assert_eq!(frames.0.lock().unwrap().len(), 10);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn dpac_with_strings() {
+        let (controller, device, client) = connect_test_controller().await;
+
+        let variable = controller.lookup_variable("Test.Text").unwrap();
+        device.write_variable(&variable, &Variant::String("Hello".into())).await.unwrap();
+
+        let file = controller.dpacs().get("Test").unwrap();
+        let result = client.read_dpac_with_strings(controller.address, &file).await.unwrap();
+        assert_eq!(result.len(), file.len());
+        assert_eq!(result.get(&variable).unwrap().as_ref().unwrap(), &Variant::String("Hello".into()));
+
+        let result = client.read_dpac_page_with_strings(controller.address, &file, 0).await.unwrap();
+        assert_eq!(result.get(&variable).unwrap().as_ref().unwrap(), &Variant::String("Hello".into()));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn subscribe() {
+        let (controller, device, client) = connect_test_controller().await;
+
+        let integer = controller.lookup_variable("Test.Integer").unwrap();
+        let real = controller.lookup_variable("Test.Real").unwrap();
+        let mut subscription = client.subscribe(
+            controller.address,
+            [SubscribedVariable::from(integer.clone()), (real.clone(), 1.0).into()],
+            Duration::from_millis(5),
+        );
+
+        async fn next(subscription: &mut Subscription) -> (Variable, Option<Variant>, Variant) {
+            let event = tokio::time::timeout(Duration::from_secs(1), subscription.recv()).await.unwrap().unwrap();
+            match event {
+                SubscriptionEvent::Changed { variable, old, new, .. } => (variable, old, new),
+                SubscriptionEvent::Error { error, .. } => panic!("{error}"),
+            }
+        }
+
+        let mut initial = [next(&mut subscription).await, next(&mut subscription).await];
+        initial.sort_by_key(|(variable, _, _)| variable.offset());
+        assert_eq!(initial[0], (integer.clone(), None, Variant::Integer(0)));
+        assert_eq!(initial[1], (real.clone(), None, Variant::Real(0.0)));
+
+        device.write_variable(&real, &Variant::Real(0.5)).await.unwrap(); // Within the deadband
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        device.write_variable(&integer, &Variant::Integer(3)).await.unwrap();
+        assert_eq!(next(&mut subscription).await, (integer, Some(Variant::Integer(0)), Variant::Integer(3)));
+
+        device.write_variable(&real, &Variant::Real(2.0)).await.unwrap();
+        assert_eq!(next(&mut subscription).await, (real, Some(Variant::Real(0.0)), Variant::Real(2.0)));
+    }
+
+    #[cfg(feature = "tracing")]
+    type Fields = HashMap<&'static str, String>;
+
+    /// Keeps the fields of every span, by the name of the span, and the spans that have been entered.
+    #[cfg(feature = "tracing")]
+    #[derive(Default, Clone)]
+    struct SpanFields {
+        spans: Arc<std::sync::Mutex<Vec<(&'static tracing::Metadata<'static>, Fields)>>>,
+        entered: Arc<std::sync::Mutex<Vec<tracing::span::Id>>>,
+    }
+
+    #[cfg(feature = "tracing")]
+    impl SpanFields {
+        fn spans(&self, name: &str) -> Vec<Fields> {
+            let spans = self.spans.lock().unwrap();
+            spans.iter().filter(|(span, _)| span.name() == name).map(|(_, fields)| fields.clone()).collect()
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    struct FieldVisitor<'a>(&'a mut Fields);
+
+    #[cfg(feature = "tracing")]
+    impl tracing::field::Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.insert(field.name(), value.into());
+        }
+
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name(), format!("{value:?}"));
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for SpanFields {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut spans = self.spans.lock().unwrap();
+            let mut fields = HashMap::new();
+            span.record(&mut FieldVisitor(&mut fields));
+            spans.push((span.metadata(), fields));
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+            let mut spans = self.spans.lock().unwrap();
+            values.record(&mut FieldVisitor(&mut spans[span.into_u64() as usize - 1].1));
+        }
+
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+        fn event(&self, _event: &tracing::Event<'_>) {}
+
+        fn enter(&self, span: &tracing::span::Id) {
+            self.entered.lock().unwrap().push(span.clone());
+        }
+
+        fn exit(&self, _span: &tracing::span::Id) {
+            self.entered.lock().unwrap().pop();
+        }
+
+        fn current_span(&self) -> tracing_core::span::Current {
+            match self.entered.lock().unwrap().last() {
+                Some(span) => tracing_core::span::Current::new(span.clone(), self.spans.lock().unwrap()[span.into_u64() as usize - 1].0),
+                None => tracing_core::span::Current::none(),
+            }
+        }
+    }
+
+    #[cfg(all(feature = "tokio", feature = "tracing"))]
+    #[tokio::test]
+    async fn request_spans() {
+        let subscriber = SpanFields::default();
+        let _guard = tracing::subscriber::set_default(subscriber.clone());
+        let (controller, _, client) = connect_test_controller().await;
+
+        let variable = controller.lookup_variable("Test.Integer").unwrap();
+        client.read_variable(controller.address, &variable).await.unwrap();
+        let result = client.read_variable_raw(controller.address, FileKind::VPac, 99, VariableKind::Integer, 3).await;
+        assert!(matches!(result, Err(EXOlineError::ExolineException(_))), "{result:?}");
+        client.write_variable(controller.address, &variable, &Variant::Integer(5)).await.unwrap();
+        client.read_variable_segmented(controller.address, &variable).await.unwrap();
+
+        let spans = subscriber.spans("exoline_request");
+        let [read, failed, write, segmented] = &spans[..] else {
+            panic!("Expected 4 requests, got {spans:?}");
+        };
+        assert_eq!(read["address"], "2:1");
+        assert_eq!(read["command"], "ReadInteger");
+        assert_eq!(read["load_number"], variable.load_number().to_string());
+        assert_eq!(read["offset"], variable.offset().to_string());
+        assert_eq!(read["attempts"], "1");
+        assert_eq!(read["outcome"], "ok");
+        assert_eq!(failed["load_number"], "99");
+        assert_eq!(failed["offset"], "3");
+        assert_eq!(failed["outcome"], "error");
+        assert_eq!(write["command"], "WriteInteger");
+        assert_eq!(write["offset"], variable.offset().to_string());
+        assert_eq!(segmented["command"], "ReadVPacIntegerSegmented");
+        assert_eq!(segmented["load_number"], variable.load_number().to_string());
+        assert_eq!(segmented["offset"], variable.offset().to_string());
+
+        let spans = subscriber.spans("exoline_read_variable");
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[1]["load_number"], "99");
     }
 }
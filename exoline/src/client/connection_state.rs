@@ -0,0 +1,23 @@
+use super::EXOlineError;
+
+/// The state of the connection to the device.
+#[derive(Debug, Clone)]
+pub enum ConnectionState {
+    /// Trying to establish a connection.
+    Connecting,
+    /// Connected to the device.
+    Connected,
+    /// Not connected to the device.
+    Disconnected(DisconnectReason),
+}
+
+/// Why the client is not connected.
+#[derive(Debug, Clone)]
+pub enum DisconnectReason {
+    /// The device closed the connection.
+    Closed,
+    /// The connection could not be established.
+    ConnectFailed(EXOlineError),
+    /// The connection failed.
+    Error(EXOlineError),
+}
@@ -1,16 +1,26 @@
 use num_enum::{FromPrimitive, IntoPrimitive};
 
+use crate::commands::PartAttrHeaderKind;
+
+/// Identifies the command of a request.
+///
+/// Features such as `unstable-stats` add commands, so matches need a wildcard arm.
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, IntoPrimitive, FromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, IntoPrimitive, FromPrimitive)]
+#[non_exhaustive]
 pub enum CommandId {
     GetControllerId = 0x19,
-    // GetControllerStats = 0x80,
-    // GetDPacStats = 0xA4,
-    // GetTaskStats = 0x16,
-    // ReadBPacIndex = 0x3D,
-    // ReadBPacInteger = 0x3E,
-    // ReadBPacLogic = 0xBC,
-    // ReadBPacReal = 0xBF,
+    #[cfg(feature = "unstable-stats")]
+    GetControllerStats = 0x80,
+    #[cfg(feature = "unstable-stats")]
+    GetDPacStats = 0xA4,
+    #[cfg(feature = "unstable-stats")]
+    GetTaskStats = 0x16,
+    Login = 0x8C,
+    ReadBPacIndex = 0x3D,
+    ReadBPacInteger = 0x3E,
+    ReadBPacLogic = 0xBC,
+    ReadBPacReal = 0xBF,
     ReadPartAttrHeader = 0xCB,
     ReadDPacPage = 0x10,
     ReadHuge = 0xD9,
@@ -19,43 +29,130 @@ pub enum CommandId {
     ReadLogic = 0xD5,
     ReadReal = 0x58,
     ReadString = 0xDA,
-    // ReadTaskIndex = 0x2C,
-    // ReadTaskInteger = 0xAD,
-    // ReadTaskLogic = 0xAB,
-    // ReadTaskPage = 0x0D,
-    // ReadTaskReal = 0xAE,
-    // ReadText = 0x8A,
+    ReadTaskIndex = 0x2C,
+    ReadTaskInteger = 0xAD,
+    ReadTaskLogic = 0xAB,
+    ReadTaskPage = 0x0D,
+    ReadTaskReal = 0xAE,
+    ReadText = 0x8A,
     // ReadVPacIndex = 0x07,
-    // ReadVPacIndexSegmented = 0x34,
+    ReadVPacIndexSegmented = 0x34,
     // ReadVPacInteger = 0x08,
-    // ReadVPacIntegerSegmented = 0xB5,
+    ReadVPacIntegerSegmented = 0xB5,
     // ReadVPacLogic = 0x86,
-    // ReadVPacLogicSegmented = 0xB3,
+    ReadVPacLogicSegmented = 0xB3,
     // ReadVPacReal = 0x89,
-    // ReadVPacRealSegmented = 0xB6,
-    // WriteBPacIndex = 0xB9,
-    // WriteBPacInteger = 0xBA,
-    // WriteBPacLogic = 0x38,
-    // WriteBPacReal = 0x3B,
+    ReadVPacRealSegmented = 0xB6,
+    WriteBPacIndex = 0xB9,
+    WriteBPacInteger = 0xBA,
+    WriteBPacLogic = 0x38,
+    WriteBPacReal = 0x3B,
     WriteHuge = 0xD3,
     WriteIndex = 0xD0,
     WriteInteger = 0x51,
     WriteLogic = 0x4F,
     WriteReal = 0x52,
     WriteString = 0x54,
-    // WriteTaskIndex = 0xA8,
-    // WriteTaskInteger = 0x29,
-    // WriteTaskLogic = 0xA7,
-    // WriteTaskReal = 0x2A,
-    // WriteText = 0x85,
+    WriteTaskIndex = 0xA8,
+    WriteTaskInteger = 0x29,
+    WriteTaskLogic = 0xA7,
+    WriteTaskReal = 0x2A,
+    WriteText = 0x85,
     // WriteVPacIndex = 0x02,
-    // WriteVPacIndexSegmented = 0xB0,
+    WriteVPacIndexSegmented = 0xB0,
     // WriteVPacInteger = 0x83,
-    // WriteVPacIntegerSegmented = 0x31,
+    WriteVPacIntegerSegmented = 0x31,
     // WriteVPacLogic = 0x01,
-    // WriteVPacLogicSegmented = 0x2F,
+    WriteVPacLogicSegmented = 0x2F,
     // WriteVPacReal = 0x04,
-    // WriteVPacRealSegmented = 0x32,
+    WriteVPacRealSegmented = 0x32,
     #[num_enum(catch_all)]
     Unknown(u8),
 }
+
+impl CommandId {
+    /// Whether the command writes to the controller.
+    pub fn is_write(self) -> bool {
+        matches!(
+            self,
+            Self::WriteBPacIndex
+                | Self::WriteBPacInteger
+                | Self::WriteBPacLogic
+                | Self::WriteBPacReal
+                | Self::WriteHuge
+                | Self::WriteIndex
+                | Self::WriteInteger
+                | Self::WriteLogic
+                | Self::WriteReal
+                | Self::WriteString
+                | Self::WriteTaskIndex
+                | Self::WriteTaskInteger
+                | Self::WriteTaskLogic
+                | Self::WriteTaskReal
+                | Self::WriteText
+                | Self::WriteVPacIndexSegmented
+                | Self::WriteVPacIntegerSegmented
+                | Self::WriteVPacLogicSegmented
+                | Self::WriteVPacRealSegmented
+        )
+    }
+
+    /// The payload a response to this command can have, given the payload of the request.
+    /// Exceptions are not included.
+    ///
+    /// Only what the decoders of the responses require is checked. Writes and the stats commands are [ResponseShape::Unknown],
+    /// since nothing tells what a device answers to them. Strings are sent without a length, so any string up to
+    /// [MAX_STRING_LENGTH] bytes fits, and a response of the same shape as the expected one can't be told apart from it,
+    /// like a duplicated answer. It is only found out when a later response doesn't fit.
+    pub fn response_shape(self, request: &[u8]) -> ResponseShape {
+        match self {
+            Self::ReadHuge | Self::ReadReal | Self::ReadBPacReal | Self::ReadTaskReal | Self::ReadVPacRealSegmented => ResponseShape::Exactly(4),
+            Self::ReadInteger | Self::ReadBPacInteger | Self::ReadTaskInteger | Self::ReadVPacIntegerSegmented => ResponseShape::Exactly(2),
+            Self::ReadIndex
+            | Self::ReadLogic
+            | Self::ReadBPacIndex
+            | Self::ReadBPacLogic
+            | Self::ReadTaskIndex
+            | Self::ReadTaskLogic
+            | Self::ReadVPacIndexSegmented
+            | Self::ReadVPacLogicSegmented
+            | Self::Login => ResponseShape::Exactly(1),
+            Self::ReadString | Self::ReadText | Self::GetControllerId => ResponseShape::AtMost(MAX_STRING_LENGTH),
+            // The kind of the attribute is the first byte of the request.
+            Self::ReadPartAttrHeader => match request.first().map(|kind| PartAttrHeaderKind::from(*kind)) {
+                Some(PartAttrHeaderKind::Huge | PartAttrHeaderKind::Real) => ResponseShape::Exactly(4),
+                Some(PartAttrHeaderKind::String) => ResponseShape::AtMost(MAX_STRING_LENGTH),
+                _ => ResponseShape::Unknown,
+            },
+            // The last page may be short, the rest of it is filled with zeros.
+            Self::ReadDPacPage | Self::ReadTaskPage => ResponseShape::AtMost(PAGE_SIZE),
+            _ => ResponseShape::Unknown,
+        }
+    }
+}
+
+/// The longest string that is sent, see [Decoder::read_string](super::encoding::Decoder::read_string).
+pub const MAX_STRING_LENGTH: usize = 127;
+
+/// The size of a page of a DPac or of the locals of a task.
+pub const PAGE_SIZE: usize = 120;
+
+/// The lengths the payload of a response can have.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseShape {
+    Exactly(usize),
+    AtMost(usize),
+    /// Any length.
+    Unknown,
+}
+
+impl ResponseShape {
+    /// Whether a payload of `length` bytes has this shape.
+    pub fn fits(self, length: usize) -> bool {
+        match self {
+            Self::Exactly(expected) => length == expected,
+            Self::AtMost(max) => length <= max,
+            Self::Unknown => true,
+        }
+    }
+}
@@ -1,23 +1,29 @@
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
-    net::{
-        tcp::{OwnedReadHalf, OwnedWriteHalf},
-        TcpStream,
-    },
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter, ReadHalf, WriteHalf},
     sync::Mutex,
 };
 
+use super::super::Transport;
 use super::consts::*;
 
 pub struct Connection {
-    reader: Mutex<BufReader<OwnedReadHalf>>,
-    writer: Mutex<BufWriter<OwnedWriteHalf>>,
+    reader: Mutex<Reader>,
+    writer: Mutex<BufWriter<WriteHalf<Box<dyn Transport>>>>,
+}
+
+/// The read half and what has been read of the next frame so far.
+/// Kept between reads, so that a read that is cancelled halfway through a frame loses nothing.
+struct Reader {
+    stream: BufReader<ReadHalf<Box<dyn Transport>>>,
+    frame: Option<Vec<u8>>,
+    discarded: Vec<u8>,
 }
 
 #[derive(Debug)]
 pub enum ReadError {
     IO(std::io::Error),
-    InvalidData,
+    /// The bytes that were thrown away. Reading continues at the next byte that begins a frame.
+    InvalidData(Vec<u8>),
 }
 
 impl From<std::io::Error> for ReadError {
@@ -27,64 +33,89 @@ impl From<std::io::Error> for ReadError {
 }
 
 impl Connection {
-    pub fn new(stream: TcpStream) -> Self {
-        let (reader, writer) = stream.into_split();
+    pub fn new(stream: impl Transport) -> Self {
+        let stream: Box<dyn Transport> = Box::new(stream);
+        let (reader, writer) = tokio::io::split(stream);
         Self {
-            reader: Mutex::new(BufReader::new(reader)),
+            reader: Mutex::new(Reader {
+                stream: BufReader::new(reader),
+                frame: None,
+                discarded: Vec::new(),
+            }),
             writer: Mutex::new(BufWriter::new(writer)),
         }
     }
 
     pub async fn read_response(&self) -> Result<Option<Vec<u8>>, ReadError> {
-        let mut reader = self.reader.lock().await;
-        let mut buffer: Option<Vec<u8>> = None;
-        loop {
-            let mut byte = [0; 1];
+        self.read_message(BEGIN_RESPONSE, BEGIN_REQUEST).await
+    }
 
-            let bytes_read = reader.read(&mut byte).await?;
+    pub async fn read_request(&self) -> Result<Option<Vec<u8>>, ReadError> {
+        self.read_message(BEGIN_REQUEST, BEGIN_RESPONSE).await
+    }
 
-            if bytes_read == 0 {
+    /// Reads the next frame that starts with `begin`.
+    ///
+    /// Bytes that don't belong to a frame are returned as [ReadError::InvalidData] once the next frame begins,
+    /// so that the following call starts reading at the beginning of that frame.
+    ///
+    /// Cancel safe. A frame that is partly read when the future is dropped is finished by the next call.
+    async fn read_message(&self, begin: u8, unexpected: u8) -> Result<Option<Vec<u8>>, ReadError> {
+        let mut reader = self.reader.lock().await;
+        let Reader { stream, frame: buffer, discarded } = &mut *reader;
+        loop {
+            let Some(&byte) = stream.fill_buf().await?.first() else {
                 _ = self.writer.lock().await.shutdown().await;
                 return Ok(None);
+            };
+
+            if byte == begin {
+                // Leave the byte for the next call.
+                if let Some(buffer) = buffer.take() {
+                    discarded.push(begin);
+                    discarded.extend(buffer);
+                    return Err(ReadError::InvalidData(std::mem::take(discarded)));
+                }
+                if !discarded.is_empty() {
+                    return Err(ReadError::InvalidData(std::mem::take(discarded)));
+                }
+                stream.consume(1);
+                *buffer = Some(Vec::with_capacity(16));
+                continue;
             }
+            stream.consume(1);
 
-            match byte[0] {
-                BEGIN_RESPONSE => match buffer {
-                    Some(_) => {
-                        return Err(ReadError::InvalidData);
-                    }
-                    None => buffer = Some(Vec::with_capacity(16)),
-                },
-                BEGIN_REQUEST => {
-                    return Err(ReadError::InvalidData);
+            match buffer {
+                Some(_) if byte == END_MESSAGE => return Ok(buffer.take()),
+                Some(frame) if byte != unexpected && frame.len() <= 1024 => frame.push(byte),
+                Some(frame) => {
+                    discarded.push(begin);
+                    discarded.append(frame);
+                    discarded.push(byte);
+                    *buffer = None;
                 }
-                END_MESSAGE => match buffer.take() {
-                    None => {
-                        return Err(ReadError::InvalidData);
-                    }
-                    Some(buffer) => {
-                        return Ok(Some(buffer));
-                    }
-                },
-                value => match &mut buffer {
-                    None => {
-                        return Err(ReadError::InvalidData);
-                    }
-                    Some(buffer) => {
-                        if buffer.len() > 1024 {
-                            return Err(ReadError::InvalidData);
-                        }
-                        buffer.push(value);
+                None => {
+                    if discarded.len() > 1024 {
+                        return Err(ReadError::InvalidData(std::mem::take(discarded)));
                     }
-                },
+                    discarded.push(byte);
+                }
             }
         }
     }
 
     pub async fn write_request(&self, data: &[u8]) -> Result<(), std::io::Error> {
+        self.write_message(BEGIN_REQUEST, data).await
+    }
+
+    pub async fn write_response(&self, data: &[u8]) -> Result<(), std::io::Error> {
+        self.write_message(BEGIN_RESPONSE, data).await
+    }
+
+    async fn write_message(&self, begin: u8, data: &[u8]) -> Result<(), std::io::Error> {
         let mut writer = self.writer.lock().await;
 
-        writer.write_u8(BEGIN_REQUEST).await?;
+        writer.write_u8(begin).await?;
         writer.write_all(data).await?;
         writer.write_u8(END_MESSAGE).await?;
         writer.flush().await?;
@@ -92,3 +123,30 @@ impl Connection {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn cancelled_read_keeps_the_frame() {
+        let (mut device, stream) = tokio::io::duplex(64);
+        let connection = Connection::new(stream);
+
+        device.write_all(&[BEGIN_RESPONSE, 1, 2]).await.unwrap();
+        let read = tokio::time::timeout(Duration::from_millis(20), connection.read_response()).await;
+        assert!(read.is_err());
+
+        device.write_all(&[3, END_MESSAGE, BEGIN_RESPONSE, 4]).await.unwrap();
+        assert_eq!(connection.read_response().await.unwrap(), Some(vec![1, 2, 3]));
+        let read = tokio::time::timeout(Duration::from_millis(20), connection.read_response()).await;
+        assert!(read.is_err());
+
+        device.write_all(&[5, END_MESSAGE]).await.unwrap();
+        assert_eq!(connection.read_response().await.unwrap(), Some(vec![4, 5]));
+    }
+}
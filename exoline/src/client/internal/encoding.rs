@@ -60,6 +60,11 @@ impl Encoder {
         self.buffer.extend(&value.to_le_bytes()[0..3]);
     }
 
+    #[cfg(feature = "unstable-stats")]
+    pub fn write_u32(&mut self, value: u32) {
+        self.buffer.extend(value.to_le_bytes());
+    }
+
     pub fn write_i32(&mut self, value: i32) {
         self.buffer.extend(value.to_le_bytes());
     }
@@ -165,6 +170,13 @@ impl<'a> Decoder<'a> {
         Ok(value)
     }
 
+    #[cfg(feature = "unstable-stats")]
+    pub fn read_u32(&mut self) -> DecodeResult<u32> {
+        let bytes = self.read_bytes_const()?;
+        let value = u32::from_le_bytes(bytes);
+        Ok(value)
+    }
+
     pub fn read_i32(&mut self) -> DecodeResult<i32> {
         let bytes = self.read_bytes_const()?;
         let value = i32::from_le_bytes(bytes);
@@ -311,6 +323,11 @@ pub fn verify_and_remove_crc(data: &[u8]) -> Option<&[u8]> {
     Some(body)
 }
 
+/// Formats bytes as hex, like `01 AB` with `" "` as the separator.
+pub fn hex(data: &[u8], separator: &str) -> String {
+    data.iter().map(|byte| format!("{byte:02X}")).collect::<Vec<_>>().join(separator)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,5 +1,4 @@
 pub mod command_id;
-pub mod commands;
 pub mod connection;
 pub mod consts;
 pub mod encoding;
@@ -0,0 +1,178 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use super::{CommandId, EXOlineError, EXOlineException};
+
+/// The upper bounds of the buckets of a [LatencyHistogram]. Longer times go in a last bucket.
+pub const LATENCY_BUCKETS: [Duration; 12] = [
+    Duration::from_millis(1),
+    Duration::from_millis(2),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(20),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(200),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(5),
+];
+
+/// Receives the measurements of an [EXOlineTCPClient](super::EXOlineTCPClient) as they are made.
+///
+/// Called from the task that owns the connection, so it should return quickly.
+pub trait MetricsObserver: Send + Sync + 'static {
+    fn on_event(&self, event: &MetricsEvent);
+}
+
+/// A measurement reported to a [MetricsObserver].
+#[derive(Debug, Clone)]
+pub enum MetricsEvent {
+    /// A request was written to the connection.
+    RequestSent { address: (u8, u8), command_id: CommandId, bytes: usize },
+    /// The response to a request arrived.
+    ResponseReceived {
+        address: (u8, u8),
+        command_id: CommandId,
+        /// The time from writing the request until the response arrived.
+        latency: Duration,
+        bytes: usize,
+        outcome: ResponseOutcome,
+    },
+    /// A request failed, with or without a response.
+    RequestFailed { address: (u8, u8), command_id: CommandId, error: EXOlineError },
+    /// Received bytes that didn't answer a request, like unexpected responses and invalid data.
+    Discarded { bytes: usize },
+    /// The number of requests waiting to be sent changed.
+    QueueDepth(usize),
+    /// The connection was established again after it was lost.
+    Reconnected,
+}
+
+/// What kind of response a request got.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseOutcome {
+    Ok,
+    Exception(EXOlineException),
+    /// The CRC doesn't match the content.
+    CrcMismatch,
+}
+
+/// A snapshot of the measurements of a client, see [EXOlineTCPClient::metrics](super::EXOlineTCPClient::metrics).
+#[derive(Debug, Clone, Default)]
+pub struct ClientMetrics {
+    /// Requests by command.
+    pub commands: HashMap<CommandId, RequestMetrics>,
+    /// Requests by the address of the controller.
+    pub controllers: HashMap<(u8, u8), RequestMetrics>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// The number of requests waiting to be sent.
+    pub queue_depth: usize,
+    /// The largest number of requests that have been waiting to be sent.
+    pub max_queue_depth: usize,
+    pub reconnects: u64,
+}
+
+/// Measurements of the requests of a command, or to a controller.
+#[derive(Debug, Clone, Default)]
+pub struct RequestMetrics {
+    /// Requests written to the connection. Retries are counted again.
+    pub requests: u64,
+    /// Requests that failed, including the ones below.
+    pub failures: u64,
+    /// Requests that got no response within the timeout.
+    pub timeouts: u64,
+    /// Responses with a CRC that doesn't match.
+    pub crc_failures: u64,
+    /// Exceptions by code.
+    pub exceptions: BTreeMap<u8, u64>,
+    pub latency: LatencyHistogram,
+}
+
+/// Round-trip times, counted in the buckets of [LATENCY_BUCKETS].
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    /// The number of times in each bucket. The last one holds the times above the largest bound.
+    pub buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    pub count: u64,
+    pub sum: Duration,
+    pub max: Duration,
+}
+
+impl LatencyHistogram {
+    fn record(&mut self, latency: Duration) {
+        let bucket = LATENCY_BUCKETS.iter().position(|bound| latency <= *bound).unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        self.sum.checked_div(self.count.try_into().ok()?)
+    }
+
+    /// The upper bound of the bucket that holds the `quantile`, like 0.99 for the 99th percentile.
+    /// The [max](Self::max) if it's in the last bucket.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (quantile.clamp(0.0, 1.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(LATENCY_BUCKETS.get(bucket).map_or(self.max, |bound| (*bound).min(self.max)));
+            }
+        }
+        Some(self.max)
+    }
+}
+
+impl ClientMetrics {
+    pub(crate) fn record(&mut self, event: &MetricsEvent) {
+        match event {
+            MetricsEvent::RequestSent { address, command_id, bytes } => {
+                self.bytes_sent += *bytes as u64;
+                self.update(*address, *command_id, |metrics| metrics.requests += 1);
+            }
+            MetricsEvent::ResponseReceived {
+                address,
+                command_id,
+                latency,
+                bytes,
+                outcome,
+            } => {
+                self.bytes_received += *bytes as u64;
+                self.update(*address, *command_id, |metrics| {
+                    metrics.latency.record(*latency);
+                    match outcome {
+                        ResponseOutcome::Ok => {}
+                        ResponseOutcome::Exception(exception) => *metrics.exceptions.entry((*exception).into()).or_default() += 1,
+                        ResponseOutcome::CrcMismatch => metrics.crc_failures += 1,
+                    }
+                });
+            }
+            MetricsEvent::RequestFailed { address, command_id, error } => self.update(*address, *command_id, |metrics| {
+                metrics.failures += 1;
+                if let EXOlineError::Timeout = error {
+                    metrics.timeouts += 1;
+                }
+            }),
+            MetricsEvent::Discarded { bytes } => self.bytes_received += *bytes as u64,
+            MetricsEvent::QueueDepth(depth) => {
+                self.queue_depth = *depth;
+                self.max_queue_depth = self.max_queue_depth.max(*depth);
+            }
+            MetricsEvent::Reconnected => self.reconnects += 1,
+        }
+    }
+
+    fn update(&mut self, address: (u8, u8), command_id: CommandId, update: impl Fn(&mut RequestMetrics)) {
+        update(self.commands.entry(command_id).or_default());
+        update(self.controllers.entry(address).or_default());
+    }
+}
@@ -0,0 +1,217 @@
+use metrics::{counter, gauge, histogram};
+
+use super::{EXOlineError, MetricsEvent, MetricsObserver, ResponseOutcome};
+
+/// A [MetricsObserver] that forwards the measurements to the [metrics] crate,
+/// for whichever recorder the application has installed.
+///
+/// Requests are labeled with `command` and `controller`.
+///
+/// | Name | Kind |
+/// |------|------|
+/// | `exoline_requests_total` | counter |
+/// | `exoline_request_failures_total` | counter, also labeled with `error` |
+/// | `exoline_response_seconds` | histogram |
+/// | `exoline_crc_failures_total` | counter |
+/// | `exoline_exceptions_total` | counter, also labeled with `code` |
+/// | `exoline_bytes_sent_total` | counter |
+/// | `exoline_bytes_received_total` | counter |
+/// | `exoline_queue_depth` | gauge |
+/// | `exoline_reconnects_total` | counter |
+#[derive(Debug, Default)]
+pub struct MetricsExporter;
+
+impl MetricsObserver for MetricsExporter {
+    fn on_event(&self, event: &MetricsEvent) {
+        match event {
+            MetricsEvent::RequestSent { address, command_id, bytes } => {
+                counter!("exoline_requests_total", "command" => format!("{command_id:?}"), "controller" => controller(*address)).increment(1);
+                counter!("exoline_bytes_sent_total").increment(*bytes as u64);
+            }
+            MetricsEvent::ResponseReceived {
+                address,
+                command_id,
+                latency,
+                bytes,
+                outcome,
+            } => {
+                let labels = [("command", format!("{command_id:?}")), ("controller", controller(*address))];
+                histogram!("exoline_response_seconds", &labels).record(latency.as_secs_f64());
+                counter!("exoline_bytes_received_total").increment(*bytes as u64);
+                match outcome {
+                    ResponseOutcome::Ok => {}
+                    ResponseOutcome::Exception(exception) => {
+                        counter!("exoline_exceptions_total", "code" => format!("{exception:?}"), "command" => labels[0].1.clone(), "controller" => labels[1].1.clone())
+                            .increment(1);
+                    }
+                    ResponseOutcome::CrcMismatch => counter!("exoline_crc_failures_total", &labels).increment(1),
+                }
+            }
+            MetricsEvent::RequestFailed { address, command_id, error } => {
+                let error = match error {
+                    EXOlineError::IO(_) => "io",
+                    EXOlineError::InvalidArguments(_) => "invalid_arguments",
+                    EXOlineError::Internal(_) => "internal",
+                    EXOlineError::InvalidResponse(_) => "invalid_response",
+                    EXOlineError::ExolineException(_) => "exception",
+                    EXOlineError::Timeout => "timeout",
+                    EXOlineError::OutOfSync => "out_of_sync",
+                };
+                counter!("exoline_request_failures_total", "command" => format!("{command_id:?}"), "controller" => controller(*address), "error" => error)
+                    .increment(1);
+            }
+            MetricsEvent::Discarded { bytes } => counter!("exoline_bytes_received_total").increment(*bytes as u64),
+            MetricsEvent::QueueDepth(depth) => gauge!("exoline_queue_depth").set(*depth as f64),
+            MetricsEvent::Reconnected => counter!("exoline_reconnects_total").increment(1),
+        }
+    }
+}
+
+/// The controller label, "PLA:ELA".
+fn controller((pla, ela): (u8, u8)) -> String {
+    format!("{pla}:{ela}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use metrics::{Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+
+    use crate::client::internal::command_id::CommandId;
+    use crate::client::EXOlineException;
+
+    use super::*;
+
+    /// Keeps the last value of every metric, with counters added up, by the name and the labels.
+    #[derive(Default)]
+    struct TestRecorder(Arc<Mutex<HashMap<String, f64>>>);
+
+    struct Handle {
+        key: String,
+        values: Arc<Mutex<HashMap<String, f64>>>,
+    }
+
+    impl Handle {
+        fn update(&self, update: impl FnOnce(&mut f64)) {
+            update(self.values.lock().unwrap().entry(self.key.clone()).or_default());
+        }
+    }
+
+    impl CounterFn for Handle {
+        fn increment(&self, value: u64) {
+            self.update(|total| *total += value as f64);
+        }
+
+        fn absolute(&self, value: u64) {
+            self.update(|total| *total = value as f64);
+        }
+    }
+
+    impl GaugeFn for Handle {
+        fn increment(&self, value: f64) {
+            self.update(|total| *total += value);
+        }
+
+        fn decrement(&self, value: f64) {
+            self.update(|total| *total -= value);
+        }
+
+        fn set(&self, value: f64) {
+            self.update(|total| *total = value);
+        }
+    }
+
+    impl HistogramFn for Handle {
+        fn record(&self, value: f64) {
+            self.update(|last| *last = value);
+        }
+    }
+
+    impl TestRecorder {
+        fn handle(&self, key: &Key) -> Arc<Handle> {
+            let labels = key.labels().map(|label| format!("{}={}", label.key(), label.value())).collect::<Vec<_>>();
+            Arc::new(Handle {
+                key: format!("{}{{{}}}", key.name(), labels.join(",")),
+                values: self.0.clone(),
+            })
+        }
+
+        fn value(&self, key: &str) -> Option<f64> {
+            self.0.lock().unwrap().get(key).copied()
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+        fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+        fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.handle(key))
+        }
+
+        fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.handle(key))
+        }
+
+        fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(self.handle(key))
+        }
+    }
+
+    #[test]
+    fn export() {
+        let recorder = TestRecorder::default();
+        let address = (1, 2);
+        let command_id = CommandId::ReadInteger;
+        let events = [
+            MetricsEvent::RequestSent { address, command_id, bytes: 11 },
+            MetricsEvent::RequestSent { address, command_id, bytes: 11 },
+            MetricsEvent::ResponseReceived {
+                address,
+                command_id,
+                latency: Duration::from_millis(250),
+                bytes: 7,
+                outcome: ResponseOutcome::Ok,
+            },
+            MetricsEvent::ResponseReceived {
+                address,
+                command_id,
+                latency: Duration::from_millis(10),
+                bytes: 3,
+                outcome: ResponseOutcome::Exception(EXOlineException::AddressOutsideRange),
+            },
+            MetricsEvent::RequestFailed {
+                address,
+                command_id,
+                error: EXOlineError::Timeout,
+            },
+            MetricsEvent::Discarded { bytes: 5 },
+            MetricsEvent::QueueDepth(3),
+            MetricsEvent::Reconnected,
+        ];
+        metrics::with_local_recorder(&recorder, || {
+            for event in &events {
+                MetricsExporter.on_event(event);
+            }
+        });
+
+        assert_eq!(recorder.value("exoline_requests_total{command=ReadInteger,controller=1:2}"), Some(2.0));
+        assert_eq!(recorder.value("exoline_bytes_sent_total{}"), Some(22.0));
+        assert_eq!(recorder.value("exoline_response_seconds{command=ReadInteger,controller=1:2}"), Some(0.01));
+        assert_eq!(recorder.value("exoline_bytes_received_total{}"), Some(15.0));
+        assert_eq!(
+            recorder.value("exoline_exceptions_total{code=AddressOutsideRange,command=ReadInteger,controller=1:2}"),
+            Some(1.0)
+        );
+        assert_eq!(recorder.value("exoline_request_failures_total{command=ReadInteger,controller=1:2,error=timeout}"), Some(1.0));
+        assert_eq!(recorder.value("exoline_crc_failures_total{command=ReadInteger,controller=1:2}"), None);
+        assert_eq!(recorder.value("exoline_queue_depth{}"), Some(3.0));
+        assert_eq!(recorder.value("exoline_reconnects_total{}"), Some(1.0));
+    }
+}
@@ -2,12 +2,48 @@
 //! Reads data from a device.
 //!
 //! Start with the [EXOlineTCPClient].
+//!
+//! The client needs a Tokio runtime, also without the `tokio` feature.
+//! It spawns its tasks and timers on the runtime it is created on.
 
 mod client_impl;
+mod connection_state;
 mod exoline_exception;
-mod internal;
+pub(crate) mod internal;
+mod metrics;
+#[cfg(feature = "metrics")]
+mod metrics_exporter;
+mod priority;
+mod reconnect_options;
+mod retry_policy;
+#[cfg(feature = "tokio")]
+mod scan;
+#[cfg(feature = "serial")]
+mod serial;
+#[cfg(feature = "unstable-stats")]
+mod stats;
+mod subscription;
+mod trace;
+mod transport;
 mod variant;
 
-pub use client_impl::{EXOlineError, EXOlineTCPClient};
+pub use client_impl::{EXOlineError, EXOlineTCPClient, EXOLINE_TCP_PORT};
+pub use connection_state::{ConnectionState, DisconnectReason};
 pub use exoline_exception::EXOlineException;
+pub use internal::command_id::CommandId;
+pub use metrics::{ClientMetrics, LatencyHistogram, MetricsEvent, MetricsObserver, RequestMetrics, ResponseOutcome, LATENCY_BUCKETS};
+#[cfg(feature = "metrics")]
+pub use metrics_exporter::MetricsExporter;
+pub use priority::Priority;
+pub use reconnect_options::{InFlightPolicy, ReconnectOptions};
+pub use retry_policy::RetryPolicy;
+#[cfg(feature = "tokio")]
+pub use scan::{parse_hosts, scan, ScanOptions, ScannedController};
+#[cfg(feature = "serial")]
+pub use serial::{Parity, SerialOptions, SerialTransport};
+#[cfg(feature = "unstable-stats")]
+pub use stats::{ControllerStats, DPacStats, TaskStats};
+pub use subscription::{SubscribedVariable, Subscription, SubscriptionEvent};
+pub use trace::{Frame, FrameDirection, FrameError, FrameObserver, JsonLinesTraceWriter, TextTraceWriter};
+pub use transport::Transport;
 pub use variant::Variant;
@@ -0,0 +1,15 @@
+/// How urgent a request is.
+///
+/// Requests that wait for room in the [in-flight window](super::EXOlineTCPClient::set_in_flight_window) are sent in order of priority,
+/// and in the order they were made within a priority.
+///
+/// Unless a priority is set with [with_priority](super::EXOlineTCPClient::with_priority), writes are [Interactive](Priority::Interactive),
+/// reads that sweep many variables or whole files are [Bulk](Priority::Bulk), and everything else is [Normal](Priority::Normal).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Background work, like dumps and polling.
+    Bulk,
+    Normal,
+    /// Operator actions, like writes.
+    Interactive,
+}
@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+/// What happens to requests waiting for a response when the connection is lost.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InFlightPolicy {
+    /// The requests fail with the error that closed the connection.
+    Fail,
+    /// The requests are sent again after reconnecting. Writes are only sent again when they are marked idempotent,
+    /// see [EXOlineTCPClient::with_idempotent_writes](super::EXOlineTCPClient::with_idempotent_writes).
+    /// The others fail like with [InFlightPolicy::Fail], since they may already have been performed.
+    Retry,
+}
+
+/// Configuration for [EXOlineTCPClient::connect_with_reconnect](super::EXOlineTCPClient::connect_with_reconnect).
+#[derive(Debug, Clone)]
+pub struct ReconnectOptions {
+    /// The delay after the first failed connection attempt.
+    pub initial_backoff: Duration,
+    /// The longest delay between connection attempts.
+    pub max_backoff: Duration,
+    /// The delay is multiplied with this after every failed attempt.
+    pub backoff_multiplier: f64,
+    /// The time to wait for a connection to be established.
+    pub connect_timeout: Duration,
+    /// Give up after this many failed attempts in a row. `None` never gives up.
+    pub max_attempts: Option<u32>,
+    /// What happens to requests waiting for a response when the connection is lost.
+    pub in_flight: InFlightPolicy,
+}
+
+impl Default for ReconnectOptions {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            backoff_multiplier: 2.0,
+            connect_timeout: Duration::from_secs(5),
+            max_attempts: None,
+            in_flight: InFlightPolicy::Fail,
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+/// When a failed request is sent again, see [EXOlineTCPClient::set_retry_policy](super::EXOlineTCPClient::set_retry_policy).
+///
+/// Only errors where a new attempt may succeed are retried, see [EXOlineError::is_transient](super::EXOlineError::is_transient).
+/// IO errors are only retried by clients that reconnect, and exceptions from the controller are never retried.
+/// Writes are not retried unless the client says they are idempotent, see
+/// [EXOlineTCPClient::with_idempotent_writes](super::EXOlineTCPClient::with_idempotent_writes).
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The number of times a request is sent, including the first time.
+    pub max_attempts: u32,
+    /// The delay before the first retry.
+    pub initial_backoff: Duration,
+    /// The longest delay between two attempts.
+    pub max_backoff: Duration,
+    /// The delay is multiplied with this after every retry.
+    pub backoff_multiplier: f64,
+    /// Each delay is varied randomly by up to this fraction, so that clients that failed together don't retry together.
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            backoff_multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before retry number `retry`, starting at 1.
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self.initial_backoff.as_secs_f64() * self.backoff_multiplier.powi(retry.saturating_sub(1) as i32);
+        let backoff = Duration::try_from_secs_f64(backoff).unwrap_or(self.max_backoff).min(self.max_backoff);
+        // Uniform in -jitter..jitter.
+        let jitter = self.jitter.clamp(0.0, 1.0) * (2.0 * fastrand::f64() - 1.0);
+        backoff.mul_f64(1.0 + jitter)
+    }
+}
@@ -0,0 +1,200 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+
+use super::client_impl::resume_panic;
+use super::{EXOlineError, EXOlineTCPClient, EXOLINE_TCP_PORT};
+
+/// The largest number of hosts [parse_hosts] expands a range to.
+const MAX_HOSTS: u128 = 65536;
+
+/// Configuration for [scan].
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// The TCP port to probe.
+    pub port: u16,
+    /// The number of hosts probed at once.
+    pub concurrency: usize,
+    /// The time to wait for a connection to be established.
+    pub connect_timeout: Duration,
+    /// The time to wait for each response once connected.
+    pub timeout: Duration,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            port: EXOLINE_TCP_PORT,
+            concurrency: 64,
+            connect_timeout: Duration::from_millis(500),
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A controller that answered a [scan].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScannedController {
+    pub addr: SocketAddr,
+    /// The EXOline address of the controller. (PLA, ELA)
+    pub address: (u8, u8),
+    /// The controller model and version, as returned by [read_controller_id](EXOlineTCPClient::read_controller_id).
+    /// `None` if the controller didn't answer.
+    pub controller_id: Option<String>,
+}
+
+impl ScannedController {
+    /// The model part of the controller id.
+    pub fn model(&self) -> Option<&str> {
+        let id = self.controller_id.as_deref()?;
+        Some(split_controller_id(id).0)
+    }
+
+    /// The firmware version, if the controller id ends with one.
+    pub fn firmware(&self) -> Option<&str> {
+        split_controller_id(self.controller_id.as_deref()?).1
+    }
+}
+
+/// Splits a controller id into the model and the firmware version.
+/// The version is the last word, when it starts with a digit.
+fn split_controller_id(id: &str) -> (&str, Option<&str>) {
+    let id = id.trim();
+    match id.rsplit_once(char::is_whitespace) {
+        Some((model, version)) if version.starts_with(|c: char| c.is_ascii_digit()) => (model.trim_end(), Some(version)),
+        _ => (id, None),
+    }
+}
+
+/// Probes every host for an EXOline controller, and returns the ones that answered ordered by IP address.
+///
+/// Each host that accepts a connection is asked for its EXOline address and its controller id.
+/// Hosts that don't answer with an address are left out.
+pub async fn scan<I>(hosts: I, options: &ScanOptions) -> Vec<ScannedController>
+where
+    I: IntoIterator<Item = IpAddr>,
+{
+    let mut hosts = hosts.into_iter();
+    let mut found = Vec::new();
+    let mut join_set = JoinSet::new();
+
+    loop {
+        while join_set.len() < options.concurrency.max(1) {
+            let Some(host) = hosts.next() else {
+                break;
+            };
+            join_set.spawn(probe(SocketAddr::new(host, options.port), options.clone()));
+        }
+
+        match join_set.join_next().await {
+            None => break,
+            Some(Ok(Some(controller))) => found.push(controller),
+            Some(Ok(None)) => {}
+            // A cancelled probe is left out like a host that doesn't answer.
+            Some(Err(error)) => resume_panic(error),
+        }
+    }
+
+    found.sort_by_key(|controller| controller.addr);
+    found
+}
+
+async fn probe(addr: SocketAddr, options: ScanOptions) -> Option<ScannedController> {
+    let stream = tokio::time::timeout(options.connect_timeout, TcpStream::connect(addr)).await.ok()?.ok()?;
+    let (mut client, _) = EXOlineTCPClient::new(stream);
+    client.set_timeout(Some(options.timeout));
+
+    let address = client.read_exoline_address().await.ok()?;
+    let controller_id = client.read_controller_id(address).await.ok();
+    Some(ScannedController {
+        addr,
+        address,
+        controller_id,
+    })
+}
+
+/// Parses a comma separated list of IP addresses and CIDR ranges, like `10.0.0.5,192.168.1.0/24`.
+///
+/// The network and broadcast addresses of IPv4 ranges are left out.
+pub fn parse_hosts(input: &str) -> Result<Vec<IpAddr>, EXOlineError> {
+    let mut hosts = Vec::new();
+    for part in input.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        let Some((ip, prefix)) = part.split_once('/') else {
+            hosts.push(part.parse().map_err(|_| EXOlineError::InvalidArguments("Invalid IP address"))?);
+            continue;
+        };
+        let ip: IpAddr = ip.parse().map_err(|_| EXOlineError::InvalidArguments("Invalid IP address"))?;
+        let prefix: u32 = prefix.parse().map_err(|_| EXOlineError::InvalidArguments("Invalid prefix length"))?;
+
+        let bits = if ip.is_ipv4() { 32 } else { 128 };
+        if prefix > bits {
+            return Err(EXOlineError::InvalidArguments("Invalid prefix length"));
+        }
+        let size = 1u128.checked_shl(bits - prefix).unwrap_or(u128::MAX);
+        if size > MAX_HOSTS {
+            return Err(EXOlineError::InvalidArguments("The range is too large"));
+        }
+
+        match ip {
+            IpAddr::V4(ip) => {
+                let first = u32::from(ip) & !((size - 1) as u32);
+                let last = first + (size - 1) as u32;
+                let range = match size > 2 {
+                    true => first + 1..=last - 1,
+                    false => first..=last,
+                };
+                hosts.extend(range.map(|ip| IpAddr::V4(Ipv4Addr::from(ip))));
+            }
+            IpAddr::V6(ip) => {
+                let first = u128::from(ip) & !(size - 1);
+                hosts.extend((0..size).map(|i| IpAddr::V6(Ipv6Addr::from(first + i))));
+            }
+        }
+    }
+    Ok(hosts)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::VirtualController;
+    use crate::test_util::test_controller;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn scan_range() {
+        let controller = test_controller();
+        let device = VirtualController::new(&controller);
+        device.set_controller_id("EXOcompact C3 4.1-1-08").await;
+        let (addr, _) = device.listen("127.0.0.1:0").await.unwrap();
+
+        let hosts = parse_hosts("127.0.0.0/30").unwrap();
+        assert_eq!(hosts, ["127.0.0.1".parse::<IpAddr>().unwrap(), "127.0.0.2".parse().unwrap()]);
+
+        let options = ScanOptions {
+            port: addr.port(),
+            ..Default::default()
+        };
+        let found = scan(hosts, &options).await;
+        let [controller_found] = &found[..] else {
+            panic!("Expected 1 controller, got {found:?}");
+        };
+        assert_eq!(controller_found.addr, addr);
+        assert_eq!(controller_found.address, controller.address);
+        assert_eq!(controller_found.model(), Some("EXOcompact C3"));
+        assert_eq!(controller_found.firmware(), Some("4.1-1-08"));
+    }
+
+    #[test]
+    fn ranges_at_the_end_of_the_address_space() {
+        let hosts = |input| parse_hosts(input).unwrap().into_iter().map(|ip| ip.to_string()).collect::<Vec<_>>();
+        assert_eq!(hosts("255.255.255.255/32"), ["255.255.255.255"]);
+        assert_eq!(hosts("255.255.255.254/31"), ["255.255.255.254", "255.255.255.255"]);
+        assert_eq!(hosts("255.255.255.252/30"), ["255.255.255.253", "255.255.255.254"]);
+        assert_eq!(hosts("0.0.0.0/32, 10.0.0.7"), ["0.0.0.0", "10.0.0.7"]);
+        assert_eq!(hosts("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff/127").len(), 2);
+        assert!(parse_hosts("10.0.0.0/33").is_err());
+    }
+}
@@ -0,0 +1,285 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+use tokio_serial::{DataBits, SerialPortBuilderExt, SerialStream, StopBits};
+
+use super::internal::consts::END_MESSAGE;
+
+/// The parity bit of a serial port.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+/// Configuration for a [SerialTransport].
+#[derive(Debug, Clone)]
+pub struct SerialOptions {
+    /// Bits per second.
+    pub baud_rate: u32,
+    pub parity: Parity,
+    /// The time the bus must be quiet after a frame has been sent, before the next frame is sent.
+    pub inter_frame_delay: Duration,
+    /// The time to wait after the last byte was received before sending.
+    /// Gives the other device time to turn off its RS-485 driver.
+    pub turnaround_delay: Duration,
+    /// How long to wait for the answer to a frame before the next frame is sent anyway.
+    /// Only one device may talk at a time, so the next frame waits for the end of the answer.
+    pub response_timeout: Duration,
+}
+
+impl Default for SerialOptions {
+    fn default() -> Self {
+        Self {
+            baud_rate: 9600,
+            parity: Parity::None,
+            inter_frame_delay: Duration::from_millis(10),
+            turnaround_delay: Duration::from_millis(5),
+            response_timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+impl SerialOptions {
+    /// The time it takes to send `bytes` bytes with a start bit, 8 data bits, the parity bit and a stop bit.
+    fn transmit_time(&self, bytes: usize) -> Duration {
+        let bits_per_byte = match self.parity {
+            Parity::None => 10,
+            Parity::Odd | Parity::Even => 11,
+        };
+        Duration::from_secs_f64((bytes * bits_per_byte) as f64 / self.baud_rate.max(1) as f64)
+    }
+}
+
+/// A [Transport](super::Transport) for the EXOline serial bus.
+///
+/// Frames are sent the same way as over TCP. What the serial bus adds is the timing:
+/// a frame is only sent once the bus has been quiet for the [inter_frame_delay](SerialOptions::inter_frame_delay)
+/// and the [turnaround_delay](SerialOptions::turnaround_delay).
+/// Only one frame is answered at a time: after a frame has been sent, the next one waits until the answer has been read,
+/// or until the [response_timeout](SerialOptions::response_timeout) has passed.
+///
+/// Use it with [EXOlineTCPClient::new](super::EXOlineTCPClient::new),
+/// or in the `connect` function of [EXOlineTCPClient::new_reconnecting](super::EXOlineTCPClient::new_reconnecting).
+pub struct SerialTransport<S = SerialStream> {
+    stream: S,
+    options: SerialOptions,
+    /// The earliest time the next frame may be sent.
+    quiet_until: Instant,
+    /// A frame has been partially written.
+    in_frame: bool,
+    /// When to stop waiting for the answer to the last frame, if it has not been read yet.
+    response_deadline: Option<Instant>,
+    /// The task waiting to write the next frame, woken when the answer has been read.
+    writer: Option<Waker>,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl SerialTransport<SerialStream> {
+    /// Opens the serial port at `path`, like `/dev/ttyUSB0` or `COM3`.
+    pub fn open(path: &str, options: SerialOptions) -> io::Result<Self> {
+        let parity = match options.parity {
+            Parity::None => tokio_serial::Parity::None,
+            Parity::Odd => tokio_serial::Parity::Odd,
+            Parity::Even => tokio_serial::Parity::Even,
+        };
+        let stream = tokio_serial::new(path, options.baud_rate)
+            .data_bits(DataBits::Eight)
+            .parity(parity)
+            .stop_bits(StopBits::One)
+            .open_native_async()?;
+        Ok(Self::new(stream, options))
+    }
+}
+
+impl<S> SerialTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Applies the timing of `options` to a stream that is already open, like a pseudo terminal.
+    /// The port settings of the stream are left as they are.
+    pub fn new(stream: S, options: SerialOptions) -> Self {
+        Self {
+            stream,
+            options,
+            quiet_until: Instant::now(),
+            in_frame: false,
+            response_deadline: None,
+            writer: None,
+            delay: None,
+        }
+    }
+
+    /// The underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    fn keep_quiet_until(&mut self, instant: Instant) {
+        self.quiet_until = self.quiet_until.max(instant);
+    }
+}
+
+impl<S> AsyncRead for SerialTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.stream).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            if buf.filled().len() > filled {
+                let turnaround = Instant::now() + self.options.turnaround_delay;
+                self.keep_quiet_until(turnaround);
+                if buf.filled()[filled..].contains(&END_MESSAGE) && self.response_deadline.take().is_some() {
+                    if let Some(writer) = self.writer.take() {
+                        writer.wake();
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+impl<S> AsyncWrite for SerialTransport<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if !self.in_frame {
+            let send_at = match self.response_deadline {
+                Some(deadline) => {
+                    // Woken by poll_read when the answer arrives.
+                    self.writer = Some(cx.waker().clone());
+                    self.quiet_until.max(deadline)
+                }
+                None => self.quiet_until,
+            };
+            if Instant::now() < send_at {
+                let delay = self.delay.get_or_insert_with(|| Box::pin(tokio::time::sleep_until(send_at)));
+                delay.as_mut().reset(send_at);
+                if delay.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+            }
+            self.response_deadline = None;
+            self.writer = None;
+        }
+
+        // Stop at the end of the frame, so that the next frame waits for the bus.
+        let end = buf.iter().position(|byte| *byte == END_MESSAGE).map(|i| i + 1).unwrap_or(buf.len());
+        let written = match Pin::new(&mut self.stream).poll_write(cx, &buf[..end]) {
+            Poll::Ready(Ok(written)) => written,
+            other => return other,
+        };
+
+        // The bytes are still being transmitted when the write returns.
+        let sent = Instant::now() + self.options.transmit_time(written);
+        let quiet_until = sent + self.options.inter_frame_delay;
+        self.keep_quiet_until(quiet_until);
+        self.in_frame = true;
+        if written == end && end > 0 && buf[end - 1] == END_MESSAGE {
+            self.in_frame = false;
+            self.response_deadline = Some(sent + self.options.response_timeout);
+        }
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(all(test, unix, feature = "tokio"))]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use crate::client::internal::consts::{BEGIN_REQUEST, BEGIN_RESPONSE};
+    use crate::client::{EXOlineTCPClient, Variant};
+    use crate::emulator::VirtualController;
+    use crate::test_util::test_controller;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn pseudo_terminal() {
+        let controller = test_controller();
+        let (client_side, device_side) = SerialStream::pair().unwrap();
+        let options = SerialOptions {
+            inter_frame_delay: Duration::from_millis(30),
+            response_timeout: Duration::from_secs(5),
+            ..Default::default()
+        };
+
+        let device = VirtualController::new(&controller);
+        tokio::spawn(async move { device.handle_connection(SerialTransport::new(device_side, SerialOptions::default())).await });
+        let (client, _) = EXOlineTCPClient::new(SerialTransport::new(client_side, options));
+
+        let variable = controller.lookup_variable("Test.Integer").unwrap();
+        let start = Instant::now();
+        client.write_variable(controller.address, &variable, &Variant::Integer(7)).await.unwrap();
+        assert_eq!(client.read_variable(controller.address, &variable).await.unwrap(), Variant::Integer(7));
+        // The read waits for the bus after the write, but not for the response timeout, since the answer ends the wait.
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(30), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+    }
+
+    /// Reads the bytes that arrive within `wait`.
+    async fn read_for(stream: &mut SerialStream, wait: Duration) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buffer = [0; 64];
+        while let Ok(Ok(read)) = tokio::time::timeout(wait, stream.read(&mut buffer)).await {
+            data.extend(&buffer[..read]);
+        }
+        data
+    }
+
+    #[tokio::test]
+    async fn one_frame_at_a_time() {
+        let (client_side, mut device_side) = SerialStream::pair().unwrap();
+        let options = SerialOptions {
+            inter_frame_delay: Duration::ZERO,
+            turnaround_delay: Duration::ZERO,
+            response_timeout: Duration::from_millis(300),
+            ..Default::default()
+        };
+        let (mut reader, mut writer) = tokio::io::split(SerialTransport::new(client_side, options));
+        let written = tokio::spawn(async move {
+            for frame in [1, 2, 3] {
+                writer.write_all(&[BEGIN_REQUEST, frame, END_MESSAGE]).await.unwrap();
+                writer.flush().await.unwrap();
+            }
+        });
+        tokio::spawn(async move {
+            let mut buffer = [0; 64];
+            while reader.read(&mut buffer).await.is_ok_and(|read| read > 0) {}
+        });
+
+        // The second frame waits for the answer to the first.
+        assert_eq!(read_for(&mut device_side, Duration::from_millis(100)).await, [BEGIN_REQUEST, 1, END_MESSAGE]);
+        device_side.write_all(&[BEGIN_RESPONSE, 1, END_MESSAGE]).await.unwrap();
+        let start = Instant::now();
+        assert_eq!(read_for(&mut device_side, Duration::from_millis(100)).await, [BEGIN_REQUEST, 2, END_MESSAGE]);
+        assert!(start.elapsed() < Duration::from_millis(300));
+
+        // Without an answer the third frame is sent once the response timeout has passed.
+        let start = Instant::now();
+        let mut buffer = [0; 3];
+        device_side.read_exact(&mut buffer).await.unwrap();
+        assert_eq!(buffer, [BEGIN_REQUEST, 3, END_MESSAGE]);
+        assert!(start.elapsed() >= Duration::from_millis(150), "{:?}", start.elapsed());
+        written.await.unwrap();
+    }
+}
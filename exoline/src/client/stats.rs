@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+/// Statistics of a controller.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControllerStats {
+    /// The size of the memory in bytes.
+    pub total_memory: u32,
+    /// The free memory in bytes.
+    pub free_memory: u32,
+    /// The number of loaded DPac's.
+    pub dpac_count: u8,
+    /// The number of loaded tasks.
+    pub task_count: u8,
+    /// The time since the controller started.
+    pub uptime: Duration,
+}
+
+/// Statistics of a DPac.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DPacStats {
+    /// The size in bytes.
+    pub size: u32,
+    /// The number of pages that can be read.
+    pub page_count: u8,
+}
+
+/// Statistics of a task.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskStats {
+    /// Whether the task is running.
+    pub running: bool,
+    /// The time between the starts of two runs.
+    pub cycle_time: Duration,
+    /// The time the last run took.
+    pub execution_time: Duration,
+    /// The time the longest run took.
+    pub max_execution_time: Duration,
+}
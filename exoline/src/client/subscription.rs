@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+use futures_core::Stream;
+use tokio::{
+    sync::mpsc,
+    task::AbortHandle,
+    time::{interval, MissedTickBehavior},
+};
+
+use crate::controller::Variable;
+
+use super::{EXOlineError, EXOlineTCPClient, Variant};
+
+/// A variable to watch with [EXOlineTCPClient::subscribe].
+#[derive(Clone)]
+pub struct SubscribedVariable {
+    /// The variable to watch.
+    pub variable: Variable,
+    /// Changes of Huge, Integer and Real values that are not larger than this are not reported.
+    pub deadband: Option<f64>,
+}
+
+impl From<Variable> for SubscribedVariable {
+    fn from(variable: Variable) -> Self {
+        Self { variable, deadband: None }
+    }
+}
+
+impl From<&Variable> for SubscribedVariable {
+    fn from(variable: &Variable) -> Self {
+        variable.clone().into()
+    }
+}
+
+impl From<(Variable, f64)> for SubscribedVariable {
+    fn from((variable, deadband): (Variable, f64)) -> Self {
+        Self {
+            variable,
+            deadband: Some(deadband),
+        }
+    }
+}
+
+/// An event reported by a [Subscription].
+#[derive(Debug, Clone)]
+pub enum SubscriptionEvent {
+    /// The value of a variable changed.
+    /// `old` is `None` for the first value that is read, and holds the last reported value otherwise.
+    Changed {
+        variable: Variable,
+        old: Option<Variant>,
+        new: Variant,
+        timestamp: SystemTime,
+    },
+    /// A variable could not be read, and its last reported value is stale.
+    /// Reported once until the variable can be read again.
+    Error {
+        variable: Variable,
+        error: EXOlineError,
+        timestamp: SystemTime,
+    },
+}
+
+/// A stream of [SubscriptionEvent]s, created by [EXOlineTCPClient::subscribe].
+///
+/// Polling stops when the subscription is dropped.
+pub struct Subscription {
+    receiver: mpsc::Receiver<SubscriptionEvent>,
+    abort_handle: AbortHandle,
+}
+
+impl Subscription {
+    pub(crate) fn spawn(client: EXOlineTCPClient, address: (u8, u8), variables: Vec<SubscribedVariable>, period: Duration) -> Self {
+        let (sender, receiver) = mpsc::channel(256);
+        let join_handle = tokio::spawn(Self::run(client, address, variables, period, sender));
+        Self {
+            receiver,
+            abort_handle: join_handle.abort_handle(),
+        }
+    }
+
+    /// Waits for the next event.
+    pub async fn recv(&mut self) -> Option<SubscriptionEvent> {
+        self.receiver.recv().await
+    }
+
+    async fn run(
+        client: EXOlineTCPClient,
+        address: (u8, u8),
+        subscribed: Vec<SubscribedVariable>,
+        period: Duration,
+        sender: mpsc::Sender<SubscriptionEvent>,
+    ) {
+        let variables = subscribed.iter().map(|s| s.variable.clone()).collect::<Vec<_>>();
+        let deadbands = subscribed
+            .into_iter()
+            .filter_map(|s| Some((s.variable, s.deadband?)))
+            .collect::<HashMap<_, _>>();
+
+        let mut last_values: HashMap<Variable, Variant> = HashMap::new();
+        let mut failing: HashSet<Variable> = HashSet::new();
+
+        let mut interval = interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            let values = client.read_many(address, &variables).await;
+            let timestamp = SystemTime::now();
+
+            for (variable, value) in values {
+                let event = match value {
+                    Ok(new) => {
+                        let recovered = failing.remove(&variable);
+                        let old = last_values.get(&variable);
+                        let changed = match old {
+                            None => true,
+                            Some(old) => recovered || exceeds_deadband(old, &new, deadbands.get(&variable).copied()),
+                        };
+                        if !changed {
+                            continue;
+                        }
+                        let old = last_values.insert(variable.clone(), new.clone());
+                        SubscriptionEvent::Changed {
+                            variable,
+                            old,
+                            new,
+                            timestamp,
+                        }
+                    }
+                    Err(error) => {
+                        if !failing.insert(variable.clone()) {
+                            continue;
+                        }
+                        SubscriptionEvent::Error {
+                            variable,
+                            error,
+                            timestamp,
+                        }
+                    }
+                };
+
+                if sender.send(event).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+    }
+}
+
+impl Stream for Subscription {
+    type Item = SubscriptionEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Whether the change from `old` to `new` should be reported.
+fn exceeds_deadband(old: &Variant, new: &Variant, deadband: Option<f64>) -> bool {
+    let difference = match (old, new) {
+        (Variant::Huge(old), Variant::Huge(new)) => (*new as f64 - *old as f64).abs(),
+        (Variant::Integer(old), Variant::Integer(new)) => (*new as f64 - *old as f64).abs(),
+        (Variant::Real(old), Variant::Real(new)) => (*new as f64 - *old as f64).abs(),
+        _ => return old != new,
+    };
+    match deadband {
+        None => old != new,
+        Some(deadband) => difference > deadband,
+    }
+}
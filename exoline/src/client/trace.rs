@@ -0,0 +1,447 @@
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::server::{EXOlineRequest, EXOlineResponse};
+
+use super::internal::encoding::{hex, unescape, verify_and_remove_crc};
+use super::{CommandId, EXOlineException};
+
+/// Receives every frame that an [EXOlineTCPClient](super::EXOlineTCPClient) sends and receives.
+///
+/// Called from the task that owns the connection, so it should return quickly.
+pub trait FrameObserver: Send + Sync + 'static {
+    fn on_frame(&self, frame: &Frame);
+}
+
+/// Whether a frame was sent or received.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameDirection {
+    /// A request sent by the client.
+    Outgoing,
+    /// A response received by the client.
+    Incoming,
+}
+
+/// What was wrong with a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameError {
+    /// The bytes don't form a frame. The client fails the requests in flight and resyncs on the next frame.
+    InvalidData,
+    /// The CRC doesn't match the content.
+    Crc,
+    /// The payload can't be decoded as an answer to the request.
+    Decode,
+    /// A response arrived while no request was waiting for one.
+    Unexpected,
+    /// The response doesn't fit the request it was paired with.
+    /// The client fails the requests in flight and resyncs on the next frame.
+    Mismatch,
+}
+
+/// A frame seen by a [FrameObserver].
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub direction: FrameDirection,
+    pub timestamp: SystemTime,
+    /// The escaped bytes on the wire, without the begin and end markers.
+    pub raw: Vec<u8>,
+    /// The unescaped data without the CRC. For requests the address and command id are not included.
+    /// `None` if the frame could not be unpacked, or is an exception code.
+    pub payload: Option<Vec<u8>>,
+    /// The address of the request. For responses, the address of the request they answer.
+    pub address: Option<(u8, u8)>,
+    /// The command of the request. For responses, the command of the request they answer.
+    pub command_id: Option<CommandId>,
+    /// For responses, the time since the request was written.
+    pub latency: Option<Duration>,
+    /// Exception code sent instead of a response.
+    pub exception: Option<EXOlineException>,
+    pub error: Option<FrameError>,
+}
+
+/// A request as it was written, with the address and command id split off.
+struct UnpackedRequest {
+    address: (u8, u8),
+    command_id: CommandId,
+    payload: Vec<u8>,
+}
+
+fn unpack_request(raw: &[u8]) -> Option<UnpackedRequest> {
+    let data = unescape(raw);
+    let [pla, ela, command_id, payload @ ..] = verify_and_remove_crc(&data)? else {
+        return None;
+    };
+    Some(UnpackedRequest {
+        address: (*pla, *ela),
+        command_id: (*command_id).into(),
+        payload: payload.into(),
+    })
+}
+
+impl Frame {
+    fn new(direction: FrameDirection, raw: &[u8]) -> Self {
+        Self {
+            direction,
+            timestamp: SystemTime::now(),
+            raw: raw.into(),
+            payload: None,
+            address: None,
+            command_id: None,
+            latency: None,
+            exception: None,
+            error: None,
+        }
+    }
+
+    /// A request that has been written.
+    pub(crate) fn outgoing(raw: &[u8]) -> Self {
+        let mut frame = Self::new(FrameDirection::Outgoing, raw);
+        match unpack_request(raw) {
+            None => frame.error = Some(FrameError::Crc),
+            Some(request) => {
+                frame.address = Some(request.address);
+                frame.command_id = Some(request.command_id);
+                frame.payload = Some(request.payload);
+            }
+        }
+        frame
+    }
+
+    /// A response, and the request it answers together with when it was written.
+    pub(crate) fn incoming(raw: &[u8], request: Option<(&[u8], Instant)>) -> Self {
+        let mut frame = Self::new(FrameDirection::Incoming, raw);
+
+        let Some((request, sent)) = request else {
+            frame.error = Some(FrameError::Unexpected);
+            return frame;
+        };
+        frame.latency = Some(sent.elapsed());
+        let request = unpack_request(request);
+        if let Some(request) = &request {
+            frame.address = Some(request.address);
+            frame.command_id = Some(request.command_id);
+        }
+
+        if raw.len() == 1 {
+            frame.exception = Some(raw[0].into());
+            return frame;
+        }
+
+        let data = unescape(raw);
+        let Some(payload) = verify_and_remove_crc(&data) else {
+            frame.error = Some(FrameError::Crc);
+            return frame;
+        };
+        frame.payload = Some(payload.into());
+
+        let decoded = request.and_then(|request| EXOlineRequest::decode(request.command_id, &request.payload).ok());
+        if let Some(request) = decoded {
+            if EXOlineResponse::decode(&request, payload).is_err() {
+                frame.error = Some(FrameError::Decode);
+            }
+        }
+        frame
+    }
+
+    /// A response that doesn't fit the request it was paired with.
+    pub(crate) fn mismatched(raw: &[u8], request: (&[u8], Instant)) -> Self {
+        let mut frame = Self::incoming(raw, Some(request));
+        frame.error = Some(FrameError::Mismatch);
+        frame
+    }
+
+    /// Bytes that could not be read as a frame.
+    pub(crate) fn invalid(raw: &[u8]) -> Self {
+        let mut frame = Self::new(FrameDirection::Incoming, raw);
+        frame.error = Some(FrameError::InvalidData);
+        frame
+    }
+}
+
+/// Writes frames as human readable lines.
+pub struct TextTraceWriter<W> {
+    writer: Mutex<W>,
+}
+
+impl<W> TextTraceWriter<W>
+where
+    W: Write + Send + 'static,
+{
+    pub fn new(writer: W) -> Self {
+        Self { writer: Mutex::new(writer) }
+    }
+}
+
+impl TextTraceWriter<LineWriter<File>> {
+    /// Creates or truncates the file at `path` and writes the trace to it.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(LineWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W> FrameObserver for TextTraceWriter<W>
+where
+    W: Write + Send + 'static,
+{
+    fn on_frame(&self, frame: &Frame) {
+        let mut line = format!("{} ", format_timestamp(frame.timestamp));
+        line += match frame.direction {
+            FrameDirection::Outgoing => ">>",
+            FrameDirection::Incoming => "<<",
+        };
+        if let Some((pla, ela)) = frame.address {
+            line += &format!(" {pla}:{ela}");
+        }
+        if let Some(command_id) = frame.command_id {
+            line += &format!(" {command_id:?}");
+        }
+        if let Some(latency) = frame.latency {
+            line += &format!(" {:.1}ms", latency.as_secs_f64() * 1000.0);
+        }
+        line += &format!(" raw=[{}]", hex(&frame.raw, " "));
+        if let Some(payload) = &frame.payload {
+            line += &format!(" payload=[{}]", hex(payload, " "));
+        }
+        if let Some(exception) = frame.exception {
+            line += &format!(" exception={exception:?}");
+        }
+        if let Some(error) = frame.error {
+            line += &format!(" error={error:?}");
+        }
+
+        _ = writeln!(self.writer.lock().unwrap(), "{line}");
+    }
+}
+
+/// Writes every frame as a JSON object on its own line.
+pub struct JsonLinesTraceWriter<W> {
+    writer: Mutex<W>,
+}
+
+impl<W> JsonLinesTraceWriter<W>
+where
+    W: Write + Send + 'static,
+{
+    pub fn new(writer: W) -> Self {
+        Self { writer: Mutex::new(writer) }
+    }
+}
+
+impl JsonLinesTraceWriter<LineWriter<File>> {
+    /// Creates or truncates the file at `path` and writes the trace to it.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(LineWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W> FrameObserver for JsonLinesTraceWriter<W>
+where
+    W: Write + Send + 'static,
+{
+    fn on_frame(&self, frame: &Frame) {
+        let direction = match frame.direction {
+            FrameDirection::Outgoing => "outgoing",
+            FrameDirection::Incoming => "incoming",
+        };
+        let fields = [
+            ("timestamp", format!("\"{}\"", format_timestamp(frame.timestamp))),
+            ("direction", format!("\"{direction}\"")),
+            ("address", json_or_null(frame.address.map(|(pla, ela)| format!("[{pla},{ela}]")))),
+            ("command", json_or_null(frame.command_id.map(|command_id| format!("\"{command_id:?}\"")))),
+            ("command_id", json_or_null(frame.command_id.map(|command_id| u8::from(command_id).to_string()))),
+            ("latency_ms", json_or_null(frame.latency.map(|latency| (latency.as_secs_f64() * 1000.0).to_string()))),
+            ("raw", format!("\"{}\"", hex(&frame.raw, ""))),
+            ("payload", json_or_null(frame.payload.as_ref().map(|payload| format!("\"{}\"", hex(payload, ""))))),
+            ("exception", json_or_null(frame.exception.map(|exception| format!("\"{exception:?}\"")))),
+            ("error", json_or_null(frame.error.map(|error| format!("\"{error:?}\"")))),
+        ];
+
+        let fields = fields.map(|(name, value)| format!("\"{name}\":{value}"));
+        _ = writeln!(self.writer.lock().unwrap(), "{{{}}}", fields.join(","));
+    }
+}
+
+fn json_or_null(value: Option<String>) -> String {
+    value.unwrap_or_else(|| "null".into())
+}
+
+/// Formats a timestamp as UTC in the ISO 8601 format, with milliseconds.
+fn format_timestamp(timestamp: SystemTime) -> String {
+    let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, seconds_of_day) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // Converts days since 1970-01-01 to a date in the proleptic Gregorian calendar.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::client::Variant;
+    use crate::test_util::{connect_test_controller, Frames};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn observe_frames() {
+        let (controller, _, client) = connect_test_controller().await;
+        let frames = Arc::new(Frames::default());
+        client.set_frame_observer(Some(frames.clone()));
+
+        let variable = controller.lookup_variable("Test.Integer").unwrap();
+        assert_eq!(client.read_variable(controller.address, &variable).await.unwrap(), Variant::Integer(0));
+        _ = client.read_dpac_page_raw(controller.address, variable.file_kind(), 99, 0).await;
+
+        let frames = frames.0.lock().unwrap();
+        let [request, response, _, exception] = &frames[..] else {
+            panic!("Expected 4 frames, got {}", frames.len());
+        };
+        assert_eq!(request.direction, FrameDirection::Outgoing);
+        assert_eq!(request.address, Some(controller.address));
+        assert_eq!(request.command_id, Some(CommandId::ReadInteger));
+        assert_eq!(response.direction, FrameDirection::Incoming);
+        assert_eq!(response.command_id, Some(CommandId::ReadInteger));
+        assert_eq!(response.payload.as_deref(), Some(&[0, 0][..]));
+        assert!(response.latency.is_some());
+        assert_eq!(response.error, None);
+        assert_eq!(exception.exception, Some(EXOlineException::DPacNotPresent));
+    }
+
+    /// A writer that keeps what is written, to read it back.
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Output {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap().lines().map(String::from).collect()
+        }
+    }
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Reads Test.Integer with the observer, and returns the times before and after the read.
+    async fn traced_read(observer: Arc<dyn FrameObserver>) -> (String, String) {
+        let (controller, device, client) = connect_test_controller().await;
+        let variable = controller.lookup_variable("Test.Integer").unwrap();
+        device.write_variable(&variable, &Variant::Integer(0x1234)).await.unwrap();
+        client.set_frame_observer(Some(observer));
+
+        let before = format_timestamp(SystemTime::now());
+        client.read_variable(controller.address, &variable).await.unwrap();
+        (before, format_timestamp(SystemTime::now()))
+    }
+
+    /// Checks that a timestamp is in the ISO 8601 format, and was taken between `before` and `after`.
+    fn assert_timestamp(timestamp: &str, before: &str, after: &str) {
+        assert_eq!(timestamp.len(), "1970-01-01T00:00:00.000Z".len(), "{timestamp}");
+        assert!(timestamp.ends_with('Z'), "{timestamp}");
+        assert!((before..=after).contains(&timestamp), "{timestamp} is not within {before}..={after}");
+    }
+
+    #[tokio::test]
+    async fn text_trace() {
+        let output = Output::default();
+        let (before, after) = traced_read(Arc::new(TextTraceWriter::new(output.clone()))).await;
+
+        let lines = output.lines();
+        let [request, response] = lines.as_slice() else {
+            panic!("Expected a request and a response, got {lines:?}");
+        };
+
+        // The timestamps and the latency change from run to run, so those are taken from the lines.
+        let (timestamp, _) = request.split_once(' ').unwrap();
+        assert_timestamp(timestamp, &before, &after);
+        assert_eq!(
+            request,
+            &format!("{timestamp} >> 2:1 ReadInteger raw=[02 01 57 00 0A 04 00 00 5A] payload=[00 0A 04 00 00]")
+        );
+
+        let (timestamp, rest) = response.split_once(' ').unwrap();
+        assert_timestamp(timestamp, &before, &after);
+        let latency = rest.split(' ').nth(3).unwrap();
+        let (whole, tenths) = latency.strip_suffix("ms").unwrap().split_once('.').unwrap();
+        assert!(whole.parse::<u64>().is_ok() && tenths.len() == 1 && tenths.parse::<u8>().is_ok(), "{latency}");
+        assert_eq!(
+            response,
+            &format!("{timestamp} << 2:1 ReadInteger {latency} raw=[34 12 26] payload=[34 12]")
+        );
+    }
+
+    #[tokio::test]
+    async fn json_lines_trace() {
+        let output = Output::default();
+        let (before, after) = traced_read(Arc::new(JsonLinesTraceWriter::new(output.clone()))).await;
+
+        let lines = output.lines();
+        let [request, response] = lines.as_slice() else {
+            panic!("Expected a request and a response, got {lines:?}");
+        };
+
+        // The value of a field, up to the next comma.
+        fn field<'a>(line: &'a str, name: &str) -> &'a str {
+            let (_, rest) = line.split_once(&format!("\"{name}\":")).unwrap();
+            rest.split(',').next().unwrap()
+        }
+
+        let timestamp = field(request, "timestamp").trim_matches('"');
+        assert_timestamp(timestamp, &before, &after);
+        assert_eq!(
+            request,
+            &format!(
+                "{{\"timestamp\":\"{timestamp}\",\"direction\":\"outgoing\",\"address\":[2,1],\"command\":\"ReadInteger\",\
+                 \"command_id\":87,\"latency_ms\":null,\"raw\":\"020157000A0400005A\",\"payload\":\"000A040000\",\
+                 \"exception\":null,\"error\":null}}"
+            )
+        );
+
+        let timestamp = field(response, "timestamp").trim_matches('"');
+        assert_timestamp(timestamp, &before, &after);
+        let latency = field(response, "latency_ms");
+        assert!(latency.parse::<f64>().unwrap() >= 0.0, "{latency}");
+        assert_eq!(
+            response,
+            &format!(
+                "{{\"timestamp\":\"{timestamp}\",\"direction\":\"incoming\",\"address\":[2,1],\"command\":\"ReadInteger\",\
+                 \"command_id\":87,\"latency_ms\":{latency},\"raw\":\"341226\",\"payload\":\"3412\",\
+                 \"exception\":null,\"error\":null}}"
+            )
+        );
+    }
+
+    #[test]
+    fn timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let timestamp = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(format_timestamp(timestamp), "2024-02-29T12:34:56.789Z");
+    }
+}
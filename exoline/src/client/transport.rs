@@ -0,0 +1,10 @@
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A bidirectional byte stream that EXOline frames are sent over.
+///
+/// Implemented for everything that is both [AsyncRead] and [AsyncWrite],
+/// like a TCP stream, a Unix socket, an in-memory [duplex](tokio::io::duplex), a TLS or SSH tunnel, or a serial port.
+/// The traits are the ones from Tokio, so other runtimes' streams need a compatibility wrapper, and the client still needs a Tokio runtime.
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
+
+impl<T> Transport for T where T: AsyncRead + AsyncWrite + Send + Unpin + 'static {}
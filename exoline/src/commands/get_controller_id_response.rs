@@ -1,6 +1,6 @@
 use std::borrow::Cow;
 
-use super::super::encoding::*;
+use crate::client::internal::encoding::*;
 
 #[derive(PartialEq, Debug)]
 pub struct GetControllerIdResponse<'a> {
@@ -0,0 +1,17 @@
+use crate::client::internal::encoding::*;
+
+/// Reads the memory usage and uptime of the controller.
+#[derive(PartialEq, Debug)]
+pub struct GetControllerStatsRequest;
+
+impl Encodable for GetControllerStatsRequest {
+    fn encode(&self, _encoder: &mut Encoder) -> EncodeResult {
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for GetControllerStatsRequest {
+    fn decode(_decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {})
+    }
+}
@@ -0,0 +1,38 @@
+use crate::client::internal::encoding::*;
+
+/// The answer to a [GetControllerStatsRequest](super::GetControllerStatsRequest).
+///
+/// There is no public description of this response. The layout is the one the [emulator](crate::emulator) uses,
+/// and has not been checked against a real controller.
+#[derive(PartialEq, Debug)]
+pub struct GetControllerStatsResponse {
+    pub total_memory: u32,
+    pub free_memory: u32,
+    pub dpac_count: u8,
+    pub task_count: u8,
+    /// Seconds since the controller started.
+    pub uptime: u32,
+}
+
+impl Encodable for GetControllerStatsResponse {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u32(self.total_memory);
+        encoder.write_u32(self.free_memory);
+        encoder.write_u8(self.dpac_count);
+        encoder.write_u8(self.task_count);
+        encoder.write_u32(self.uptime);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for GetControllerStatsResponse {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            total_memory: decoder.read_u32()?,
+            free_memory: decoder.read_u32()?,
+            dpac_count: decoder.read_u8()?,
+            task_count: decoder.read_u8()?,
+            uptime: decoder.read_u32()?,
+        })
+    }
+}
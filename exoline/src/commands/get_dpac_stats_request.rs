@@ -0,0 +1,23 @@
+use crate::client::internal::encoding::*;
+
+/// Reads the statistics of a DPac.
+#[derive(PartialEq, Debug)]
+pub struct GetDPacStatsRequest {
+    /// The load number of the DPac.
+    pub load_number: u8,
+}
+
+impl Encodable for GetDPacStatsRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for GetDPacStatsRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+        })
+    }
+}
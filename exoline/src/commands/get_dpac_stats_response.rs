@@ -0,0 +1,29 @@
+use crate::client::internal::encoding::*;
+
+/// The answer to a [GetDPacStatsRequest](super::GetDPacStatsRequest).
+///
+/// There is no public description of this response. The layout is the one the [emulator](crate::emulator) uses,
+/// and has not been checked against a real controller.
+#[derive(PartialEq, Debug)]
+pub struct GetDPacStatsResponse {
+    /// The size in bytes.
+    pub size: u32,
+    pub page_count: u8,
+}
+
+impl Encodable for GetDPacStatsResponse {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u32(self.size);
+        encoder.write_u8(self.page_count);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for GetDPacStatsResponse {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            size: decoder.read_u32()?,
+            page_count: decoder.read_u8()?,
+        })
+    }
+}
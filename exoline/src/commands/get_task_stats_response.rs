@@ -0,0 +1,37 @@
+use crate::client::internal::encoding::*;
+
+/// The answer to a [GetTaskStatsRequest](super::GetTaskStatsRequest).
+///
+/// There is no public description of this response. The layout is the one the [emulator](crate::emulator) uses,
+/// and has not been checked against a real controller.
+#[derive(PartialEq, Debug)]
+pub struct GetTaskStatsResponse {
+    pub running: bool,
+    /// Milliseconds between the starts of two runs.
+    pub cycle_time: u16,
+    /// Milliseconds the last run took.
+    pub execution_time: u16,
+    /// Milliseconds the longest run took.
+    pub max_execution_time: u16,
+}
+
+impl Encodable for GetTaskStatsResponse {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.running as u8);
+        encoder.write_u16(self.cycle_time);
+        encoder.write_u16(self.execution_time);
+        encoder.write_u16(self.max_execution_time);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for GetTaskStatsResponse {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            running: decoder.read_u8()? != 0,
+            cycle_time: decoder.read_u16()?,
+            execution_time: decoder.read_u16()?,
+            max_execution_time: decoder.read_u16()?,
+        })
+    }
+}
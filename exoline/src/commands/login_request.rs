@@ -0,0 +1,34 @@
+use std::borrow::Cow;
+use std::fmt::Debug;
+
+use crate::client::internal::encoding::*;
+
+/// Logs in with a password to raise the access level.
+///
+/// The layout is not publicly documented. It is only tested against the emulator.
+#[derive(PartialEq)]
+pub struct LoginRequest<'a> {
+    pub password: Cow<'a, str>,
+}
+
+/// The password is left out, since requests end up in traces and logs.
+impl<'a> Debug for LoginRequest<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoginRequest").finish_non_exhaustive()
+    }
+}
+
+impl<'a> Encodable for LoginRequest<'a> {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_string(&self.password)?;
+        Ok(())
+    }
+}
+
+impl<'a> Decodable<Self> for LoginRequest<'a> {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            password: decoder.read_string()?.into(),
+        })
+    }
+}
@@ -0,0 +1,22 @@
+use crate::client::internal::encoding::*;
+
+#[derive(PartialEq, Debug)]
+pub struct LoginResponse {
+    /// The access level given by the password.
+    pub access_level: u8,
+}
+
+impl Encodable for LoginResponse {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.access_level);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for LoginResponse {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            access_level: decoder.read_u8()?,
+        })
+    }
+}
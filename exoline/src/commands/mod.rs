@@ -0,0 +1,128 @@
+//! The payloads of EXOline commands.
+//!
+//! Requests are decoded into these by the [server](crate::server) and the [protocol](crate::protocol) dissector.
+
+use num_enum::{FromPrimitive, IntoPrimitive};
+
+mod get_controller_id_request;
+mod get_controller_id_response;
+#[cfg(feature = "unstable-stats")]
+mod get_controller_stats_request;
+#[cfg(feature = "unstable-stats")]
+mod get_controller_stats_response;
+#[cfg(feature = "unstable-stats")]
+mod get_dpac_stats_request;
+#[cfg(feature = "unstable-stats")]
+mod get_dpac_stats_response;
+#[cfg(feature = "unstable-stats")]
+mod get_task_stats_request;
+#[cfg(feature = "unstable-stats")]
+mod get_task_stats_response;
+mod login_request;
+mod login_response;
+mod read_bpac_request;
+mod read_dpac_page_request;
+mod read_dpac_page_response;
+mod read_huge_response;
+mod read_index_response;
+mod read_integer_response;
+mod read_logic_response;
+mod read_part_attr_header;
+mod read_real_response;
+mod read_request;
+mod read_string_response;
+mod read_task_page_request;
+mod read_task_request;
+mod read_text_request;
+mod read_vpac_segmented_request;
+mod write_bpac_index_request;
+mod write_bpac_integer_request;
+mod write_bpac_logic_request;
+mod write_bpac_real_request;
+mod write_huge_request;
+mod write_index_request;
+mod write_integer_request;
+mod write_logic_request;
+mod write_real_request;
+mod write_string_request;
+mod write_task_index_request;
+mod write_task_integer_request;
+mod write_task_logic_request;
+mod write_task_real_request;
+mod write_text_request;
+mod write_vpac_index_segmented_request;
+mod write_vpac_integer_segmented_request;
+mod write_vpac_logic_segmented_request;
+mod write_vpac_real_segmented_request;
+mod write_response;
+
+pub(crate) use get_controller_id_request::*;
+pub(crate) use get_controller_id_response::*;
+#[cfg(feature = "unstable-stats")]
+pub(crate) use get_controller_stats_request::*;
+#[cfg(feature = "unstable-stats")]
+pub(crate) use get_controller_stats_response::*;
+#[cfg(feature = "unstable-stats")]
+pub(crate) use get_dpac_stats_request::*;
+#[cfg(feature = "unstable-stats")]
+pub(crate) use get_dpac_stats_response::*;
+#[cfg(feature = "unstable-stats")]
+pub(crate) use get_task_stats_request::*;
+#[cfg(feature = "unstable-stats")]
+pub(crate) use get_task_stats_response::*;
+pub use login_request::*;
+pub(crate) use login_response::*;
+pub use read_bpac_request::*;
+pub use read_dpac_page_request::*;
+pub(crate) use read_dpac_page_response::*;
+pub(crate) use read_huge_response::*;
+pub(crate) use read_index_response::*;
+pub(crate) use read_integer_response::*;
+pub(crate) use read_logic_response::*;
+pub use read_part_attr_header::*;
+pub(crate) use read_real_response::*;
+pub use read_request::*;
+pub(crate) use read_string_response::*;
+pub use read_task_page_request::*;
+pub use read_task_request::*;
+pub use read_text_request::*;
+pub use read_vpac_segmented_request::*;
+pub use write_bpac_index_request::*;
+pub use write_bpac_integer_request::*;
+pub use write_bpac_logic_request::*;
+pub use write_bpac_real_request::*;
+pub use write_huge_request::*;
+pub use write_index_request::*;
+pub use write_integer_request::*;
+pub use write_logic_request::*;
+pub use write_real_request::*;
+pub use write_string_request::*;
+pub use write_task_index_request::*;
+pub use write_task_integer_request::*;
+pub use write_task_logic_request::*;
+pub use write_task_real_request::*;
+pub use write_text_request::*;
+pub use write_vpac_index_segmented_request::*;
+pub use write_vpac_integer_segmented_request::*;
+pub use write_vpac_logic_segmented_request::*;
+pub use write_vpac_real_segmented_request::*;
+pub(crate) use write_response::*;
+
+/// Segmented commands address a VPac in segments of 60 offsets, the same alignment the VPac loader uses.
+pub(crate) const SEGMENT_SIZE: u32 = 60;
+
+/// The offset in the VPac of an offset within a segment.
+pub(crate) fn segment_offset(segment: u8, offset: u8) -> u32 {
+    segment as u32 * SEGMENT_SIZE + offset as u32
+}
+
+/// The kind of file a command is addressing.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, IntoPrimitive, FromPrimitive)]
+pub enum CommandFileKind {
+    VPac = 0x00,
+    Task = 0x01,
+    BPac = 0x02,
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
@@ -0,0 +1,27 @@
+use crate::client::internal::encoding::*;
+
+/// Reads a single value from a BPac with the BPac specific commands.
+#[derive(PartialEq, Debug)]
+pub struct ReadBPacRequest {
+    /// The load number of the BPac.
+    pub load_number: u8,
+    /// The offset of the variable in the BPac.
+    pub offset: u32,
+}
+
+impl Encodable for ReadBPacRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u24(self.offset);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for ReadBPacRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            offset: decoder.read_u24()?,
+        })
+    }
+}
@@ -1,8 +1,11 @@
-use super::super::encoding::*;
+use crate::client::internal::encoding::*;
 
+/// Reads a page of 120 bytes from a DPac.
 #[derive(PartialEq, Debug)]
 pub struct ReadDPacPageRequest {
+    /// The load number of the DPac.
     pub load_number: u8,
+    /// The page to read.
     pub page: u8,
 }
 
@@ -1,4 +1,4 @@
-use super::super::encoding::*;
+use crate::client::internal::encoding::*;
 
 #[derive(PartialEq, Debug)]
 pub struct ReadIntegerResponse {
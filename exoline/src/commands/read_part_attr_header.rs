@@ -1,7 +1,8 @@
 use num_enum::{FromPrimitive, IntoPrimitive};
 
-use super::super::encoding::*;
+use crate::client::internal::encoding::*;
 
+/// The datatype of a partition attribute.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, IntoPrimitive, FromPrimitive)]
 pub enum PartAttrHeaderKind {
@@ -12,10 +13,14 @@ pub enum PartAttrHeaderKind {
     Unknown(u8),
 }
 
+/// Reads an attribute from a partition header.
 #[derive(PartialEq, Debug)]
 pub struct ReadPartAttrHeader {
+    /// The datatype of the attribute.
     pub kind: PartAttrHeaderKind,
+    /// The partition number.
     pub part_no: u8,
+    /// The attribute id.
     pub attr: u16,
 }
 
@@ -1,11 +1,15 @@
-use super::super::encoding::*;
+use crate::client::internal::encoding::*;
 
 use super::CommandFileKind;
 
+/// Reads a single value from a file.
 #[derive(PartialEq, Debug)]
 pub struct ReadRequest {
+    /// The kind of file to read from.
     pub kind: CommandFileKind,
+    /// The load number of the file.
     pub load_number: u8,
+    /// The offset of the variable in the file.
     pub offset: u32,
 }
 
@@ -0,0 +1,27 @@
+use crate::client::internal::encoding::*;
+
+/// Reads a page of 120 bytes from the locals of a task.
+#[derive(PartialEq, Debug)]
+pub struct ReadTaskPageRequest {
+    /// The load number of the task.
+    pub load_number: u8,
+    /// The page to read.
+    pub page: u8,
+}
+
+impl Encodable for ReadTaskPageRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u8(self.page);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for ReadTaskPageRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            page: decoder.read_u8()?,
+        })
+    }
+}
@@ -0,0 +1,27 @@
+use crate::client::internal::encoding::*;
+
+/// Reads a single value from the locals of a task with the task specific commands.
+#[derive(PartialEq, Debug)]
+pub struct ReadTaskRequest {
+    /// The load number of the task.
+    pub load_number: u8,
+    /// The offset of the variable in the task.
+    pub offset: u32,
+}
+
+impl Encodable for ReadTaskRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u24(self.offset);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for ReadTaskRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            offset: decoder.read_u24()?,
+        })
+    }
+}
@@ -0,0 +1,27 @@
+use crate::client::internal::encoding::*;
+
+/// Reads a string from a text file.
+#[derive(PartialEq, Debug)]
+pub struct ReadTextRequest {
+    /// The load number of the text file.
+    pub load_number: u8,
+    /// The offset of the string in the text file.
+    pub offset: u32,
+}
+
+impl Encodable for ReadTextRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u24(self.offset);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for ReadTextRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            offset: decoder.read_u24()?,
+        })
+    }
+}
@@ -0,0 +1,32 @@
+use crate::client::internal::encoding::*;
+
+/// Reads a single value from a VPac, addressed by segment.
+/// A segment is 60 offsets long, which is one page.
+#[derive(PartialEq, Debug)]
+pub struct ReadVPacSegmentedRequest {
+    /// The load number of the VPac.
+    pub load_number: u8,
+    /// The segment the variable is in.
+    pub segment: u8,
+    /// The offset of the variable in the segment.
+    pub offset: u8,
+}
+
+impl Encodable for ReadVPacSegmentedRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u8(self.segment);
+        encoder.write_u8(self.offset);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for ReadVPacSegmentedRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            segment: decoder.read_u8()?,
+            offset: decoder.read_u8()?,
+        })
+    }
+}
@@ -0,0 +1,28 @@
+use crate::client::internal::encoding::*;
+
+/// Writes an [Index](crate::client::Variant::Index) value to a BPac with the BPac specific commands.
+#[derive(PartialEq, Debug)]
+pub struct WriteBPacIndexRequest {
+    pub load_number: u8,
+    pub offset: u32,
+    pub value: u8,
+}
+
+impl Encodable for WriteBPacIndexRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u24(self.offset);
+        encoder.write_u8(self.value);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for WriteBPacIndexRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            offset: decoder.read_u24()?,
+            value: decoder.read_u8()?,
+        })
+    }
+}
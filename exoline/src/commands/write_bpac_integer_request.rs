@@ -0,0 +1,28 @@
+use crate::client::internal::encoding::*;
+
+/// Writes an [Integer](crate::client::Variant::Integer) value to a BPac with the BPac specific commands.
+#[derive(PartialEq, Debug)]
+pub struct WriteBPacIntegerRequest {
+    pub load_number: u8,
+    pub offset: u32,
+    pub value: i16,
+}
+
+impl Encodable for WriteBPacIntegerRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u24(self.offset);
+        encoder.write_i16(self.value);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for WriteBPacIntegerRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            offset: decoder.read_u24()?,
+            value: decoder.read_i16()?,
+        })
+    }
+}
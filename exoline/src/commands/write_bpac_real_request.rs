@@ -0,0 +1,28 @@
+use crate::client::internal::encoding::*;
+
+/// Writes a [Real](crate::client::Variant::Real) value to a BPac with the BPac specific commands.
+#[derive(PartialEq, Debug)]
+pub struct WriteBPacRealRequest {
+    pub load_number: u8,
+    pub offset: u32,
+    pub value: f32,
+}
+
+impl Encodable for WriteBPacRealRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u24(self.offset);
+        encoder.write_f32(self.value);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for WriteBPacRealRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            offset: decoder.read_u24()?,
+            value: decoder.read_f32()?,
+        })
+    }
+}
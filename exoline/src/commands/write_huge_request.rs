@@ -1,7 +1,8 @@
-use super::super::encoding::*;
+use crate::client::internal::encoding::*;
 
 use super::CommandFileKind;
 
+/// Writes a [Huge](crate::client::Variant::Huge) value to a file.
 #[derive(PartialEq, Debug)]
 pub struct WriteHugeRequest {
     pub kind: CommandFileKind,
@@ -1,7 +1,8 @@
-use super::super::encoding::*;
+use crate::client::internal::encoding::*;
 
 use super::CommandFileKind;
 
+/// Writes an [Index](crate::client::Variant::Index) value to a file.
 #[derive(PartialEq, Debug)]
 pub struct WriteIndexRequest {
     pub kind: CommandFileKind,
@@ -1,7 +1,8 @@
-use super::super::encoding::*;
+use crate::client::internal::encoding::*;
 
 use super::CommandFileKind;
 
+/// Writes an [Integer](crate::client::Variant::Integer) value to a file.
 #[derive(PartialEq, Debug)]
 pub struct WriteIntegerRequest {
     pub kind: CommandFileKind,
@@ -1,7 +1,8 @@
-use super::super::encoding::*;
+use crate::client::internal::encoding::*;
 
 use super::CommandFileKind;
 
+/// Writes a [Logic](crate::client::Variant::Logic) value to a file.
 #[derive(PartialEq, Debug)]
 pub struct WriteLogicRequest {
     pub kind: CommandFileKind,
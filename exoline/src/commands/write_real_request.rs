@@ -1,7 +1,8 @@
-use super::super::encoding::*;
+use crate::client::internal::encoding::*;
 
 use super::CommandFileKind;
 
+/// Writes a [Real](crate::client::Variant::Real) value to a file.
 #[derive(PartialEq, Debug)]
 pub struct WriteRealRequest {
     pub kind: CommandFileKind,
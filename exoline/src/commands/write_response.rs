@@ -0,0 +1,19 @@
+use crate::client::internal::encoding::*;
+
+/// The answer to a write. What devices put in it is not documented, so any payload is accepted. One byte is sent,
+/// since a response of a single byte without a CRC is an exception code.
+#[derive(PartialEq, Debug)]
+pub struct WriteResponse;
+
+impl Encodable for WriteResponse {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(0);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for WriteResponse {
+    fn decode(_decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self)
+    }
+}
@@ -1,9 +1,10 @@
 use std::borrow::Cow;
 
-use super::super::encoding::*;
+use crate::client::internal::encoding::*;
 
 use super::CommandFileKind;
 
+/// Writes a [String](crate::client::Variant::String) value to a file.
 #[derive(PartialEq, Debug)]
 pub struct WriteStringRequest<'a> {
     pub kind: CommandFileKind,
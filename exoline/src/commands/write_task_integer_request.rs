@@ -0,0 +1,28 @@
+use crate::client::internal::encoding::*;
+
+/// Writes an [Integer](crate::client::Variant::Integer) value to the locals of a task with the task specific commands.
+#[derive(PartialEq, Debug)]
+pub struct WriteTaskIntegerRequest {
+    pub load_number: u8,
+    pub offset: u32,
+    pub value: i16,
+}
+
+impl Encodable for WriteTaskIntegerRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u24(self.offset);
+        encoder.write_i16(self.value);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for WriteTaskIntegerRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            offset: decoder.read_u24()?,
+            value: decoder.read_i16()?,
+        })
+    }
+}
@@ -0,0 +1,30 @@
+use std::borrow::Cow;
+
+use crate::client::internal::encoding::*;
+
+/// Writes a string to a text file.
+#[derive(PartialEq, Debug)]
+pub struct WriteTextRequest<'a> {
+    pub load_number: u8,
+    pub offset: u32,
+    pub value: Cow<'a, str>,
+}
+
+impl<'a> Encodable for WriteTextRequest<'a> {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u24(self.offset);
+        encoder.write_string(&self.value)?;
+        Ok(())
+    }
+}
+
+impl<'a> Decodable<Self> for WriteTextRequest<'a> {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            offset: decoder.read_u24()?,
+            value: decoder.read_string()?.into(),
+        })
+    }
+}
@@ -0,0 +1,31 @@
+use crate::client::internal::encoding::*;
+
+/// Writes an [Index](crate::client::Variant::Index) value to a VPac, addressed by segment.
+#[derive(PartialEq, Debug)]
+pub struct WriteVPacIndexSegmentedRequest {
+    pub load_number: u8,
+    pub segment: u8,
+    pub offset: u8,
+    pub value: u8,
+}
+
+impl Encodable for WriteVPacIndexSegmentedRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u8(self.segment);
+        encoder.write_u8(self.offset);
+        encoder.write_u8(self.value);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for WriteVPacIndexSegmentedRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            segment: decoder.read_u8()?,
+            offset: decoder.read_u8()?,
+            value: decoder.read_u8()?,
+        })
+    }
+}
@@ -0,0 +1,31 @@
+use crate::client::internal::encoding::*;
+
+/// Writes an [Integer](crate::client::Variant::Integer) value to a VPac, addressed by segment.
+#[derive(PartialEq, Debug)]
+pub struct WriteVPacIntegerSegmentedRequest {
+    pub load_number: u8,
+    pub segment: u8,
+    pub offset: u8,
+    pub value: i16,
+}
+
+impl Encodable for WriteVPacIntegerSegmentedRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u8(self.segment);
+        encoder.write_u8(self.offset);
+        encoder.write_i16(self.value);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for WriteVPacIntegerSegmentedRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            segment: decoder.read_u8()?,
+            offset: decoder.read_u8()?,
+            value: decoder.read_i16()?,
+        })
+    }
+}
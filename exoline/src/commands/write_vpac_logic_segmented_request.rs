@@ -0,0 +1,31 @@
+use crate::client::internal::encoding::*;
+
+/// Writes a [Logic](crate::client::Variant::Logic) value to a VPac, addressed by segment.
+#[derive(PartialEq, Debug)]
+pub struct WriteVPacLogicSegmentedRequest {
+    pub load_number: u8,
+    pub segment: u8,
+    pub offset: u8,
+    pub value: bool,
+}
+
+impl Encodable for WriteVPacLogicSegmentedRequest {
+    fn encode(&self, encoder: &mut Encoder) -> EncodeResult {
+        encoder.write_u8(self.load_number);
+        encoder.write_u8(self.segment);
+        encoder.write_u8(self.offset);
+        encoder.write_u8(self.value as u8);
+        Ok(())
+    }
+}
+
+impl Decodable<Self> for WriteVPacLogicSegmentedRequest {
+    fn decode(decoder: &mut Decoder) -> DecodeResult<Self> {
+        Ok(Self {
+            load_number: decoder.read_u8()?,
+            segment: decoder.read_u8()?,
+            offset: decoder.read_u8()?,
+            value: decoder.read_u8()? != 0,
+        })
+    }
+}
@@ -132,7 +132,11 @@ impl ControllerLoader {
                 continue;
             }
             let path = match self.resolve_filename(&item.filename, controller_dir, Some(module_library_dir.as_path())) {
-                None => continue,
+                None => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(filename = item.filename, "Could not resolve filename");
+                    continue;
+                }
                 Some(path) => path,
             };
             let path = controller_dir.join(path);
@@ -171,7 +175,11 @@ impl ControllerLoader {
             let load_number = match kind {
                 LoadFileKind::Text => 127,
                 _ => match load_mdl_item.load_number.or(file.load_number) {
-                    None => continue,
+                    None => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(path = %path.display(), "File has no load number");
+                        continue;
+                    }
                     Some(load_number) => load_number,
                 },
             };
@@ -267,7 +275,7 @@ impl ControllerLoader {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 enum LoadFileKind {
     Task,
     DPac,
@@ -289,15 +297,66 @@ async fn load_file(cache: Cache, slib_dir: &Option<PathBuf>, kind: LoadFileKind,
 }
 
 async fn load_file_inner(kind: LoadFileKind, path: &Path, mode: LoadMode) -> Option<Arc<FileInternal>> {
-    let content = read_file_cp850(path).await.ok()?;
+    let future = load_and_parse_file(kind, path, mode);
+    #[cfg(feature = "tracing")]
+    let future = tracing::Instrument::instrument(future, tracing::debug_span!("exoline_load_file", path = %path.display(), ?kind));
+    future.await
+}
+
+async fn load_and_parse_file(kind: LoadFileKind, path: &Path, mode: LoadMode) -> Option<Arc<FileInternal>> {
+    let content = read_file_cp850(path).await;
+    #[cfg(feature = "tracing")]
+    let content = content.inspect_err(|error| tracing::warn!(%error, "Could not read file"));
+    let Ok(content) = content else {
+        return None;
+    };
+    #[cfg(feature = "tracing")]
+    let start = std::time::Instant::now();
     let mut hasher = DefaultHasher::new();
     content.hash(&mut hasher);
     let hash = hasher.finish();
-    match kind {
+    let result = match kind {
         LoadFileKind::Task => parse_task_file(&content, mode, hash),
         LoadFileKind::DPac => parse_dpac_file(&content, mode, hash),
         LoadFileKind::Text => parse_text_file(&content, mode, hash),
+    };
+    #[cfg(feature = "tracing")]
+    match &result {
+        Ok(_) => tracing::debug!(parse_duration = ?start.elapsed(), "Parsed file"),
+        Err(error) => tracing::warn!(parse_duration = ?start.elapsed(), %error, "Could not parse file"),
+    }
+    result.ok().map(Arc::new)
+}
+
+/// Builds a controller from file contents instead of reading a controller directory.
+/// Every item is a filename and the content of the file.
+#[cfg(all(test, feature = "tokio"))]
+pub(crate) fn load_from_sources(address: (u8, u8), dpacs: &[(&str, &str)], tasks: &[(&str, &str)], texts: &[(&str, &str)]) -> Controller {
+    let parse = |items: &[(&str, &str)], kind: LoadFileKind| {
+        let mut file_set = HashMap::with_capacity(items.len());
+        for (name, content) in items {
+            let file = match kind {
+                LoadFileKind::Task => parse_task_file(content, LoadMode::WithNames, 0),
+                LoadFileKind::DPac => parse_dpac_file(content, LoadMode::WithNames, 0),
+                LoadFileKind::Text => parse_text_file(content, LoadMode::WithNames, 0),
+            }
+            .unwrap();
+            let load_number = match kind {
+                LoadFileKind::Text => 127,
+                _ => file.load_number.unwrap(),
+            };
+            file_set.insert(Arc::new(UniCase::new(name.to_string())), (load_number, Arc::new(file)));
+        }
+        Arc::new(file_set)
+    };
+
+    Controller {
+        tasks: parse(tasks, LoadFileKind::Task),
+        dpacs: parse(dpacs, LoadFileKind::DPac),
+        texts: parse(texts, LoadFileKind::Text),
+        globals: HashMap::new().into(),
+        address,
+        require_password: false,
+        system_password: None,
     }
-    .ok()
-    .map(Arc::new)
 }
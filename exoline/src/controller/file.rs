@@ -7,7 +7,7 @@ use super::{
     Variable,
 };
 
-#[derive(PartialEq, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum FileKind {
     /// A table of primitive values.
     BPac,
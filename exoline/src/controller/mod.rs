@@ -11,6 +11,8 @@ mod internal;
 mod variable;
 
 pub use controller_impl::Controller;
+#[cfg(all(test, feature = "tokio"))]
+pub(crate) use controller_loader::load_from_sources;
 pub use controller_loader::{ControllerLoader, LoadMode};
 pub use file::{File, FileKind};
 pub use file_set::FileSet;
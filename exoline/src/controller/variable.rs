@@ -1,4 +1,4 @@
-use std::{hash::Hash, sync::Arc};
+use std::{fmt::Debug, hash::Hash, sync::Arc};
 
 use unicase::UniCase;
 
@@ -87,6 +87,20 @@ impl PartialEq for Variable {
 
 impl Eq for Variable {}
 
+/// Needed by [SubscriptionEvent](crate::client::SubscriptionEvent), which holds variables.
+/// Only the identifying fields are shown, not the whole file the variable belongs to.
+impl Debug for Variable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Variable")
+            .field("name", &self.full_name())
+            .field("file_kind", &self.file.kind)
+            .field("load_number", &self.load_number)
+            .field("kind", &self.kind)
+            .field("offset", &self.offset)
+            .finish()
+    }
+}
+
 impl Hash for Variable {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         match self.file.kind {
@@ -0,0 +1,126 @@
+use std::{collections::HashMap, ops::Range};
+
+use crate::client::{EXOlineException, Variant};
+use crate::controller::{File, FileKind, VariableKind};
+
+pub const PAGE_SIZE: usize = 120;
+
+/// Memory image of a file in the virtual controller.
+pub struct Memory {
+    pub kind: FileKind,
+    pub data: Vec<u8>,
+    pub strings: HashMap<u32, String>,
+}
+
+impl Memory {
+    pub fn new(kind: FileKind, pages: usize) -> Self {
+        Self {
+            kind,
+            data: match kind {
+                FileKind::Text => Vec::new(),
+                _ => vec![0; pages * PAGE_SIZE],
+            },
+            strings: HashMap::new(),
+        }
+    }
+
+    pub fn from_file(file: &File) -> Self {
+        let mut size = 0;
+        let mut strings = HashMap::new();
+
+        for variable in file.iter() {
+            let offset = variable.offset() as usize;
+            let end = match file.kind() {
+                FileKind::BPac => offset + variable.kind().page_size_of_bpac_variable() as usize,
+                FileKind::VPac | FileKind::Task => offset * 2 + variable.kind().page_size_of_vpac_variable() as usize,
+                FileKind::Text => 0,
+            };
+            size = usize::max(size, end);
+
+            if variable.kind() == VariableKind::String {
+                strings.insert(variable.offset(), String::new());
+            }
+        }
+
+        let mut memory = Self::new(file.kind(), usize::max(size.div_ceil(PAGE_SIZE), 1));
+        memory.strings = strings;
+        memory
+    }
+
+    pub fn page(&self, page: u8) -> Result<&[u8], EXOlineException> {
+        let start = page as usize * PAGE_SIZE;
+        self.data
+            .get(start..start + PAGE_SIZE)
+            .ok_or(EXOlineException::AddressOutsideRange)
+    }
+
+    pub fn read(&self, kind: VariableKind, offset: u32) -> Result<Variant, EXOlineException> {
+        if kind == VariableKind::String {
+            self.check_string(offset)?;
+            return Ok(Variant::String(self.strings.get(&offset).cloned().unwrap_or_default()));
+        }
+
+        let bytes = &self.data[self.value_range(kind, offset)?];
+
+        let variant = match kind {
+            VariableKind::Huge => Variant::Huge(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            VariableKind::Index => Variant::Index(bytes[0]),
+            VariableKind::Integer => Variant::Integer(i16::from_le_bytes([bytes[0], bytes[1]])),
+            VariableKind::Logic => Variant::Logic(bytes[0] != 0),
+            VariableKind::Real => Variant::Real(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            VariableKind::String => unreachable!(),
+        };
+
+        Ok(variant)
+    }
+
+    pub fn write(&mut self, offset: u32, value: &Variant) -> Result<(), EXOlineException> {
+        let (kind, bytes) = match value {
+            Variant::Huge(value) => (VariableKind::Huge, value.to_le_bytes().to_vec()),
+            Variant::Index(value) => (VariableKind::Index, vec![*value]),
+            Variant::Integer(value) => (VariableKind::Integer, value.to_le_bytes().to_vec()),
+            Variant::Logic(value) => (VariableKind::Logic, vec![*value as u8]),
+            Variant::Real(value) => (VariableKind::Real, value.to_le_bytes().to_vec()),
+            Variant::String(value) => {
+                self.check_string(offset)?;
+                self.strings.insert(offset, value.clone());
+                return Ok(());
+            }
+        };
+
+        let range = self.value_range(kind, offset)?;
+        self.data[range].copy_from_slice(&bytes);
+
+        Ok(())
+    }
+
+    fn value_range(&self, kind: VariableKind, offset: u32) -> Result<Range<usize>, EXOlineException> {
+        let start = match self.kind {
+            FileKind::BPac => offset as usize,
+            // The first byte of every slot is not part of the value.
+            FileKind::VPac | FileKind::Task => offset as usize * 2 + 1,
+            FileKind::Text => return Err(EXOlineException::WrongType),
+        };
+        let end = start + kind.page_size_of_bpac_variable() as usize;
+
+        if end > self.data.len() {
+            return Err(EXOlineException::AddressOutsideRange);
+        }
+
+        Ok(start..end)
+    }
+
+    fn check_string(&self, offset: u32) -> Result<(), EXOlineException> {
+        match self.kind {
+            FileKind::BPac => Err(EXOlineException::WrongType),
+            FileKind::Text => match self.strings.contains_key(&offset) {
+                true => Ok(()),
+                false => Err(EXOlineException::AddressOutsideRange),
+            },
+            FileKind::VPac | FileKind::Task => match (offset as usize * 2) < self.data.len() {
+                true => Ok(()),
+                false => Err(EXOlineException::AddressOutsideRange),
+            },
+        }
+    }
+}
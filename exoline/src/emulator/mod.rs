@@ -0,0 +1,9 @@
+//! Virtual EXOline controller.
+//! Answers requests like a device, without any hardware.
+//!
+//! Start with the [VirtualController].
+
+mod internal;
+mod virtual_controller;
+
+pub use virtual_controller::VirtualController;
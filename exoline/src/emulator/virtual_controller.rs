@@ -0,0 +1,501 @@
+#[cfg(feature = "unstable-stats")]
+use std::time::{Duration, Instant};
+use std::{collections::HashMap, sync::Arc};
+#[cfg(feature = "tokio")]
+use std::net::SocketAddr;
+
+use tokio::sync::Mutex;
+#[cfg(feature = "tokio")]
+use tokio::{
+    net::{TcpListener, ToSocketAddrs},
+    task::JoinHandle,
+};
+
+use crate::client::{EXOlineException, Transport, Variant};
+#[cfg(feature = "unstable-stats")]
+use crate::client::{ControllerStats, DPacStats, TaskStats};
+use crate::commands::*;
+use crate::controller::{Controller, FileKind, Variable, VariableKind};
+use crate::server::*;
+
+use super::internal::memory::Memory;
+#[cfg(feature = "unstable-stats")]
+use super::internal::memory::PAGE_SIZE;
+
+/// Address that is answered by any controller.
+const LOCAL_ADDRESS: (u8, u8) = (255, 30);
+/// Load number of QSystem.
+const Q_SYSTEM: u8 = 241;
+/// Load number used by text files.
+const TEXT_LOAD_NUMBER: u8 = 127;
+/// Memory size reported in the controller statistics.
+#[cfg(feature = "unstable-stats")]
+const MEMORY_SIZE: u32 = 512 * 1024;
+/// Access level given by the correct password.
+const SYSTEM_ACCESS_LEVEL: u8 = 3;
+
+/// A virtual EXOline device.
+///
+/// Keeps memory images for every file in a [Controller] and answers requests
+/// from an [EXOlineTCPClient](crate::client::EXOlineTCPClient) like a real device would.
+/// Implements [EXOlineRequestHandler] so it can be used with an [EXOlineTCPServer].
+#[derive(Clone)]
+pub struct VirtualController {
+    address: (u8, u8),
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    controller_id: String,
+    dpacs: HashMap<u8, Memory>,
+    tasks: HashMap<u8, Memory>,
+    texts: Memory,
+    partition_attributes: HashMap<(u8, u16), Variant>,
+    /// Kinds of files where the generic read and write commands are rejected.
+    reject_generic_commands: Vec<CommandFileKind>,
+    #[cfg(feature = "unstable-stats")]
+    task_stats: HashMap<u8, TaskStats>,
+    #[cfg(feature = "unstable-stats")]
+    started: Instant,
+    /// Requests fail with [NoAccess](EXOlineException::NoAccess) until a client has logged in with this password.
+    /// There is one login for all connections.
+    password: Option<String>,
+    logged_in: bool,
+}
+
+impl VirtualController {
+    /// Creates a new virtual controller with all variables set to zero.
+    ///
+    /// If `controller` requires a password, the system password is needed to log in.
+    pub fn new(controller: &Controller) -> Self {
+        let mut dpacs = HashMap::with_capacity(controller.dpacs().len());
+        for file in controller.dpacs().iter() {
+            dpacs.insert(file.load_number(), Memory::from_file(&file));
+        }
+
+        let mut tasks = HashMap::with_capacity(controller.tasks().len());
+        for file in controller.tasks().iter() {
+            tasks.insert(file.load_number(), Memory::from_file(&file));
+        }
+
+        let mut texts = Memory::new(FileKind::Text, 0);
+        for file in controller.texts().iter() {
+            texts.strings.extend(Memory::from_file(&file).strings);
+        }
+
+        // QSystem.PLA and QSystem.ELA are needed for address discovery.
+        let q_system = dpacs.entry(Q_SYSTEM).or_insert_with(|| Memory::new(FileKind::VPac, 1));
+        _ = q_system.write(0, &Variant::Index(controller.address.0));
+        _ = q_system.write(1, &Variant::Index(controller.address.1));
+
+        let state = State {
+            controller_id: "Virtual controller".into(),
+            dpacs,
+            tasks,
+            texts,
+            partition_attributes: HashMap::new(),
+            reject_generic_commands: Vec::new(),
+            #[cfg(feature = "unstable-stats")]
+            task_stats: HashMap::new(),
+            #[cfg(feature = "unstable-stats")]
+            started: Instant::now(),
+            password: match controller.require_password {
+                true => Some(controller.system_password.clone().unwrap_or_default()),
+                false => None,
+            },
+            logged_in: false,
+        };
+
+        Self {
+            address: controller.address,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// The EXOline address the controller answers to. (PLA, ELA)
+    pub fn address(&self) -> (u8, u8) {
+        self.address
+    }
+
+    /// Sets the string returned when the controller id is requested.
+    pub async fn set_controller_id(&self, id: &str) {
+        self.state.lock().await.controller_id = id.into();
+    }
+
+    /// Sets the value returned for a partition attribute.
+    pub async fn set_partition_attribute(&self, partition: u8, attribute_id: u16, value: Variant) {
+        self.state.lock().await.partition_attributes.insert((partition, attribute_id), value);
+    }
+
+    /// Sets the statistics returned for a task.
+    #[cfg(feature = "unstable-stats")]
+    pub async fn set_task_stats(&self, load_number: u8, stats: TaskStats) {
+        self.state.lock().await.task_stats.insert(load_number, stats);
+    }
+
+    /// Sets the password needed to log in. `None` gives access without logging in.
+    /// Clients that have already logged in have to log in again.
+    pub async fn set_password(&self, password: Option<&str>) {
+        let mut state = self.state.lock().await;
+        state.password = password.map(Into::into);
+        state.logged_in = false;
+    }
+
+    /// Makes the generic read and write commands fail with [IllegalCommand](EXOlineException::IllegalCommand) for BPac's or tasks,
+    /// like older firmware that only supports the file specific commands.
+    pub async fn set_reject_generic_commands(&self, file_kind: FileKind, reject: bool) {
+        let kind = command_file_kind(file_kind);
+        let mut state = self.state.lock().await;
+        state.reject_generic_commands.retain(|k| *k != kind);
+        if reject {
+            state.reject_generic_commands.push(kind);
+        }
+    }
+
+    /// Reads the current value of a variable.
+    pub async fn read_variable(&self, variable: &Variable) -> Result<Variant, EXOlineException> {
+        let mut state = self.state.lock().await;
+        let memory = state.memory(command_file_kind(variable.file_kind()), variable.load_number())?;
+        memory.read(variable.kind(), variable.offset())
+    }
+
+    /// Changes the value of a variable.
+    pub async fn write_variable(&self, variable: &Variable, value: &Variant) -> Result<(), EXOlineException> {
+        let mut state = self.state.lock().await;
+        let memory = state.memory(command_file_kind(variable.file_kind()), variable.load_number())?;
+        memory.write(variable.offset(), value)
+    }
+
+    /// Binds to `addr` and serves every incoming connection in the background.
+    /// Returns the address that was bound, which is useful when binding to port 0.
+    #[cfg(feature = "tokio")]
+    pub async fn listen<A>(&self, addr: A) -> std::io::Result<(SocketAddr, JoinHandle<std::io::Result<()>>)>
+    where
+        A: ToSocketAddrs,
+    {
+        EXOlineTCPServer::new(self.clone()).listen(addr).await
+    }
+
+    /// Accepts connections from `listener` until an error occurs.
+    #[cfg(feature = "tokio")]
+    pub async fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+        EXOlineTCPServer::new(self).serve(listener).await
+    }
+
+    /// Answers requests on a single connection until it is closed.
+    pub async fn handle_connection(&self, stream: impl Transport) -> std::io::Result<()> {
+        EXOlineTCPServer::new(self.clone()).handle_connection(stream).await
+    }
+}
+
+impl EXOlineRequestHandler for VirtualController {
+    fn accepts_address(&self, address: (u8, u8)) -> bool {
+        address == self.address || address == LOCAL_ADDRESS
+    }
+
+    async fn handle(&self, _address: (u8, u8), request: EXOlineRequest) -> Result<EXOlineResponse, EXOlineException> {
+        self.state.lock().await.handle_request(request)
+    }
+}
+
+impl State {
+    fn handle_request(&mut self, request: EXOlineRequest) -> Result<EXOlineResponse, EXOlineException> {
+        if self.password.is_some() && !self.logged_in && requires_access(&request) {
+            return Err(EXOlineException::NoAccess);
+        }
+
+        match request {
+            EXOlineRequest::GetControllerId => Ok(EXOlineResponse::Value(Variant::String(self.controller_id.clone()))),
+            #[cfg(feature = "unstable-stats")]
+            EXOlineRequest::GetControllerStats => {
+                let used_memory: usize = self.dpacs.values().chain(self.tasks.values()).map(|memory| memory.data.len()).sum();
+                Ok(EXOlineResponse::ControllerStats(ControllerStats {
+                    total_memory: MEMORY_SIZE,
+                    free_memory: MEMORY_SIZE.saturating_sub(used_memory as u32),
+                    dpac_count: self.dpacs.len() as u8,
+                    task_count: self.tasks.len() as u8,
+                    uptime: self.started.elapsed(),
+                }))
+            }
+            #[cfg(feature = "unstable-stats")]
+            EXOlineRequest::GetDPacStats(request) => {
+                let memory = self.dpacs.get(&request.load_number).ok_or(EXOlineException::DPacNotPresent)?;
+                Ok(EXOlineResponse::DPacStats(DPacStats {
+                    size: memory.data.len() as u32,
+                    page_count: memory.data.len().div_ceil(PAGE_SIZE) as u8,
+                }))
+            }
+            #[cfg(feature = "unstable-stats")]
+            EXOlineRequest::GetTaskStats(request) => {
+                if !self.tasks.contains_key(&request.load_number) {
+                    return Err(EXOlineException::TaskNotPresent);
+                }
+                let stats = self.task_stats.get(&request.load_number).cloned().unwrap_or(TaskStats {
+                    running: true,
+                    cycle_time: Duration::from_secs(1),
+                    execution_time: Duration::ZERO,
+                    max_execution_time: Duration::ZERO,
+                });
+                Ok(EXOlineResponse::TaskStats(stats))
+            }
+            EXOlineRequest::Login(request) => match &self.password {
+                Some(password) if *password != request.password => {
+                    self.logged_in = false;
+                    Err(EXOlineException::WrongKey)
+                }
+                _ => {
+                    self.logged_in = true;
+                    Ok(EXOlineResponse::AccessLevel(SYSTEM_ACCESS_LEVEL))
+                }
+            },
+            EXOlineRequest::ReadPartAttrHeader(request) => {
+                let value = self.partition_attributes.get(&(request.part_no, request.attr)).cloned();
+                let value = match (request.kind, value) {
+                    (PartAttrHeaderKind::Huge, None) => Variant::Huge(0),
+                    (PartAttrHeaderKind::Real, None) => Variant::Real(0.0),
+                    (PartAttrHeaderKind::String, None) => Variant::String(String::new()),
+                    (PartAttrHeaderKind::Huge, Some(value @ Variant::Huge(_)))
+                    | (PartAttrHeaderKind::Real, Some(value @ Variant::Real(_)))
+                    | (PartAttrHeaderKind::String, Some(value @ Variant::String(_))) => value,
+                    (PartAttrHeaderKind::Unknown(_), _) => return Err(EXOlineException::ParamIllegal),
+                    _ => return Err(EXOlineException::WrongType),
+                };
+                Ok(EXOlineResponse::Value(value))
+            }
+            EXOlineRequest::ReadDPacPage(request) => {
+                let memory = self.dpacs.get(&request.load_number).ok_or(EXOlineException::DPacNotPresent)?;
+                Ok(EXOlineResponse::DPacPage(memory.page(request.page)?.to_vec()))
+            }
+            EXOlineRequest::ReadHuge(request) => self.read(request, VariableKind::Huge),
+            EXOlineRequest::ReadIndex(request) => self.read(request, VariableKind::Index),
+            EXOlineRequest::ReadInteger(request) => self.read(request, VariableKind::Integer),
+            EXOlineRequest::ReadLogic(request) => self.read(request, VariableKind::Logic),
+            EXOlineRequest::ReadReal(request) => self.read(request, VariableKind::Real),
+            EXOlineRequest::ReadString(request) => self.read(request, VariableKind::String),
+            EXOlineRequest::ReadVPacIndexSegmented(request) => self.read_segmented(request, VariableKind::Index),
+            EXOlineRequest::ReadVPacIntegerSegmented(request) => self.read_segmented(request, VariableKind::Integer),
+            EXOlineRequest::ReadVPacLogicSegmented(request) => self.read_segmented(request, VariableKind::Logic),
+            EXOlineRequest::ReadVPacRealSegmented(request) => self.read_segmented(request, VariableKind::Real),
+            EXOlineRequest::ReadBPacIndex(request) => self.read_bpac(request, VariableKind::Index),
+            EXOlineRequest::ReadBPacInteger(request) => self.read_bpac(request, VariableKind::Integer),
+            EXOlineRequest::ReadBPacLogic(request) => self.read_bpac(request, VariableKind::Logic),
+            EXOlineRequest::ReadBPacReal(request) => self.read_bpac(request, VariableKind::Real),
+            EXOlineRequest::ReadTaskPage(request) => {
+                let memory = self.tasks.get(&request.load_number).ok_or(EXOlineException::TaskNotPresent)?;
+                Ok(EXOlineResponse::DPacPage(memory.page(request.page)?.to_vec()))
+            }
+            EXOlineRequest::ReadTaskIndex(request) => self.read_task(request, VariableKind::Index),
+            EXOlineRequest::ReadTaskInteger(request) => self.read_task(request, VariableKind::Integer),
+            EXOlineRequest::ReadTaskLogic(request) => self.read_task(request, VariableKind::Logic),
+            EXOlineRequest::ReadTaskReal(request) => self.read_task(request, VariableKind::Real),
+            EXOlineRequest::ReadText(request) => {
+                let value = self.text_memory(request.load_number)?.read(VariableKind::String, request.offset)?;
+                Ok(EXOlineResponse::Value(value))
+            }
+            EXOlineRequest::WriteHuge(request) => {
+                self.write(request.kind, request.load_number, request.offset, Variant::Huge(request.value))
+            }
+            EXOlineRequest::WriteIndex(request) => {
+                self.write(request.kind, request.load_number, request.offset, Variant::Index(request.value))
+            }
+            EXOlineRequest::WriteInteger(request) => {
+                self.write(request.kind, request.load_number, request.offset, Variant::Integer(request.value))
+            }
+            EXOlineRequest::WriteLogic(request) => {
+                self.write(request.kind, request.load_number, request.offset, Variant::Logic(request.value))
+            }
+            EXOlineRequest::WriteReal(request) => {
+                self.write(request.kind, request.load_number, request.offset, Variant::Real(request.value))
+            }
+            EXOlineRequest::WriteString(request) => {
+                self.write(request.kind, request.load_number, request.offset, Variant::String(request.value.into_owned()))
+            }
+            EXOlineRequest::WriteVPacIndexSegmented(request) => {
+                self.write_segmented(request.load_number, request.segment, request.offset, Variant::Index(request.value))
+            }
+            EXOlineRequest::WriteVPacIntegerSegmented(request) => {
+                self.write_segmented(request.load_number, request.segment, request.offset, Variant::Integer(request.value))
+            }
+            EXOlineRequest::WriteVPacLogicSegmented(request) => {
+                self.write_segmented(request.load_number, request.segment, request.offset, Variant::Logic(request.value))
+            }
+            EXOlineRequest::WriteVPacRealSegmented(request) => {
+                self.write_segmented(request.load_number, request.segment, request.offset, Variant::Real(request.value))
+            }
+            EXOlineRequest::WriteBPacIndex(request) => self.write_bpac(request.load_number, request.offset, Variant::Index(request.value)),
+            EXOlineRequest::WriteBPacInteger(request) => {
+                self.write_bpac(request.load_number, request.offset, Variant::Integer(request.value))
+            }
+            EXOlineRequest::WriteBPacLogic(request) => self.write_bpac(request.load_number, request.offset, Variant::Logic(request.value)),
+            EXOlineRequest::WriteBPacReal(request) => self.write_bpac(request.load_number, request.offset, Variant::Real(request.value)),
+            EXOlineRequest::WriteTaskIndex(request) => self.write_task(request.load_number, request.offset, Variant::Index(request.value)),
+            EXOlineRequest::WriteTaskInteger(request) => {
+                self.write_task(request.load_number, request.offset, Variant::Integer(request.value))
+            }
+            EXOlineRequest::WriteTaskLogic(request) => self.write_task(request.load_number, request.offset, Variant::Logic(request.value)),
+            EXOlineRequest::WriteTaskReal(request) => self.write_task(request.load_number, request.offset, Variant::Real(request.value)),
+            EXOlineRequest::WriteText(request) => {
+                self.text_memory(request.load_number)?.write(request.offset, &Variant::String(request.value.into_owned()))?;
+                Ok(EXOlineResponse::Ack)
+            }
+            EXOlineRequest::Unknown(_, _) => Err(EXOlineException::IllegalCommand),
+        }
+    }
+
+    fn read(&mut self, request: ReadRequest, variable_kind: VariableKind) -> Result<EXOlineResponse, EXOlineException> {
+        self.check_generic_command(request.kind)?;
+        let value = self.memory(request.kind, request.load_number)?.read(variable_kind, request.offset)?;
+        Ok(EXOlineResponse::Value(value))
+    }
+
+    fn write(&mut self, kind: CommandFileKind, load_number: u8, offset: u32, value: Variant) -> Result<EXOlineResponse, EXOlineException> {
+        self.check_generic_command(kind)?;
+        self.memory(kind, load_number)?.write(offset, &value)?;
+        Ok(EXOlineResponse::Ack)
+    }
+
+    fn check_generic_command(&self, kind: CommandFileKind) -> Result<(), EXOlineException> {
+        match self.reject_generic_commands.contains(&kind) {
+            true => Err(EXOlineException::IllegalCommand),
+            false => Ok(()),
+        }
+    }
+
+    fn read_bpac(&mut self, request: ReadBPacRequest, variable_kind: VariableKind) -> Result<EXOlineResponse, EXOlineException> {
+        let value = self.memory(CommandFileKind::BPac, request.load_number)?.read(variable_kind, request.offset)?;
+        Ok(EXOlineResponse::Value(value))
+    }
+
+    fn write_bpac(&mut self, load_number: u8, offset: u32, value: Variant) -> Result<EXOlineResponse, EXOlineException> {
+        self.memory(CommandFileKind::BPac, load_number)?.write(offset, &value)?;
+        Ok(EXOlineResponse::Ack)
+    }
+
+    fn read_task(&mut self, request: ReadTaskRequest, variable_kind: VariableKind) -> Result<EXOlineResponse, EXOlineException> {
+        let value = self.memory(CommandFileKind::Task, request.load_number)?.read(variable_kind, request.offset)?;
+        Ok(EXOlineResponse::Value(value))
+    }
+
+    fn write_task(&mut self, load_number: u8, offset: u32, value: Variant) -> Result<EXOlineResponse, EXOlineException> {
+        self.memory(CommandFileKind::Task, load_number)?.write(offset, &value)?;
+        Ok(EXOlineResponse::Ack)
+    }
+
+    fn read_segmented(&mut self, request: ReadVPacSegmentedRequest, variable_kind: VariableKind) -> Result<EXOlineResponse, EXOlineException> {
+        let offset = segmented_offset(request.segment, request.offset)?;
+        let value = self.memory(CommandFileKind::VPac, request.load_number)?.read(variable_kind, offset)?;
+        Ok(EXOlineResponse::Value(value))
+    }
+
+    fn write_segmented(&mut self, load_number: u8, segment: u8, offset: u8, value: Variant) -> Result<EXOlineResponse, EXOlineException> {
+        self.write(CommandFileKind::VPac, load_number, segmented_offset(segment, offset)?, value)
+    }
+
+    fn text_memory(&mut self, load_number: u8) -> Result<&mut Memory, EXOlineException> {
+        match load_number {
+            TEXT_LOAD_NUMBER => Ok(&mut self.texts),
+            _ => Err(EXOlineException::ParamIllegal),
+        }
+    }
+
+    fn memory(&mut self, kind: CommandFileKind, load_number: u8) -> Result<&mut Memory, EXOlineException> {
+        match kind {
+            CommandFileKind::VPac if load_number == TEXT_LOAD_NUMBER => Ok(&mut self.texts),
+            CommandFileKind::VPac | CommandFileKind::BPac => {
+                let memory = self.dpacs.get_mut(&load_number).ok_or(EXOlineException::DPacNotPresent)?;
+                match (kind, memory.kind) {
+                    (CommandFileKind::VPac, FileKind::VPac) | (CommandFileKind::BPac, FileKind::BPac) => Ok(memory),
+                    _ => Err(EXOlineException::WrongType),
+                }
+            }
+            CommandFileKind::Task => self.tasks.get_mut(&load_number).ok_or(EXOlineException::TaskNotPresent),
+            CommandFileKind::Unknown(_) => Err(EXOlineException::ParamIllegal),
+        }
+    }
+}
+
+/// The offset in the VPac, if the offset is within its segment.
+fn segmented_offset(segment: u8, offset: u8) -> Result<u32, EXOlineException> {
+    match (offset as u32) < SEGMENT_SIZE {
+        true => Ok(segment_offset(segment, offset)),
+        false => Err(EXOlineException::AddressOutsideRange),
+    }
+}
+
+/// Whether a request needs a login when the controller has a password.
+/// QSystem can always be read, so that the address can be found.
+fn requires_access(request: &EXOlineRequest) -> bool {
+    match request {
+        EXOlineRequest::GetControllerId | EXOlineRequest::Login(_) => false,
+        EXOlineRequest::ReadIndex(request) => request.load_number != Q_SYSTEM,
+        _ => true,
+    }
+}
+
+fn command_file_kind(file_kind: FileKind) -> CommandFileKind {
+    match file_kind {
+        FileKind::BPac => CommandFileKind::BPac,
+        FileKind::Task => CommandFileKind::Task,
+        FileKind::Text | FileKind::VPac => CommandFileKind::VPac,
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use crate::client::EXOlineError;
+    use crate::test_util::connect_test_controller;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn read_write_variables() {
+        let (controller, device, client) = connect_test_controller().await;
+
+        let values = [
+            ("Test.Huge", Variant::Huge(-123456)),
+            ("Test.Index", Variant::Index(42)),
+            ("Test.Integer", Variant::Integer(-1234)),
+            ("Test.Logic", Variant::Logic(true)),
+            ("Test.Real", Variant::Real(21.5)),
+            ("Test.Text", Variant::String("Hello".into())),
+            ("Table.Records(1).Value", Variant::Real(1.5)),
+            ("Program.Counter", Variant::Integer(7)),
+        ];
+
+        for (name, value) in values.iter() {
+            let variable = controller.lookup_variable(name).unwrap();
+            client.write_variable(controller.address, &variable, value).await.unwrap();
+            assert_eq!(&client.read_variable(controller.address, &variable).await.unwrap(), value);
+            assert_eq!(&device.read_variable(&variable).await.unwrap(), value);
+        }
+
+        let file = controller.dpacs().get("Test").unwrap();
+        let result = client.read_dpac(controller.address, &file).await.unwrap();
+        for (name, value) in values[..5].iter() {
+            assert_eq!(result.get(&controller.lookup_variable(name).unwrap()), Some(value));
+        }
+    }
+
+    #[tokio::test]
+    async fn exceptions() {
+        let (controller, _, client) = connect_test_controller().await;
+
+        let result = client.read_dpac_page_raw(controller.address, FileKind::VPac, 99, 0).await;
+        assert!(matches!(result, Err(EXOlineError::ExolineException(EXOlineException::DPacNotPresent))));
+
+        let result = client.read_dpac_page_raw(controller.address, FileKind::VPac, 10, 3).await;
+        assert!(matches!(result, Err(EXOlineError::ExolineException(EXOlineException::AddressOutsideRange))));
+
+        let result = client.read_variable_raw(controller.address, FileKind::VPac, 10, VariableKind::Real, 1000).await;
+        assert!(matches!(result, Err(EXOlineError::ExolineException(EXOlineException::AddressOutsideRange))));
+    }
+
+    #[tokio::test]
+    async fn address_and_id() {
+        let (controller, device, client) = connect_test_controller().await;
+        device.set_controller_id("EXOcompact").await;
+
+        assert_eq!(client.read_exoline_address().await.unwrap(), controller.address);
+        assert_eq!(client.read_controller_id(controller.address).await.unwrap(), "EXOcompact");
+    }
+}
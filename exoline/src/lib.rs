@@ -1,2 +1,21 @@
+//! EXOline client, server, virtual controller and protocol tools.
+//!
+//! The crate is built on Tokio and needs a Tokio runtime, with or without the default `tokio` feature.
+//! The feature only adds the TCP connections and listeners.
+//!
+//! Features:
+//! - `tokio` (default): TCP connections, listeners and the network scan.
+//! - `serial`: the EXOline serial bus.
+//! - `metrics`: client metrics forwarded to the metrics crate.
+//! - `tracing`: spans and events for requests and loaded files.
+//! - `unstable-stats`: controller, DPac and task statistics. Their response layout is not publicly documented and may change.
+
 pub mod client;
+pub mod commands;
 pub mod controller;
+pub mod emulator;
+pub mod protocol;
+pub mod server;
+
+#[cfg(all(test, feature = "tokio"))]
+mod test_util;
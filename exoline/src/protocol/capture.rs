@@ -0,0 +1,493 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::client::{EXOlineException, Variant, EXOLINE_TCP_PORT};
+use crate::controller::{Controller, FileKind};
+use crate::server::{EXOlineRequest, EXOlineResponse};
+
+use super::dissector::{target, Target};
+use super::pcap::{read_packets, tcp_segment, TcpSegment};
+use super::{DissectedFrame, Direction, Dissector, FrameBody};
+
+/// Whether a variable was read or written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Read,
+    Write,
+}
+
+/// A read or write of a variable found in a capture.
+#[derive(Debug, Clone)]
+pub struct CaptureEvent {
+    /// When the request was captured.
+    pub timestamp: SystemTime,
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    /// The EXOline address the request was sent to. (PLA, ELA)
+    pub address: (u8, u8),
+    pub operation: Operation,
+    pub file_kind: FileKind,
+    pub load_number: u8,
+    pub offset: u32,
+    /// The name of the variable. Only present when a [Controller] was given and it has the variable.
+    pub name: Option<String>,
+    /// The value that was written, or the value that was read.
+    /// `None` for reads that were not answered with a value.
+    pub value: Option<Variant>,
+    /// Exception code sent instead of a response.
+    pub exception: Option<EXOlineException>,
+    /// The time until the response was captured. `None` if there is no response in the capture.
+    pub latency: Option<Duration>,
+}
+
+/// Data that is missing from a capture, like a TCP segment that was dropped by the capturing device.
+///
+/// The requests and responses around a gap may be paired with the wrong responses and requests.
+#[derive(Debug, Clone)]
+pub struct CaptureGap {
+    /// When the data after the gap was captured.
+    pub timestamp: SystemTime,
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    /// Whether the missing data was sent by the client or by the device.
+    pub direction: Direction,
+    /// The number of missing bytes.
+    pub bytes: u32,
+}
+
+/// The reads and writes found in a capture.
+#[derive(Debug, Clone, Default)]
+pub struct CaptureAnalysis {
+    /// Ordered by when the request was captured.
+    pub events: Vec<CaptureEvent>,
+    /// Ordered by when the data after the gap was captured.
+    pub gaps: Vec<CaptureGap>,
+}
+
+/// Reads a pcap or pcapng file and finds every read and write of a variable.
+///
+/// See [analyze_capture].
+pub fn analyze_capture_file(path: impl AsRef<Path>, controller: Option<&Controller>) -> io::Result<CaptureAnalysis> {
+    analyze_capture(&std::fs::read(path)?, controller)
+}
+
+/// Finds every read and write of a variable in the content of a pcap or pcapng file.
+///
+/// TCP streams to and from port [EXOLINE_TCP_PORT] are put back together and the requests are paired with their responses.
+/// Data that is missing from the capture is skipped and reported as a [CaptureGap].
+pub fn analyze_capture(data: &[u8], controller: Option<&Controller>) -> io::Result<CaptureAnalysis> {
+    let dissector = match controller {
+        Some(controller) => Dissector::with_controller(controller),
+        None => Dissector::new(),
+    };
+
+    let mut connections: HashMap<(SocketAddr, SocketAddr), Connection> = HashMap::new();
+    let mut analysis = CaptureAnalysis::default();
+    let mut last_timestamp = UNIX_EPOCH;
+    for packet in read_packets(data)? {
+        last_timestamp = packet.timestamp;
+        let Some(segment) = tcp_segment(&packet) else {
+            continue;
+        };
+        let (direction, client, server) = match (segment.source.port(), segment.destination.port()) {
+            (_, EXOLINE_TCP_PORT) => (Direction::Request, segment.source, segment.destination),
+            (EXOLINE_TCP_PORT, _) => (Direction::Response, segment.destination, segment.source),
+            _ => continue,
+        };
+
+        let connection = connections.entry((client, server)).or_insert_with(|| Connection::new(client, server, dissector.clone()));
+        if segment.syn && direction == Direction::Request {
+            // A new connection that reuses the ports of an old one.
+            let old = std::mem::replace(connection, Connection::new(client, server, dissector.clone()));
+            old.finish(packet.timestamp, &mut analysis);
+        }
+        connection.push(packet.timestamp, direction, &segment, &mut analysis);
+    }
+
+    for connection in connections.into_values() {
+        connection.finish(last_timestamp, &mut analysis);
+    }
+    analysis.events.sort_by_key(|event| event.timestamp);
+    analysis.gaps.sort_by_key(|gap| gap.timestamp);
+    Ok(analysis)
+}
+
+/// A TCP connection to a device.
+struct Connection {
+    client: SocketAddr,
+    server: SocketAddr,
+    requests: TcpStream,
+    responses: TcpStream,
+    dissector: Dissector,
+    /// Requests in the order the dissector pairs them with responses.
+    /// `None` for requests that are not reads or writes of a variable.
+    pending: VecDeque<Option<CaptureEvent>>,
+}
+
+impl Connection {
+    fn new(client: SocketAddr, server: SocketAddr, dissector: Dissector) -> Self {
+        Self {
+            client,
+            server,
+            requests: TcpStream::default(),
+            responses: TcpStream::default(),
+            dissector,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn stream(&mut self, direction: Direction) -> &mut TcpStream {
+        match direction {
+            Direction::Request => &mut self.requests,
+            Direction::Response => &mut self.responses,
+        }
+    }
+
+    /// Adds a segment sent in `direction`.
+    fn push(&mut self, timestamp: SystemTime, direction: Direction, segment: &TcpSegment, analysis: &mut CaptureAnalysis) {
+        let data = self.stream(direction).push(segment.sequence_number, segment.syn, segment.payload);
+        self.feed(timestamp, direction, &data, analysis);
+        while self.stream(direction).is_stalled() {
+            self.skip_gap(timestamp, direction, analysis);
+        }
+    }
+
+    fn skip_gap(&mut self, timestamp: SystemTime, direction: Direction, analysis: &mut CaptureAnalysis) {
+        let Some((bytes, data)) = self.stream(direction).skip_gap() else {
+            return;
+        };
+        analysis.gaps.push(CaptureGap {
+            timestamp,
+            client: self.client,
+            server: self.server,
+            direction,
+            bytes,
+        });
+        // The frame that was cut off by the gap can't be decoded.
+        self.dissector.reset(direction);
+        self.feed(timestamp, direction, &data, analysis);
+    }
+
+    fn feed(&mut self, timestamp: SystemTime, direction: Direction, data: &[u8], analysis: &mut CaptureAnalysis) {
+        for frame in self.dissector.feed(direction, data) {
+            analysis.events.extend(self.handle_frame(timestamp, frame));
+        }
+    }
+
+    /// Skips the gaps that were never filled, and adds the requests that were never answered.
+    fn finish(mut self, timestamp: SystemTime, analysis: &mut CaptureAnalysis) {
+        for direction in [Direction::Request, Direction::Response] {
+            while !self.stream(direction).early.is_empty() {
+                self.skip_gap(timestamp, direction, analysis);
+            }
+        }
+        analysis.events.extend(self.pending.into_iter().flatten());
+    }
+
+    /// Returns the event when a response completes it.
+    fn handle_frame(&mut self, timestamp: SystemTime, frame: DissectedFrame) -> Option<CaptureEvent> {
+        // The dissector pairs frames that have an address, so those are the ones to keep track of.
+        let address = frame.address?;
+        match frame.direction {
+            Direction::Request => {
+                let event = match frame.body {
+                    FrameBody::Request(request) => self.event(timestamp, address, request, frame.name),
+                    _ => None,
+                };
+                self.pending.push_back(event);
+                None
+            }
+            Direction::Response => {
+                let mut event = self.pending.pop_front()??;
+                event.latency = Some(timestamp.duration_since(event.timestamp).unwrap_or_default());
+                match frame.body {
+                    FrameBody::Response(EXOlineResponse::Value(value)) if event.operation == Operation::Read => event.value = Some(value),
+                    FrameBody::Exception(exception) => event.exception = Some(exception),
+                    _ => {}
+                }
+                Some(event)
+            }
+        }
+    }
+
+    fn event(&self, timestamp: SystemTime, address: (u8, u8), request: EXOlineRequest, name: Option<String>) -> Option<CaptureEvent> {
+        let Target::Variable(file_kind, load_number, offset) = target(&request)? else {
+            return None;
+        };
+        let value = written_value(request);
+
+        Some(CaptureEvent {
+            timestamp,
+            client: self.client,
+            server: self.server,
+            address,
+            operation: if value.is_some() { Operation::Write } else { Operation::Read },
+            file_kind,
+            load_number,
+            offset,
+            name,
+            value,
+            exception: None,
+            latency: None,
+        })
+    }
+}
+
+fn written_value(request: EXOlineRequest) -> Option<Variant> {
+    let value = match request {
+        EXOlineRequest::WriteHuge(request) => Variant::Huge(request.value),
+        EXOlineRequest::WriteIndex(request) => Variant::Index(request.value),
+        EXOlineRequest::WriteInteger(request) => Variant::Integer(request.value),
+        EXOlineRequest::WriteLogic(request) => Variant::Logic(request.value),
+        EXOlineRequest::WriteReal(request) => Variant::Real(request.value),
+        EXOlineRequest::WriteString(request) => Variant::String(request.value.into_owned()),
+        EXOlineRequest::WriteVPacIndexSegmented(request) => Variant::Index(request.value),
+        EXOlineRequest::WriteVPacIntegerSegmented(request) => Variant::Integer(request.value),
+        EXOlineRequest::WriteVPacLogicSegmented(request) => Variant::Logic(request.value),
+        EXOlineRequest::WriteVPacRealSegmented(request) => Variant::Real(request.value),
+        EXOlineRequest::WriteBPacIndex(request) => Variant::Index(request.value),
+        EXOlineRequest::WriteBPacInteger(request) => Variant::Integer(request.value),
+        EXOlineRequest::WriteBPacLogic(request) => Variant::Logic(request.value),
+        EXOlineRequest::WriteBPacReal(request) => Variant::Real(request.value),
+        EXOlineRequest::WriteTaskIndex(request) => Variant::Index(request.value),
+        EXOlineRequest::WriteTaskInteger(request) => Variant::Integer(request.value),
+        EXOlineRequest::WriteTaskLogic(request) => Variant::Logic(request.value),
+        EXOlineRequest::WriteTaskReal(request) => Variant::Real(request.value),
+        EXOlineRequest::WriteText(request) => Variant::String(request.value.into_owned()),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// How much data is held back waiting for a segment, before the segment is assumed to be missing from the capture.
+/// The largest TCP window without window scaling.
+const MAX_EARLY_BYTES: usize = 64 * 1024;
+
+/// One direction of a TCP connection.
+#[derive(Default)]
+struct TcpStream {
+    /// The sequence number of the next byte.
+    next: Option<u32>,
+    /// Segments that arrived before the data in front of them.
+    early: Vec<(u32, Vec<u8>)>,
+}
+
+impl TcpStream {
+    /// Returns the data that follows what has been returned before.
+    /// Retransmitted data is dropped and segments that arrive early are held back until the gap is filled.
+    fn push(&mut self, sequence_number: u32, syn: bool, payload: &[u8]) -> Vec<u8> {
+        if syn {
+            self.next = Some(sequence_number.wrapping_add(1));
+            self.early.clear();
+            return Vec::new();
+        }
+        if payload.is_empty() {
+            return Vec::new();
+        }
+        // Capture started in the middle of the connection.
+        self.next.get_or_insert(sequence_number);
+        self.early.push((sequence_number, payload.into()));
+        self.reassemble()
+    }
+
+    /// Returns `true` if so much data is held back that the gap in front of it is not going to be filled.
+    fn is_stalled(&self) -> bool {
+        self.early.iter().map(|(_, segment)| segment.len()).sum::<usize>() > MAX_EARLY_BYTES
+    }
+
+    /// Skips to the first segment that is held back, and returns the number of bytes that were skipped
+    /// and the data that follows. Returns `None` if nothing is held back.
+    fn skip_gap(&mut self) -> Option<(u32, Vec<u8>)> {
+        let next = self.next?;
+        let first = self.early.iter().map(|(sequence_number, _)| *sequence_number).min_by_key(|sequence_number| sequence_number.wrapping_sub(next))?;
+        self.next = Some(first);
+        Some((first.wrapping_sub(next), self.reassemble()))
+    }
+
+    /// Takes the segments that continue the stream.
+    fn reassemble(&mut self) -> Vec<u8> {
+        let Some(mut next) = self.next else {
+            return Vec::new();
+        };
+        let mut data = Vec::new();
+        while let Some(index) = self.early.iter().position(|(sequence_number, _)| sequence_number.wrapping_sub(next) as i32 <= 0) {
+            let (sequence_number, segment) = self.early.swap_remove(index);
+            let overlap = next.wrapping_sub(sequence_number) as usize;
+            if overlap < segment.len() {
+                data.extend(&segment[overlap..]);
+                next = next.wrapping_add((segment.len() - overlap) as u32);
+            }
+        }
+        self.next = Some(next);
+        data
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::client::FrameDirection;
+    use crate::test_util::{connect_test_controller, Frames};
+
+    use super::*;
+
+    const CLIENT: ([u8; 4], u16) = ([10, 0, 0, 1], 50000);
+    const SERVER: ([u8; 4], u16) = ([10, 0, 0, 2], EXOLINE_TCP_PORT);
+
+    /// Writes a pcap file with raw IPv4 packets between the [CLIENT] and the [SERVER].
+    struct PcapWriter {
+        data: Vec<u8>,
+        packets: u32,
+    }
+
+    impl PcapWriter {
+        fn new() -> Self {
+            let mut data = vec![0xD4, 0xC3, 0xB2, 0xA1, 2, 0, 4, 0];
+            data.extend([0; 8]);
+            data.extend(65535u32.to_le_bytes());
+            data.extend(101u32.to_le_bytes());
+            Self { data, packets: 0 }
+        }
+
+        fn request(&mut self, sequence_number: u32, flags: u8, payload: &[u8]) {
+            self.tcp(CLIENT, SERVER, sequence_number, flags, payload);
+        }
+
+        fn response(&mut self, sequence_number: u32, payload: &[u8]) {
+            self.tcp(SERVER, CLIENT, sequence_number, 0x18, payload);
+        }
+
+        fn tcp(&mut self, (source, source_port): ([u8; 4], u16), (destination, destination_port): ([u8; 4], u16), sequence_number: u32, flags: u8, payload: &[u8]) {
+            let mut ip = vec![0x45, 0];
+            ip.extend((40 + payload.len() as u16).to_be_bytes());
+            ip.extend([0, 0, 0x40, 0, 64, 6, 0, 0]);
+            ip.extend(source);
+            ip.extend(destination);
+            ip.extend(source_port.to_be_bytes());
+            ip.extend(destination_port.to_be_bytes());
+            ip.extend(sequence_number.to_be_bytes());
+            ip.extend([0, 0, 0, 0, 0x50, flags, 0xFF, 0xFF, 0, 0, 0, 0]);
+            ip.extend(payload);
+
+            self.packets += 1;
+            self.data.extend(1_700_000_000u32.to_le_bytes());
+            self.data.extend((self.packets * 1000).to_le_bytes());
+            self.data.extend((ip.len() as u32).to_le_bytes());
+            self.data.extend((ip.len() as u32).to_le_bytes());
+            self.data.extend(ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn analyze() {
+        let (controller, _, client) = connect_test_controller().await;
+        let capture = Arc::new(Frames::default());
+        client.set_frame_observer(Some(capture.clone()));
+
+        let variable = controller.lookup_variable("Test.Integer").unwrap();
+        client.write_variable(controller.address, &variable, &Variant::Integer(42)).await.unwrap();
+        client.read_variable(controller.address, &variable).await.unwrap();
+
+        let mut pcap = PcapWriter::new();
+        pcap.request(999, 0x02, &[]);
+        let (mut request_sequence, mut response_sequence) = (1000u32, 5000u32);
+        for (i, (direction, bytes)) in capture.wire_bytes().iter().enumerate() {
+            match direction {
+                FrameDirection::Outgoing if i == 0 => {
+                    // Out of order, followed by a retransmission.
+                    let (first, second) = bytes.split_at(4);
+                    pcap.request(request_sequence + 4, 0x18, second);
+                    pcap.request(request_sequence, 0x18, first);
+                    pcap.request(request_sequence, 0x18, bytes);
+                    request_sequence += bytes.len() as u32;
+                }
+                FrameDirection::Outgoing => {
+                    pcap.request(request_sequence, 0x18, bytes);
+                    request_sequence += bytes.len() as u32;
+                }
+                FrameDirection::Incoming => {
+                    pcap.response(response_sequence, bytes);
+                    response_sequence += bytes.len() as u32;
+                }
+            }
+        }
+
+        let analysis = analyze_capture(&pcap.data, Some(&controller)).unwrap();
+        assert!(analysis.gaps.is_empty());
+        let events = analysis.events;
+        let [write, read] = &events[..] else {
+            panic!("Expected 2 events, got {events:?}");
+        };
+        assert_eq!(write.operation, Operation::Write);
+        assert_eq!(write.name.as_deref(), Some("Test.Integer"));
+        assert_eq!(write.value, Some(Variant::Integer(42)));
+        assert_eq!(write.address, controller.address);
+        assert_eq!(write.client, "10.0.0.1:50000".parse().unwrap());
+        // The request is complete once the gap is filled, one packet before the retransmission.
+        assert_eq!(write.latency, Some(Duration::from_millis(2)));
+        assert_eq!(read.operation, Operation::Read);
+        assert_eq!((read.file_kind, read.load_number, read.offset), (variable.file_kind(), variable.load_number(), variable.offset()));
+        assert_eq!(read.value, Some(Variant::Integer(42)));
+        assert!(read.exception.is_none());
+    }
+
+    #[tokio::test]
+    async fn missing_segment() {
+        let (controller, _, client) = connect_test_controller().await;
+        let capture = Arc::new(Frames::default());
+        client.set_frame_observer(Some(capture.clone()));
+
+        let variable = controller.lookup_variable("Test.Integer").unwrap();
+        client.write_variable(controller.address, &variable, &Variant::Integer(42)).await.unwrap();
+        client.read_variable(controller.address, &variable).await.unwrap();
+        let [(_, write_request), _, (_, read_request), (_, read_response)] = &capture.wire_bytes()[..] else {
+            panic!("Expected 4 frames");
+        };
+
+        // The write request and its response were not captured.
+        let mut pcap = PcapWriter::new();
+        pcap.request(999, 0x02, &[]);
+        let mut request_sequence = 1000 + write_request.len() as u32;
+        pcap.request(request_sequence, 0x18, read_request);
+        request_sequence += read_request.len() as u32;
+        // Enough data after the gap that the gap is given up on.
+        let filler = vec![0; MAX_EARLY_BYTES / 2 + 1];
+        for _ in 0..2 {
+            pcap.request(request_sequence, 0x18, &filler);
+            request_sequence += filler.len() as u32;
+        }
+        pcap.response(5000, read_response);
+
+        let analysis = analyze_capture(&pcap.data, Some(&controller)).unwrap();
+        let [gap] = &analysis.gaps[..] else {
+            panic!("Expected 1 gap, got {:?}", analysis.gaps);
+        };
+        assert_eq!(gap.direction, Direction::Request);
+        assert_eq!(gap.bytes, write_request.len() as u32);
+        assert_eq!(gap.client, "10.0.0.1:50000".parse().unwrap());
+        let [read] = &analysis.events[..] else {
+            panic!("Expected 1 event, got {:?}", analysis.events);
+        };
+        assert_eq!(read.operation, Operation::Read);
+        assert_eq!(read.value, Some(Variant::Integer(42)));
+        assert!(read.latency.is_some());
+    }
+
+    #[test]
+    fn unfilled_gap() {
+        let mut pcap = PcapWriter::new();
+        pcap.request(999, 0x02, &[]);
+        pcap.request(1010, 0x18, &[0; 4]);
+
+        let analysis = analyze_capture(&pcap.data, None).unwrap();
+        let [gap] = &analysis.gaps[..] else {
+            panic!("Expected 1 gap, got {:?}", analysis.gaps);
+        };
+        assert_eq!(gap.bytes, 10);
+        assert!(analysis.events.is_empty());
+    }
+}
@@ -0,0 +1,440 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Display;
+
+use crate::client::internal::consts::*;
+use crate::client::internal::encoding::{hex, unescape, verify_and_remove_crc};
+use crate::client::{CommandId, EXOlineException};
+use crate::commands::{segment_offset, CommandFileKind};
+use crate::controller::{Controller, FileKind};
+use crate::server::{EXOlineRequest, EXOlineResponse};
+
+/// The direction of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// From a client to a device.
+    Request,
+    /// From a device to a client.
+    Response,
+}
+
+/// The content of a frame.
+#[derive(Debug, PartialEq)]
+pub enum FrameBody {
+    Request(EXOlineRequest),
+    Response(EXOlineResponse),
+    /// Exception code sent instead of a response.
+    Exception(EXOlineException),
+    /// The payload could not be decoded. Responses can only be decoded when the request they answer has been seen.
+    Undecoded(Vec<u8>),
+    /// The CRC doesn't match. Holds the unescaped data.
+    CrcMismatch(Vec<u8>),
+    /// Bytes that don't form a frame.
+    Invalid,
+}
+
+/// A decoded frame.
+#[derive(Debug, PartialEq)]
+pub struct DissectedFrame {
+    pub direction: Direction,
+    /// The escaped bytes, without the begin and end markers.
+    pub raw: Vec<u8>,
+    /// The address of the request. For responses, the address of the request they answer.
+    pub address: Option<(u8, u8)>,
+    /// The command of the request. For responses, the command of the request they answer.
+    pub command_id: Option<CommandId>,
+    pub body: FrameBody,
+    /// The variable or file that the request addresses.
+    /// Only present when the [Dissector] was given a [Controller].
+    pub name: Option<String>,
+}
+
+/// A request that is waiting for its response.
+#[derive(Clone)]
+struct PendingRequest {
+    address: (u8, u8),
+    command_id: CommandId,
+    payload: Vec<u8>,
+    name: Option<String>,
+}
+
+/// What a request addresses.
+pub(crate) enum Target {
+    Variable(FileKind, u8, u32),
+    File(FileKind, u8),
+}
+
+/// Decodes a captured byte stream into frames.
+///
+/// Both directions of a connection should be fed to the same dissector, in the order they were captured,
+/// since responses are decoded according to the request they answer.
+/// A response is assumed to answer the oldest request that has not been answered yet.
+#[derive(Default, Clone)]
+pub struct Dissector {
+    buffers: HashMap<Direction, Vec<u8>>,
+    in_frame: HashMap<Direction, bool>,
+    /// Requests waiting for a response. `None` for requests that are too short to tell what they are.
+    pending: VecDeque<Option<PendingRequest>>,
+    variables: HashMap<(FileKind, u8, u32), String>,
+    files: HashMap<(FileKind, u8), String>,
+}
+
+impl Dissector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a dissector that names the variables and files in `controller`.
+    pub fn with_controller(controller: &Controller) -> Self {
+        let mut dissector = Self::new();
+        for file in controller.files().iter().chain(controller.globals().iter()) {
+            dissector.files.insert((file.kind(), file.load_number()), file.name().to_string());
+            for variable in file.iter() {
+                let name = match variable.full_name() {
+                    Some(name) => name.to_string(),
+                    None => format!("{}@{}", variable.file_name(), variable.offset()),
+                };
+                dissector.variables.insert((file.kind(), file.load_number(), variable.offset()), name);
+            }
+        }
+        dissector
+    }
+
+    /// Feeds captured bytes sent in `direction`, and returns the frames that were completed.
+    ///
+    /// Frames may be split over several calls.
+    pub fn feed(&mut self, direction: Direction, data: &[u8]) -> Vec<DissectedFrame> {
+        let begin = match direction {
+            Direction::Request => BEGIN_REQUEST,
+            Direction::Response => BEGIN_RESPONSE,
+        };
+
+        let mut frames = Vec::new();
+        for &byte in data {
+            let buffer = self.buffers.entry(direction).or_default();
+            let in_frame = self.in_frame.entry(direction).or_default();
+
+            match byte {
+                value if value == begin => {
+                    if !buffer.is_empty() {
+                        // An unfinished frame or data outside of a frame.
+                        let raw = std::mem::take(buffer);
+                        frames.push(invalid(direction, raw));
+                    }
+                    *in_frame = true;
+                }
+                END_MESSAGE if *in_frame => {
+                    *in_frame = false;
+                    let raw = std::mem::take(buffer);
+                    frames.push(self.dissect(direction, &raw));
+                }
+                value => buffer.push(value),
+            }
+        }
+        frames
+    }
+
+    /// Drops the part of a frame that has been fed in `direction`, when the rest of it is missing from the capture.
+    pub fn reset(&mut self, direction: Direction) {
+        self.buffers.remove(&direction);
+        self.in_frame.remove(&direction);
+    }
+
+    /// Decodes a single frame without the begin and end markers.
+    pub fn dissect(&mut self, direction: Direction, raw: &[u8]) -> DissectedFrame {
+        match direction {
+            Direction::Request => self.dissect_request(raw),
+            Direction::Response => self.dissect_response(raw),
+        }
+    }
+
+    fn dissect_request(&mut self, raw: &[u8]) -> DissectedFrame {
+        let mut frame = DissectedFrame {
+            direction: Direction::Request,
+            raw: raw.into(),
+            address: None,
+            command_id: None,
+            body: FrameBody::Invalid,
+            name: None,
+        };
+
+        let data = unescape(raw);
+        let Some(body) = verify_and_remove_crc(&data) else {
+            frame.body = FrameBody::CrcMismatch(data.into_owned());
+            return frame;
+        };
+        let [pla, ela, command_id, payload @ ..] = body else {
+            // Still takes a response, so that the following responses are paired with the right requests.
+            self.pending.push_back(None);
+            return frame;
+        };
+        let address = (*pla, *ela);
+        let command_id = CommandId::from(*command_id);
+        frame.address = Some(address);
+        frame.command_id = Some(command_id);
+
+        frame.body = match EXOlineRequest::decode(command_id, payload) {
+            Ok(request) => {
+                frame.name = self.name_of(&request);
+                FrameBody::Request(request)
+            }
+            Err(_) => FrameBody::Undecoded(payload.into()),
+        };
+        self.pending.push_back(Some(PendingRequest {
+            address,
+            command_id,
+            payload: payload.into(),
+            name: frame.name.clone(),
+        }));
+        frame
+    }
+
+    fn dissect_response(&mut self, raw: &[u8]) -> DissectedFrame {
+        let pending = self.pending.pop_front().flatten();
+        let mut frame = DissectedFrame {
+            direction: Direction::Response,
+            raw: raw.into(),
+            address: pending.as_ref().map(|pending| pending.address),
+            command_id: pending.as_ref().map(|pending| pending.command_id),
+            body: FrameBody::Invalid,
+            name: pending.as_ref().and_then(|pending| pending.name.clone()),
+        };
+
+        if let [exception] = raw {
+            frame.body = FrameBody::Exception((*exception).into());
+            return frame;
+        }
+
+        let data = unescape(raw);
+        let Some(payload) = verify_and_remove_crc(&data) else {
+            frame.body = FrameBody::CrcMismatch(data.into_owned());
+            return frame;
+        };
+
+        let request = pending.and_then(|pending| EXOlineRequest::decode(pending.command_id, &pending.payload).ok());
+        frame.body = match request.map(|request| EXOlineResponse::decode(&request, payload)) {
+            Some(Ok(response)) => FrameBody::Response(response),
+            _ => FrameBody::Undecoded(payload.into()),
+        };
+        frame
+    }
+
+    fn name_of(&self, request: &EXOlineRequest) -> Option<String> {
+        match target(request)? {
+            Target::Variable(file_kind, load_number, offset) => {
+                // Generic commands address text files as VPac's.
+                let file_kinds: &[FileKind] = match file_kind {
+                    FileKind::VPac => &[FileKind::VPac, FileKind::Text],
+                    _ => &[file_kind],
+                };
+                file_kinds
+                    .iter()
+                    .find_map(|file_kind| self.variables.get(&(*file_kind, load_number, offset)))
+                    .cloned()
+            }
+            Target::File(file_kind, load_number) => {
+                // DPac commands don't tell VPac's and BPac's apart.
+                let file_kinds: &[FileKind] = match file_kind {
+                    FileKind::VPac => &[FileKind::VPac, FileKind::BPac],
+                    _ => &[file_kind],
+                };
+                file_kinds.iter().find_map(|file_kind| self.files.get(&(*file_kind, load_number))).cloned()
+            }
+        }
+    }
+}
+
+fn invalid(direction: Direction, raw: Vec<u8>) -> DissectedFrame {
+    DissectedFrame {
+        direction,
+        raw,
+        address: None,
+        command_id: None,
+        body: FrameBody::Invalid,
+        name: None,
+    }
+}
+
+/// The variable or file a request addresses.
+pub(crate) fn target(request: &EXOlineRequest) -> Option<Target> {
+    let file_kind = |kind: CommandFileKind| match kind {
+        CommandFileKind::VPac => Some(FileKind::VPac),
+        CommandFileKind::Task => Some(FileKind::Task),
+        CommandFileKind::BPac => Some(FileKind::BPac),
+        CommandFileKind::Unknown(_) => None,
+    };
+    let segmented = |load_number: u8, segment: u8, offset: u8| Target::Variable(FileKind::VPac, load_number, segment_offset(segment, offset));
+
+    let target = match request {
+        EXOlineRequest::ReadHuge(r)
+        | EXOlineRequest::ReadIndex(r)
+        | EXOlineRequest::ReadInteger(r)
+        | EXOlineRequest::ReadLogic(r)
+        | EXOlineRequest::ReadReal(r)
+        | EXOlineRequest::ReadString(r) => Target::Variable(file_kind(r.kind)?, r.load_number, r.offset),
+        EXOlineRequest::WriteHuge(r) => Target::Variable(file_kind(r.kind)?, r.load_number, r.offset),
+        EXOlineRequest::WriteIndex(r) => Target::Variable(file_kind(r.kind)?, r.load_number, r.offset),
+        EXOlineRequest::WriteInteger(r) => Target::Variable(file_kind(r.kind)?, r.load_number, r.offset),
+        EXOlineRequest::WriteLogic(r) => Target::Variable(file_kind(r.kind)?, r.load_number, r.offset),
+        EXOlineRequest::WriteReal(r) => Target::Variable(file_kind(r.kind)?, r.load_number, r.offset),
+        EXOlineRequest::WriteString(r) => Target::Variable(file_kind(r.kind)?, r.load_number, r.offset),
+        EXOlineRequest::ReadVPacIndexSegmented(r)
+        | EXOlineRequest::ReadVPacIntegerSegmented(r)
+        | EXOlineRequest::ReadVPacLogicSegmented(r)
+        | EXOlineRequest::ReadVPacRealSegmented(r) => segmented(r.load_number, r.segment, r.offset),
+        EXOlineRequest::WriteVPacIndexSegmented(r) => segmented(r.load_number, r.segment, r.offset),
+        EXOlineRequest::WriteVPacIntegerSegmented(r) => segmented(r.load_number, r.segment, r.offset),
+        EXOlineRequest::WriteVPacLogicSegmented(r) => segmented(r.load_number, r.segment, r.offset),
+        EXOlineRequest::WriteVPacRealSegmented(r) => segmented(r.load_number, r.segment, r.offset),
+        EXOlineRequest::ReadBPacIndex(r)
+        | EXOlineRequest::ReadBPacInteger(r)
+        | EXOlineRequest::ReadBPacLogic(r)
+        | EXOlineRequest::ReadBPacReal(r) => Target::Variable(FileKind::BPac, r.load_number, r.offset),
+        EXOlineRequest::WriteBPacIndex(r) => Target::Variable(FileKind::BPac, r.load_number, r.offset),
+        EXOlineRequest::WriteBPacInteger(r) => Target::Variable(FileKind::BPac, r.load_number, r.offset),
+        EXOlineRequest::WriteBPacLogic(r) => Target::Variable(FileKind::BPac, r.load_number, r.offset),
+        EXOlineRequest::WriteBPacReal(r) => Target::Variable(FileKind::BPac, r.load_number, r.offset),
+        EXOlineRequest::ReadTaskIndex(r)
+        | EXOlineRequest::ReadTaskInteger(r)
+        | EXOlineRequest::ReadTaskLogic(r)
+        | EXOlineRequest::ReadTaskReal(r) => Target::Variable(FileKind::Task, r.load_number, r.offset),
+        EXOlineRequest::WriteTaskIndex(r) => Target::Variable(FileKind::Task, r.load_number, r.offset),
+        EXOlineRequest::WriteTaskInteger(r) => Target::Variable(FileKind::Task, r.load_number, r.offset),
+        EXOlineRequest::WriteTaskLogic(r) => Target::Variable(FileKind::Task, r.load_number, r.offset),
+        EXOlineRequest::WriteTaskReal(r) => Target::Variable(FileKind::Task, r.load_number, r.offset),
+        EXOlineRequest::ReadText(r) => Target::Variable(FileKind::Text, r.load_number, r.offset),
+        EXOlineRequest::WriteText(r) => Target::Variable(FileKind::Text, r.load_number, r.offset),
+        EXOlineRequest::ReadDPacPage(r) => Target::File(FileKind::VPac, r.load_number),
+        #[cfg(feature = "unstable-stats")]
+        EXOlineRequest::GetDPacStats(r) => Target::File(FileKind::VPac, r.load_number),
+        EXOlineRequest::ReadTaskPage(r) => Target::File(FileKind::Task, r.load_number),
+        #[cfg(feature = "unstable-stats")]
+        EXOlineRequest::GetTaskStats(r) => Target::File(FileKind::Task, r.load_number),
+        EXOlineRequest::GetControllerId | EXOlineRequest::Login(_) | EXOlineRequest::ReadPartAttrHeader(_) | EXOlineRequest::Unknown(_, _) => return None,
+        #[cfg(feature = "unstable-stats")]
+        EXOlineRequest::GetControllerStats => return None,
+    };
+    Some(target)
+}
+
+impl Display for DissectedFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.direction {
+            Direction::Request => write!(f, ">>")?,
+            Direction::Response => write!(f, "<<")?,
+        }
+        if let Some((pla, ela)) = self.address {
+            write!(f, " {pla}:{ela}")?;
+        }
+        if let Some(command_id) = self.command_id {
+            write!(f, " {command_id:?}")?;
+        }
+        if let Some(name) = &self.name {
+            write!(f, " {name}")?;
+        }
+        match &self.body {
+            FrameBody::Request(EXOlineRequest::Unknown(_, payload)) => write!(f, " [{}]", hex(payload, " ")),
+            FrameBody::Request(request) => write!(f, " {request:?}"),
+            FrameBody::Response(EXOlineResponse::Raw(data) | EXOlineResponse::DPacPage(data)) => write!(f, " [{}]", hex(data, " ")),
+            FrameBody::Response(response) => write!(f, " {response:?}"),
+            FrameBody::Exception(exception) => write!(f, " exception {exception:?}"),
+            FrameBody::Undecoded(payload) => write!(f, " undecoded [{}]", hex(payload, " ")),
+            FrameBody::CrcMismatch(data) => write!(f, " CRC mismatch [{}]", hex(data, " ")),
+            FrameBody::Invalid => write!(f, " invalid [{}]", hex(&self.raw, " ")),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::client::internal::encoding::{append_crc, escape};
+    use crate::client::{FrameDirection, Variant};
+    use crate::commands::ReadRequest;
+    use crate::test_util::{connect_test_controller, Frames};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn dissect_captured_frames() {
+        let (controller, _, client) = connect_test_controller().await;
+        let capture = Arc::new(Frames::default());
+        client.set_frame_observer(Some(capture.clone()));
+
+        let variable = controller.lookup_variable("Test.Integer").unwrap();
+        client.write_variable(controller.address, &variable, &Variant::Integer(42)).await.unwrap();
+        client.read_variable(controller.address, &variable).await.unwrap();
+        _ = client.read_dpac_page_raw(controller.address, variable.file_kind(), 99, 0).await;
+
+        let mut dissector = Dissector::with_controller(&controller);
+        let mut frames = Vec::new();
+        for (direction, bytes) in capture.wire_bytes() {
+            let direction = match direction {
+                FrameDirection::Outgoing => Direction::Request,
+                FrameDirection::Incoming => Direction::Response,
+            };
+            // Split the frames to check that they are put back together.
+            for chunk in bytes.chunks(3) {
+                frames.extend(dissector.feed(direction, chunk));
+            }
+        }
+
+        let [write, ack, read, value, page, exception] = &frames[..] else {
+            panic!("Expected 6 frames, got {frames:?}");
+        };
+        assert_eq!(write.command_id, Some(CommandId::WriteInteger));
+        assert_eq!(write.name.as_deref(), Some("Test.Integer"));
+        assert_eq!(ack.body, FrameBody::Response(EXOlineResponse::Ack));
+        assert_eq!(
+            read.body,
+            FrameBody::Request(EXOlineRequest::ReadInteger(ReadRequest {
+                kind: CommandFileKind::VPac,
+                load_number: variable.load_number(),
+                offset: variable.offset(),
+            }))
+        );
+        assert_eq!(value.address, Some(controller.address));
+        assert_eq!(value.name.as_deref(), Some("Test.Integer"));
+        assert_eq!(value.body, FrameBody::Response(EXOlineResponse::Value(Variant::Integer(42))));
+        assert_eq!(page.name, None);
+        assert_eq!(exception.body, FrameBody::Exception(EXOlineException::DPacNotPresent));
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let mut dissector = Dissector::new();
+
+        let frames = dissector.feed(Direction::Request, &[1, 2, BEGIN_REQUEST, 1, 2, 3, 4, END_MESSAGE]);
+        let [stray, crc] = &frames[..] else {
+            panic!("Expected 2 frames, got {frames:?}");
+        };
+        assert_eq!(stray.body, FrameBody::Invalid);
+        assert_eq!(crc.body, FrameBody::CrcMismatch(vec![1, 2, 3, 4]));
+
+        let frames = dissector.feed(Direction::Response, &[BEGIN_RESPONSE, 0x42, END_MESSAGE]);
+        assert_eq!(frames[0].body, FrameBody::Exception(0x42.into()));
+        assert_eq!(frames[0].address, None);
+    }
+
+    #[test]
+    fn short_request() {
+        let mut dissector = Dissector::new();
+
+        let mut short = vec![1, 2];
+        append_crc(&mut short);
+        let mut read = vec![1, 2, CommandId::ReadInteger.into(), CommandFileKind::VPac.into(), 1, 0, 0, 0];
+        append_crc(&mut read);
+        for data in [short, read] {
+            let frames = dissector.feed(Direction::Request, &[&[BEGIN_REQUEST][..], &escape(&data), &[END_MESSAGE]].concat());
+            assert_eq!(frames.len(), 1);
+        }
+
+        // The short request takes the first response, so the second one answers the read.
+        let frames = dissector.feed(Direction::Response, &[BEGIN_RESPONSE, 0x42, END_MESSAGE, BEGIN_RESPONSE, 0x43, END_MESSAGE]);
+        let [first, second] = &frames[..] else {
+            panic!("Expected 2 frames, got {frames:?}");
+        };
+        assert_eq!(first.command_id, None);
+        assert_eq!(second.command_id, Some(CommandId::ReadInteger));
+    }
+}
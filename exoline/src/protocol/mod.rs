@@ -0,0 +1,13 @@
+//! EXOline protocol dissector.
+//! Decodes captured traffic into requests and responses.
+//!
+//! Start with the [Dissector], or [analyze_capture_file] to find the reads and writes in a pcap or pcapng file.
+
+mod capture;
+mod dissector;
+mod pcap;
+
+pub use capture::{analyze_capture, analyze_capture_file, CaptureAnalysis, CaptureEvent, CaptureGap, Operation};
+pub use dissector::{Direction, DissectedFrame, Dissector, FrameBody};
+#[cfg(feature = "tracing")]
+pub(crate) use dissector::{target, Target};
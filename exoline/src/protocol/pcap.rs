@@ -0,0 +1,371 @@
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A packet from a capture file.
+pub(super) struct Packet<'a> {
+    pub timestamp: SystemTime,
+    pub link_type: u16,
+    pub data: &'a [u8],
+}
+
+/// A TCP segment found in a [Packet].
+pub(super) struct TcpSegment<'a> {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    pub sequence_number: u32,
+    pub syn: bool,
+    pub payload: &'a [u8],
+}
+
+const LINKTYPE_NULL: u16 = 0;
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_LOOP: u16 = 108;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+const LINKTYPE_IPV4: u16 = 228;
+const LINKTYPE_IPV6: u16 = 229;
+const LINKTYPE_LINUX_SLL2: u16 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86DD;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const PROTOCOL_TCP: u8 = 6;
+
+/// Reads all packets from a pcap or pcapng file.
+pub(super) fn read_packets(data: &[u8]) -> Result<Vec<Packet<'_>>> {
+    match data.get(..4) {
+        Some([0x0A, 0x0D, 0x0D, 0x0A]) => read_pcapng(data),
+        Some(_) => read_pcap(data),
+        None => Err(invalid("The file is too short")),
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Reads numbers with the byte order of the file.
+#[derive(Clone, Copy)]
+struct ByteOrder {
+    big_endian: bool,
+}
+
+impl ByteOrder {
+    fn u16(self, data: &[u8], offset: usize) -> Result<u16> {
+        let bytes = data.get(offset..offset + 2).ok_or_else(|| invalid("Unexpected end of file"))?;
+        let bytes = [bytes[0], bytes[1]];
+        Ok(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(self, data: &[u8], offset: usize) -> Result<u32> {
+        let bytes = data.get(offset..offset + 4).ok_or_else(|| invalid("Unexpected end of file"))?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+}
+
+fn read_pcap(data: &[u8]) -> Result<Vec<Packet<'_>>> {
+    let (byte_order, nanoseconds) = match data[..4] {
+        [0xA1, 0xB2, 0xC3, 0xD4] => (ByteOrder { big_endian: true }, false),
+        [0xD4, 0xC3, 0xB2, 0xA1] => (ByteOrder { big_endian: false }, false),
+        [0xA1, 0xB2, 0x3C, 0x4D] => (ByteOrder { big_endian: true }, true),
+        [0x4D, 0x3C, 0xB2, 0xA1] => (ByteOrder { big_endian: false }, true),
+        _ => return Err(invalid("Not a pcap or pcapng file")),
+    };
+    let link_type = byte_order.u32(data, 20)? as u16;
+
+    let mut packets = Vec::new();
+    let mut position = 24;
+    while position < data.len() {
+        let seconds = byte_order.u32(data, position)?;
+        let fraction = byte_order.u32(data, position + 4)?;
+        let length = byte_order.u32(data, position + 8)? as usize;
+        let start = position + 16;
+        let packet = data.get(start..start + length).ok_or_else(|| invalid("Unexpected end of file"))?;
+
+        let fraction = match nanoseconds {
+            true => Duration::from_nanos(fraction as u64),
+            false => Duration::from_micros(fraction as u64),
+        };
+        packets.push(Packet {
+            timestamp: UNIX_EPOCH + Duration::from_secs(seconds as u64) + fraction,
+            link_type,
+            data: packet,
+        });
+        position = start + length;
+    }
+    Ok(packets)
+}
+
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 1;
+const BLOCK_SIMPLE_PACKET: u32 = 3;
+const BLOCK_ENHANCED_PACKET: u32 = 6;
+
+const OPTION_END: u16 = 0;
+const OPTION_IF_TSRESOL: u16 = 9;
+
+struct Interface {
+    link_type: u16,
+    /// The value of the if_tsresol option.
+    timestamp_resolution: u8,
+}
+
+fn read_pcapng(data: &[u8]) -> Result<Vec<Packet<'_>>> {
+    let mut byte_order = ByteOrder { big_endian: false };
+    let mut interfaces = Vec::new();
+
+    let mut packets = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let block_type = byte_order.u32(data, position)?;
+        if block_type == BLOCK_SECTION_HEADER {
+            // Every section can have its own byte order.
+            byte_order.big_endian = match data.get(position + 8..position + 12) {
+                Some([0x1A, 0x2B, 0x3C, 0x4D]) => true,
+                Some([0x4D, 0x3C, 0x2B, 0x1A]) => false,
+                _ => return Err(invalid("Invalid pcapng section header")),
+            };
+            interfaces.clear();
+        }
+
+        let length = byte_order.u32(data, position + 4)? as usize;
+        if length < 12 || !length.is_multiple_of(4) {
+            return Err(invalid("Invalid pcapng block length"));
+        }
+        let body = data.get(position + 8..position + length - 4).ok_or_else(|| invalid("Unexpected end of file"))?;
+
+        match block_type {
+            BLOCK_INTERFACE_DESCRIPTION => {
+                let mut interface = Interface {
+                    link_type: byte_order.u16(body, 0)?,
+                    timestamp_resolution: 6,
+                };
+                let mut option = 8;
+                while option + 4 <= body.len() {
+                    let code = byte_order.u16(body, option)?;
+                    let option_length = byte_order.u16(body, option + 2)? as usize;
+                    match code {
+                        OPTION_END => break,
+                        OPTION_IF_TSRESOL if option_length == 1 => {
+                            interface.timestamp_resolution = *body.get(option + 4).ok_or_else(|| invalid("Unexpected end of file"))?;
+                        }
+                        _ => {}
+                    }
+                    option += 4 + option_length.next_multiple_of(4);
+                }
+                interfaces.push(interface);
+            }
+            BLOCK_ENHANCED_PACKET => {
+                let interface = interfaces.get(byte_order.u32(body, 0)? as usize).ok_or_else(|| invalid("Unknown pcapng interface"))?;
+                let timestamp = (byte_order.u32(body, 4)? as u64) << 32 | byte_order.u32(body, 8)? as u64;
+                let length = byte_order.u32(body, 12)? as usize;
+                packets.push(Packet {
+                    timestamp: UNIX_EPOCH + timestamp_to_duration(timestamp, interface.timestamp_resolution),
+                    link_type: interface.link_type,
+                    data: body.get(20..20 + length).ok_or_else(|| invalid("Unexpected end of file"))?,
+                });
+            }
+            BLOCK_SIMPLE_PACKET => {
+                // Simple packets have no timestamp and belong to the first interface.
+                let interface = interfaces.first().ok_or_else(|| invalid("Unknown pcapng interface"))?;
+                let length = (byte_order.u32(body, 0)? as usize).min(body.len() - 4);
+                packets.push(Packet {
+                    timestamp: UNIX_EPOCH,
+                    link_type: interface.link_type,
+                    data: &body[4..4 + length],
+                });
+            }
+            _ => {}
+        }
+        position += length;
+    }
+    Ok(packets)
+}
+
+/// Converts a pcapng timestamp with the resolution given by the if_tsresol option.
+fn timestamp_to_duration(timestamp: u64, resolution: u8) -> Duration {
+    let exponent = (resolution & 0x7F) as u32;
+    let nanoseconds = match resolution & 0x80 != 0 {
+        // Negative power of 2.
+        true => (timestamp as u128 * 1_000_000_000) >> exponent.min(127),
+        // Negative power of 10.
+        false => match exponent <= 9 {
+            true => timestamp as u128 * 10u128.pow(9 - exponent),
+            false => timestamp as u128 / 10u128.pow((exponent - 9).min(38)),
+        },
+    };
+    Duration::from_nanos(nanoseconds.try_into().unwrap_or(u64::MAX))
+}
+
+fn be_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+/// Finds the TCP segment in a packet.
+/// Returns `None` for packets of other protocols, and for IP fragments.
+pub(super) fn tcp_segment<'a>(packet: &Packet<'a>) -> Option<TcpSegment<'a>> {
+    let data = packet.data;
+    let (ethertype, ip) = match packet.link_type {
+        LINKTYPE_ETHERNET => {
+            let mut ethertype = be_u16(data, 12)?;
+            let mut offset = 14;
+            while ethertype == ETHERTYPE_VLAN {
+                ethertype = be_u16(data, offset + 2)?;
+                offset += 4;
+            }
+            (Some(ethertype), data.get(offset..)?)
+        }
+        LINKTYPE_LINUX_SLL => (Some(be_u16(data, 14)?), data.get(16..)?),
+        LINKTYPE_LINUX_SLL2 => (Some(be_u16(data, 0)?), data.get(20..)?),
+        // The address family is ignored. The IP version is read from the IP header instead.
+        LINKTYPE_NULL | LINKTYPE_LOOP => (None, data.get(4..)?),
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => (None, data),
+        _ => return None,
+    };
+
+    let version = ip.first()? >> 4;
+    let (source, destination, tcp) = match (ethertype, version) {
+        (Some(ETHERTYPE_IPV4) | None, 4) => {
+            let header_length = (ip[0] & 0x0F) as usize * 4;
+            let total_length = be_u16(ip, 2)? as usize;
+            let fragment = be_u16(ip, 6)?;
+            // More fragments flag or a fragment offset.
+            if fragment & 0x3FFF != 0 || *ip.get(9)? != PROTOCOL_TCP {
+                return None;
+            }
+            let source: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            let destination: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+            // Ethernet frames may be padded, so the IP header decides where the packet ends.
+            let tcp = ip.get(header_length..total_length.min(ip.len()))?;
+            (IpAddr::from(Ipv4Addr::from(source)), IpAddr::from(Ipv4Addr::from(destination)), tcp)
+        }
+        (Some(ETHERTYPE_IPV6) | None, 6) => {
+            let payload_length = be_u16(ip, 4)? as usize;
+            // Extension headers are not supported.
+            if *ip.get(6)? != PROTOCOL_TCP {
+                return None;
+            }
+            let source: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let destination: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            let tcp = ip.get(40..(40 + payload_length).min(ip.len()))?;
+            (IpAddr::from(Ipv6Addr::from(source)), IpAddr::from(Ipv6Addr::from(destination)), tcp)
+        }
+        _ => return None,
+    };
+
+    let source_port = be_u16(tcp, 0)?;
+    let destination_port = be_u16(tcp, 2)?;
+    let sequence_number = u32::from_be_bytes(tcp.get(4..8)?.try_into().ok()?);
+    let header_length = (tcp.get(12)? >> 4) as usize * 4;
+    let flags = *tcp.get(13)?;
+
+    Some(TcpSegment {
+        source: SocketAddr::new(source, source_port),
+        destination: SocketAddr::new(destination, destination_port),
+        sequence_number,
+        syn: flags & 0x02 != 0,
+        payload: tcp.get(header_length..)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a little endian pcapng block.
+    fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let length = 12 + body.len().next_multiple_of(4) as u32;
+        let mut data = Vec::new();
+        data.extend(block_type.to_le_bytes());
+        data.extend(length.to_le_bytes());
+        data.extend(body);
+        data.resize(length as usize - 4, 0);
+        data.extend(length.to_le_bytes());
+        data
+    }
+
+    fn section_header() -> Vec<u8> {
+        let mut body = vec![0x4D, 0x3C, 0x2B, 0x1A, 1, 0, 0, 0];
+        body.extend(u64::MAX.to_le_bytes());
+        block(BLOCK_SECTION_HEADER, &body)
+    }
+
+    fn interface(link_type: u16, options: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend(link_type.to_le_bytes());
+        body.extend([0, 0]);
+        body.extend(65535u32.to_le_bytes());
+        body.extend(options);
+        block(BLOCK_INTERFACE_DESCRIPTION, &body)
+    }
+
+    fn enhanced_packet(timestamp: u64, packet: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend(0u32.to_le_bytes());
+        body.extend(((timestamp >> 32) as u32).to_le_bytes());
+        body.extend((timestamp as u32).to_le_bytes());
+        body.extend((packet.len() as u32).to_le_bytes());
+        body.extend((packet.len() as u32).to_le_bytes());
+        body.extend(packet);
+        block(BLOCK_ENHANCED_PACKET, &body)
+    }
+
+    fn tcp_packet(payload: &[u8]) -> Vec<u8> {
+        let mut ip = vec![0x45, 0];
+        ip.extend((40 + payload.len() as u16).to_be_bytes());
+        ip.extend([0, 0, 0x40, 0, 64, PROTOCOL_TCP, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        ip.extend(50000u16.to_be_bytes());
+        ip.extend(26486u16.to_be_bytes());
+        ip.extend(1000u32.to_be_bytes());
+        ip.extend([0, 0, 0, 0, 0x50, 0x18, 0xFF, 0xFF, 0, 0, 0, 0]);
+        ip.extend(payload);
+        ip
+    }
+
+    #[test]
+    fn pcapng() {
+        let mut data = section_header();
+        // if_tsresol of milliseconds, followed by the end of the options.
+        data.extend(interface(LINKTYPE_RAW, &[9, 0, 1, 0, 3, 0, 0, 0, 0, 0, 0, 0]));
+        data.extend(enhanced_packet(1_700_000_000_123, &tcp_packet(&[1, 2, 3])));
+
+        let packets = read_packets(&data).unwrap();
+        let [packet] = &packets[..] else {
+            panic!("Expected 1 packet");
+        };
+        assert_eq!(packet.link_type, LINKTYPE_RAW);
+        assert_eq!(packet.timestamp, UNIX_EPOCH + Duration::from_millis(1_700_000_000_123));
+
+        let segment = tcp_segment(packet).unwrap();
+        assert_eq!(segment.source, "10.0.0.1:50000".parse().unwrap());
+        assert_eq!(segment.destination, "10.0.0.2:26486".parse().unwrap());
+        assert_eq!(segment.sequence_number, 1000);
+        assert!(!segment.syn);
+        assert_eq!(segment.payload, [1, 2, 3]);
+    }
+
+    #[test]
+    fn truncated_option() {
+        let mut data = section_header();
+        // The if_tsresol option ends before its value.
+        data.extend(interface(LINKTYPE_RAW, &[9, 0, 1, 0]));
+        assert_eq!(read_packets(&data).err().map(|error| error.kind()), Some(ErrorKind::InvalidData));
+
+        let mut data = section_header();
+        data.extend(enhanced_packet(0, &[]));
+        assert!(read_packets(&data).is_err());
+    }
+
+    #[test]
+    fn timestamp_resolution() {
+        assert_eq!(timestamp_to_duration(1_500_000, 6), Duration::from_micros(1_500_000));
+        assert_eq!(timestamp_to_duration(1_500_000_000, 9), Duration::from_nanos(1_500_000_000));
+        assert_eq!(timestamp_to_duration(3, 0), Duration::from_secs(3));
+        assert_eq!(timestamp_to_duration(1_500_000_000_000, 12), Duration::from_millis(1500));
+        assert_eq!(timestamp_to_duration(1536, 0x80 | 10), Duration::from_millis(1500));
+        assert_eq!(timestamp_to_duration(u64::MAX, 0), Duration::from_nanos(u64::MAX));
+    }
+}
@@ -0,0 +1,14 @@
+//! EXOline TCP server.
+//! Answers requests from clients with data from any source.
+//!
+//! Start with the [EXOlineTCPServer] and implement an [EXOlineRequestHandler].
+//!
+//! The server needs a Tokio runtime, also without the `tokio` feature.
+
+mod request;
+mod response;
+mod server_impl;
+
+pub use request::EXOlineRequest;
+pub use response::EXOlineResponse;
+pub use server_impl::{EXOlineRequestHandler, EXOlineTCPServer};
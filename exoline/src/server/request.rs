@@ -0,0 +1,177 @@
+use crate::client::internal::{command_id::CommandId, encoding::*};
+use crate::commands::*;
+
+/// A request received from a client.
+///
+/// Non-exhaustive, since the statistics requests only exist with the `unstable-stats` feature.
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum EXOlineRequest {
+    /// Reads the controller model and version.
+    GetControllerId,
+    /// Reads the memory usage and uptime of the controller.
+    #[cfg(feature = "unstable-stats")]
+    GetControllerStats,
+    /// Reads the size and page count of a DPac.
+    #[cfg(feature = "unstable-stats")]
+    GetDPacStats(GetDPacStatsRequest),
+    /// Reads the run state and cycle times of a task.
+    #[cfg(feature = "unstable-stats")]
+    GetTaskStats(GetTaskStatsRequest),
+    /// Logs in with a password.
+    Login(LoginRequest<'static>),
+    /// Reads an attribute from a partition header.
+    ReadPartAttrHeader(ReadPartAttrHeader),
+    /// Reads a page from a DPac.
+    ReadDPacPage(ReadDPacPageRequest),
+    /// Reads a [Huge](crate::client::Variant::Huge) value.
+    ReadHuge(ReadRequest),
+    /// Reads an [Index](crate::client::Variant::Index) value.
+    ReadIndex(ReadRequest),
+    /// Reads an [Integer](crate::client::Variant::Integer) value.
+    ReadInteger(ReadRequest),
+    /// Reads a [Logic](crate::client::Variant::Logic) value.
+    ReadLogic(ReadRequest),
+    /// Reads a [Real](crate::client::Variant::Real) value.
+    ReadReal(ReadRequest),
+    /// Reads a [String](crate::client::Variant::String) value.
+    ReadString(ReadRequest),
+    /// Reads an [Index](crate::client::Variant::Index) value from a VPac, addressed by segment.
+    ReadVPacIndexSegmented(ReadVPacSegmentedRequest),
+    /// Reads an [Integer](crate::client::Variant::Integer) value from a VPac, addressed by segment.
+    ReadVPacIntegerSegmented(ReadVPacSegmentedRequest),
+    /// Reads a [Logic](crate::client::Variant::Logic) value from a VPac, addressed by segment.
+    ReadVPacLogicSegmented(ReadVPacSegmentedRequest),
+    /// Reads a [Real](crate::client::Variant::Real) value from a VPac, addressed by segment.
+    ReadVPacRealSegmented(ReadVPacSegmentedRequest),
+    /// Reads an [Index](crate::client::Variant::Index) value with the BPac specific command.
+    ReadBPacIndex(ReadBPacRequest),
+    /// Reads an [Integer](crate::client::Variant::Integer) value with the BPac specific command.
+    ReadBPacInteger(ReadBPacRequest),
+    /// Reads a [Logic](crate::client::Variant::Logic) value with the BPac specific command.
+    ReadBPacLogic(ReadBPacRequest),
+    /// Reads a [Real](crate::client::Variant::Real) value with the BPac specific command.
+    ReadBPacReal(ReadBPacRequest),
+    /// Reads a page from the locals of a task.
+    ReadTaskPage(ReadTaskPageRequest),
+    /// Reads an [Index](crate::client::Variant::Index) value with the task specific command.
+    ReadTaskIndex(ReadTaskRequest),
+    /// Reads an [Integer](crate::client::Variant::Integer) value with the task specific command.
+    ReadTaskInteger(ReadTaskRequest),
+    /// Reads a [Logic](crate::client::Variant::Logic) value with the task specific command.
+    ReadTaskLogic(ReadTaskRequest),
+    /// Reads a [Real](crate::client::Variant::Real) value with the task specific command.
+    ReadTaskReal(ReadTaskRequest),
+    /// Reads a string from a text file.
+    ReadText(ReadTextRequest),
+    /// Writes a [Huge](crate::client::Variant::Huge) value.
+    WriteHuge(WriteHugeRequest),
+    /// Writes an [Index](crate::client::Variant::Index) value.
+    WriteIndex(WriteIndexRequest),
+    /// Writes an [Integer](crate::client::Variant::Integer) value.
+    WriteInteger(WriteIntegerRequest),
+    /// Writes a [Logic](crate::client::Variant::Logic) value.
+    WriteLogic(WriteLogicRequest),
+    /// Writes a [Real](crate::client::Variant::Real) value.
+    WriteReal(WriteRealRequest),
+    /// Writes a [String](crate::client::Variant::String) value.
+    WriteString(WriteStringRequest<'static>),
+    /// Writes an [Index](crate::client::Variant::Index) value to a VPac, addressed by segment.
+    WriteVPacIndexSegmented(WriteVPacIndexSegmentedRequest),
+    /// Writes an [Integer](crate::client::Variant::Integer) value to a VPac, addressed by segment.
+    WriteVPacIntegerSegmented(WriteVPacIntegerSegmentedRequest),
+    /// Writes a [Logic](crate::client::Variant::Logic) value to a VPac, addressed by segment.
+    WriteVPacLogicSegmented(WriteVPacLogicSegmentedRequest),
+    /// Writes a [Real](crate::client::Variant::Real) value to a VPac, addressed by segment.
+    WriteVPacRealSegmented(WriteVPacRealSegmentedRequest),
+    /// Writes an [Index](crate::client::Variant::Index) value with the BPac specific command.
+    WriteBPacIndex(WriteBPacIndexRequest),
+    /// Writes an [Integer](crate::client::Variant::Integer) value with the BPac specific command.
+    WriteBPacInteger(WriteBPacIntegerRequest),
+    /// Writes a [Logic](crate::client::Variant::Logic) value with the BPac specific command.
+    WriteBPacLogic(WriteBPacLogicRequest),
+    /// Writes a [Real](crate::client::Variant::Real) value with the BPac specific command.
+    WriteBPacReal(WriteBPacRealRequest),
+    /// Writes an [Index](crate::client::Variant::Index) value with the task specific command.
+    WriteTaskIndex(WriteTaskIndexRequest),
+    /// Writes an [Integer](crate::client::Variant::Integer) value with the task specific command.
+    WriteTaskInteger(WriteTaskIntegerRequest),
+    /// Writes a [Logic](crate::client::Variant::Logic) value with the task specific command.
+    WriteTaskLogic(WriteTaskLogicRequest),
+    /// Writes a [Real](crate::client::Variant::Real) value with the task specific command.
+    WriteTaskReal(WriteTaskRealRequest),
+    /// Writes a string to a text file.
+    WriteText(WriteTextRequest<'static>),
+    /// A command that is not known by this crate. Holds the command id and the payload.
+    Unknown(u8, Vec<u8>),
+}
+
+impl EXOlineRequest {
+    pub(crate) fn decode(command_id: CommandId, payload: &[u8]) -> DecodeResult<Self> {
+        let request = match command_id {
+            CommandId::GetControllerId => {
+                GetControllerIdRequest::decode_from_bytes(payload)?;
+                Self::GetControllerId
+            }
+            #[cfg(feature = "unstable-stats")]
+            CommandId::GetControllerStats => {
+                GetControllerStatsRequest::decode_from_bytes(payload)?;
+                Self::GetControllerStats
+            }
+            #[cfg(feature = "unstable-stats")]
+            CommandId::GetDPacStats => Self::GetDPacStats(GetDPacStatsRequest::decode_from_bytes(payload)?),
+            #[cfg(feature = "unstable-stats")]
+            CommandId::GetTaskStats => Self::GetTaskStats(GetTaskStatsRequest::decode_from_bytes(payload)?),
+            CommandId::Login => Self::Login(LoginRequest::decode_from_bytes(payload)?),
+            CommandId::ReadPartAttrHeader => Self::ReadPartAttrHeader(ReadPartAttrHeader::decode_from_bytes(payload)?),
+            CommandId::ReadDPacPage => Self::ReadDPacPage(ReadDPacPageRequest::decode_from_bytes(payload)?),
+            CommandId::ReadHuge => Self::ReadHuge(ReadRequest::decode_from_bytes(payload)?),
+            CommandId::ReadIndex => Self::ReadIndex(ReadRequest::decode_from_bytes(payload)?),
+            CommandId::ReadInteger => Self::ReadInteger(ReadRequest::decode_from_bytes(payload)?),
+            CommandId::ReadLogic => Self::ReadLogic(ReadRequest::decode_from_bytes(payload)?),
+            CommandId::ReadReal => Self::ReadReal(ReadRequest::decode_from_bytes(payload)?),
+            CommandId::ReadString => Self::ReadString(ReadRequest::decode_from_bytes(payload)?),
+            CommandId::ReadVPacIndexSegmented => Self::ReadVPacIndexSegmented(ReadVPacSegmentedRequest::decode_from_bytes(payload)?),
+            CommandId::ReadVPacIntegerSegmented => Self::ReadVPacIntegerSegmented(ReadVPacSegmentedRequest::decode_from_bytes(payload)?),
+            CommandId::ReadVPacLogicSegmented => Self::ReadVPacLogicSegmented(ReadVPacSegmentedRequest::decode_from_bytes(payload)?),
+            CommandId::ReadVPacRealSegmented => Self::ReadVPacRealSegmented(ReadVPacSegmentedRequest::decode_from_bytes(payload)?),
+            CommandId::ReadBPacIndex => Self::ReadBPacIndex(ReadBPacRequest::decode_from_bytes(payload)?),
+            CommandId::ReadBPacInteger => Self::ReadBPacInteger(ReadBPacRequest::decode_from_bytes(payload)?),
+            CommandId::ReadBPacLogic => Self::ReadBPacLogic(ReadBPacRequest::decode_from_bytes(payload)?),
+            CommandId::ReadBPacReal => Self::ReadBPacReal(ReadBPacRequest::decode_from_bytes(payload)?),
+            CommandId::ReadTaskPage => Self::ReadTaskPage(ReadTaskPageRequest::decode_from_bytes(payload)?),
+            CommandId::ReadTaskIndex => Self::ReadTaskIndex(ReadTaskRequest::decode_from_bytes(payload)?),
+            CommandId::ReadTaskInteger => Self::ReadTaskInteger(ReadTaskRequest::decode_from_bytes(payload)?),
+            CommandId::ReadTaskLogic => Self::ReadTaskLogic(ReadTaskRequest::decode_from_bytes(payload)?),
+            CommandId::ReadTaskReal => Self::ReadTaskReal(ReadTaskRequest::decode_from_bytes(payload)?),
+            CommandId::ReadText => Self::ReadText(ReadTextRequest::decode_from_bytes(payload)?),
+            CommandId::WriteHuge => Self::WriteHuge(WriteHugeRequest::decode_from_bytes(payload)?),
+            CommandId::WriteIndex => Self::WriteIndex(WriteIndexRequest::decode_from_bytes(payload)?),
+            CommandId::WriteInteger => Self::WriteInteger(WriteIntegerRequest::decode_from_bytes(payload)?),
+            CommandId::WriteLogic => Self::WriteLogic(WriteLogicRequest::decode_from_bytes(payload)?),
+            CommandId::WriteReal => Self::WriteReal(WriteRealRequest::decode_from_bytes(payload)?),
+            CommandId::WriteString => Self::WriteString(WriteStringRequest::decode_from_bytes(payload)?),
+            CommandId::WriteVPacIndexSegmented => {
+                Self::WriteVPacIndexSegmented(WriteVPacIndexSegmentedRequest::decode_from_bytes(payload)?)
+            }
+            CommandId::WriteVPacIntegerSegmented => {
+                Self::WriteVPacIntegerSegmented(WriteVPacIntegerSegmentedRequest::decode_from_bytes(payload)?)
+            }
+            CommandId::WriteVPacLogicSegmented => {
+                Self::WriteVPacLogicSegmented(WriteVPacLogicSegmentedRequest::decode_from_bytes(payload)?)
+            }
+            CommandId::WriteVPacRealSegmented => Self::WriteVPacRealSegmented(WriteVPacRealSegmentedRequest::decode_from_bytes(payload)?),
+            CommandId::WriteBPacIndex => Self::WriteBPacIndex(WriteBPacIndexRequest::decode_from_bytes(payload)?),
+            CommandId::WriteBPacInteger => Self::WriteBPacInteger(WriteBPacIntegerRequest::decode_from_bytes(payload)?),
+            CommandId::WriteBPacLogic => Self::WriteBPacLogic(WriteBPacLogicRequest::decode_from_bytes(payload)?),
+            CommandId::WriteBPacReal => Self::WriteBPacReal(WriteBPacRealRequest::decode_from_bytes(payload)?),
+            CommandId::WriteTaskIndex => Self::WriteTaskIndex(WriteTaskIndexRequest::decode_from_bytes(payload)?),
+            CommandId::WriteTaskInteger => Self::WriteTaskInteger(WriteTaskIntegerRequest::decode_from_bytes(payload)?),
+            CommandId::WriteTaskLogic => Self::WriteTaskLogic(WriteTaskLogicRequest::decode_from_bytes(payload)?),
+            CommandId::WriteTaskReal => Self::WriteTaskReal(WriteTaskRealRequest::decode_from_bytes(payload)?),
+            CommandId::WriteText => Self::WriteText(WriteTextRequest::decode_from_bytes(payload)?),
+            CommandId::Unknown(command_id) => Self::Unknown(command_id, payload.into()),
+        };
+        Ok(request)
+    }
+}
@@ -0,0 +1,171 @@
+#[cfg(feature = "unstable-stats")]
+use std::time::Duration;
+
+use crate::client::internal::encoding::*;
+use crate::client::Variant;
+#[cfg(feature = "unstable-stats")]
+use crate::client::{ControllerStats, DPacStats, TaskStats};
+use crate::commands::*;
+
+use super::EXOlineRequest;
+
+/// The answer to an [EXOlineRequest](super::EXOlineRequest).
+///
+/// The `unstable-stats` feature adds the statistics answers.
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum EXOlineResponse {
+    /// Acknowledges a write.
+    Ack,
+    /// The content of a DPac page.
+    DPacPage(Vec<u8>),
+    /// A single value. Used for reads, the controller id and partition attributes.
+    Value(Variant),
+    /// Statistics of the controller.
+    #[cfg(feature = "unstable-stats")]
+    ControllerStats(ControllerStats),
+    /// Statistics of a DPac.
+    #[cfg(feature = "unstable-stats")]
+    DPacStats(DPacStats),
+    /// Statistics of a task.
+    #[cfg(feature = "unstable-stats")]
+    TaskStats(TaskStats),
+    /// The access level given by a login.
+    AccessLevel(u8),
+    /// Data that is sent as is. Useful for answering unknown commands.
+    Raw(Vec<u8>),
+}
+
+impl EXOlineResponse {
+    pub(crate) fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+        match self {
+            Self::Ack => Encoder::encode(&WriteResponse),
+            Self::DPacPage(data) => Encoder::encode(&ReadDPacPageResponse { data: data.into() }),
+            Self::Value(Variant::Huge(value)) => Encoder::encode(&ReadHugeResponse { value: *value }),
+            Self::Value(Variant::Index(value)) => Encoder::encode(&ReadIndexResponse { value: *value }),
+            Self::Value(Variant::Integer(value)) => Encoder::encode(&ReadIntegerResponse { value: *value }),
+            Self::Value(Variant::Logic(value)) => Encoder::encode(&ReadLogicResponse { value: *value }),
+            Self::Value(Variant::Real(value)) => Encoder::encode(&ReadRealResponse { value: *value }),
+            Self::Value(Variant::String(value)) => Encoder::encode(&ReadStringResponse { value: value.into() }),
+            #[cfg(feature = "unstable-stats")]
+            Self::ControllerStats(stats) => Encoder::encode(&GetControllerStatsResponse {
+                total_memory: stats.total_memory,
+                free_memory: stats.free_memory,
+                dpac_count: stats.dpac_count,
+                task_count: stats.task_count,
+                uptime: stats.uptime.as_secs().try_into().unwrap_or(u32::MAX),
+            }),
+            #[cfg(feature = "unstable-stats")]
+            Self::DPacStats(stats) => Encoder::encode(&GetDPacStatsResponse {
+                size: stats.size,
+                page_count: stats.page_count,
+            }),
+            #[cfg(feature = "unstable-stats")]
+            Self::TaskStats(stats) => Encoder::encode(&GetTaskStatsResponse {
+                running: stats.running,
+                cycle_time: millis(stats.cycle_time),
+                execution_time: millis(stats.execution_time),
+                max_execution_time: millis(stats.max_execution_time),
+            }),
+            Self::AccessLevel(access_level) => Encoder::encode(&LoginResponse {
+                access_level: *access_level,
+            }),
+            Self::Raw(data) => Ok(data.clone()),
+        }
+    }
+
+    /// Decodes the payload of a response to `request`. The kind of response depends on the request.
+    pub(crate) fn decode(request: &EXOlineRequest, payload: &[u8]) -> DecodeResult<Self> {
+        let response = match request {
+            EXOlineRequest::GetControllerId => Self::Value(Variant::String(GetControllerIdResponse::decode_from_bytes(payload)?.id.into())),
+            #[cfg(feature = "unstable-stats")]
+            EXOlineRequest::GetControllerStats => {
+                let response = GetControllerStatsResponse::decode_from_bytes(payload)?;
+                Self::ControllerStats(ControllerStats {
+                    total_memory: response.total_memory,
+                    free_memory: response.free_memory,
+                    dpac_count: response.dpac_count,
+                    task_count: response.task_count,
+                    uptime: Duration::from_secs(response.uptime as u64),
+                })
+            }
+            #[cfg(feature = "unstable-stats")]
+            EXOlineRequest::GetDPacStats(_) => {
+                let response = GetDPacStatsResponse::decode_from_bytes(payload)?;
+                Self::DPacStats(DPacStats {
+                    size: response.size,
+                    page_count: response.page_count,
+                })
+            }
+            #[cfg(feature = "unstable-stats")]
+            EXOlineRequest::GetTaskStats(_) => {
+                let response = GetTaskStatsResponse::decode_from_bytes(payload)?;
+                Self::TaskStats(TaskStats {
+                    running: response.running,
+                    cycle_time: Duration::from_millis(response.cycle_time as u64),
+                    execution_time: Duration::from_millis(response.execution_time as u64),
+                    max_execution_time: Duration::from_millis(response.max_execution_time as u64),
+                })
+            }
+            EXOlineRequest::Login(_) => Self::AccessLevel(LoginResponse::decode_from_bytes(payload)?.access_level),
+            EXOlineRequest::ReadPartAttrHeader(request) => match request.kind {
+                PartAttrHeaderKind::Huge => Self::Value(Variant::Huge(ReadHugeResponse::decode_from_bytes(payload)?.value)),
+                PartAttrHeaderKind::Real => Self::Value(Variant::Real(ReadRealResponse::decode_from_bytes(payload)?.value)),
+                PartAttrHeaderKind::String => Self::Value(Variant::String(ReadStringResponse::decode_from_bytes(payload)?.value.into())),
+                PartAttrHeaderKind::Unknown(_) => Self::Raw(payload.into()),
+            },
+            EXOlineRequest::ReadDPacPage(_) | EXOlineRequest::ReadTaskPage(_) => {
+                Self::DPacPage(ReadDPacPageResponse::decode_from_bytes(payload)?.data.into())
+            }
+            EXOlineRequest::ReadHuge(_) => Self::Value(Variant::Huge(ReadHugeResponse::decode_from_bytes(payload)?.value)),
+            EXOlineRequest::ReadIndex(_)
+            | EXOlineRequest::ReadVPacIndexSegmented(_)
+            | EXOlineRequest::ReadBPacIndex(_)
+            | EXOlineRequest::ReadTaskIndex(_) => Self::Value(Variant::Index(ReadIndexResponse::decode_from_bytes(payload)?.value)),
+            EXOlineRequest::ReadInteger(_)
+            | EXOlineRequest::ReadVPacIntegerSegmented(_)
+            | EXOlineRequest::ReadBPacInteger(_)
+            | EXOlineRequest::ReadTaskInteger(_) => Self::Value(Variant::Integer(ReadIntegerResponse::decode_from_bytes(payload)?.value)),
+            EXOlineRequest::ReadLogic(_)
+            | EXOlineRequest::ReadVPacLogicSegmented(_)
+            | EXOlineRequest::ReadBPacLogic(_)
+            | EXOlineRequest::ReadTaskLogic(_) => Self::Value(Variant::Logic(ReadLogicResponse::decode_from_bytes(payload)?.value)),
+            EXOlineRequest::ReadReal(_)
+            | EXOlineRequest::ReadVPacRealSegmented(_)
+            | EXOlineRequest::ReadBPacReal(_)
+            | EXOlineRequest::ReadTaskReal(_) => Self::Value(Variant::Real(ReadRealResponse::decode_from_bytes(payload)?.value)),
+            EXOlineRequest::ReadString(_) | EXOlineRequest::ReadText(_) => {
+                Self::Value(Variant::String(ReadStringResponse::decode_from_bytes(payload)?.value.into()))
+            }
+            EXOlineRequest::WriteHuge(_)
+            | EXOlineRequest::WriteIndex(_)
+            | EXOlineRequest::WriteInteger(_)
+            | EXOlineRequest::WriteLogic(_)
+            | EXOlineRequest::WriteReal(_)
+            | EXOlineRequest::WriteString(_)
+            | EXOlineRequest::WriteVPacIndexSegmented(_)
+            | EXOlineRequest::WriteVPacIntegerSegmented(_)
+            | EXOlineRequest::WriteVPacLogicSegmented(_)
+            | EXOlineRequest::WriteVPacRealSegmented(_)
+            | EXOlineRequest::WriteBPacIndex(_)
+            | EXOlineRequest::WriteBPacInteger(_)
+            | EXOlineRequest::WriteBPacLogic(_)
+            | EXOlineRequest::WriteBPacReal(_)
+            | EXOlineRequest::WriteTaskIndex(_)
+            | EXOlineRequest::WriteTaskInteger(_)
+            | EXOlineRequest::WriteTaskLogic(_)
+            | EXOlineRequest::WriteTaskReal(_)
+            | EXOlineRequest::WriteText(_) => {
+                WriteResponse::decode_from_bytes(payload)?;
+                Self::Ack
+            }
+            EXOlineRequest::Unknown(_, _) => Self::Raw(payload.into()),
+        };
+        Ok(response)
+    }
+}
+
+#[cfg(feature = "unstable-stats")]
+fn millis(duration: Duration) -> u16 {
+    duration.as_millis().try_into().unwrap_or(u16::MAX)
+}
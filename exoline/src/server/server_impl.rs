@@ -0,0 +1,218 @@
+use std::{future::Future, sync::Arc};
+#[cfg(feature = "tokio")]
+use std::net::SocketAddr;
+
+#[cfg(feature = "tokio")]
+use tokio::{
+    net::{TcpListener, ToSocketAddrs},
+    task::JoinHandle,
+};
+
+use crate::client::internal::{connection::*, encoding::*};
+use crate::client::{EXOlineException, Transport};
+
+use super::{EXOlineRequest, EXOlineResponse};
+
+/// Answers the requests received by an [EXOlineTCPServer].
+pub trait EXOlineRequestHandler: Send + Sync + 'static {
+    /// Returns `true` if requests to the EXOline address should be answered.
+    /// Requests to other addresses are ignored, like a device on a shared bus would.
+    fn accepts_address(&self, _address: (u8, u8)) -> bool {
+        true
+    }
+
+    /// Answers a request. An exception is sent to the client as an exception code.
+    fn handle(&self, address: (u8, u8), request: EXOlineRequest) -> impl Future<Output = Result<EXOlineResponse, EXOlineException>> + Send;
+}
+
+/// EXOline TCP server. Decodes requests from clients and answers them with an [EXOlineRequestHandler].
+pub struct EXOlineTCPServer<H> {
+    handler: Arc<H>,
+}
+
+impl<H> Clone for EXOlineTCPServer<H> {
+    fn clone(&self) -> Self {
+        Self {
+            handler: self.handler.clone(),
+        }
+    }
+}
+
+impl<H> EXOlineTCPServer<H>
+where
+    H: EXOlineRequestHandler,
+{
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler: Arc::new(handler),
+        }
+    }
+
+    /// The handler that answers requests.
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Binds to `addr` and serves every incoming connection in the background.
+    /// Returns the address that was bound, which is useful when binding to port 0.
+    #[cfg(feature = "tokio")]
+    pub async fn listen<A>(&self, addr: A) -> std::io::Result<(SocketAddr, JoinHandle<std::io::Result<()>>)>
+    where
+        A: ToSocketAddrs,
+    {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let join_handle = tokio::spawn(self.clone().serve(listener));
+        Ok((local_addr, join_handle))
+    }
+
+    /// Accepts connections from `listener` until an error occurs.
+    #[cfg(feature = "tokio")]
+    pub async fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let this = self.clone();
+            tokio::spawn(async move { this.handle_connection(stream).await });
+        }
+    }
+
+    /// Answers requests on a single connection until it is closed.
+    pub async fn handle_connection(&self, stream: impl Transport) -> std::io::Result<()> {
+        let connection = Connection::new(stream);
+        loop {
+            let frame = match connection.read_request().await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => return Ok(()),
+                Err(ReadError::IO(err)) => return Err(err),
+                Err(ReadError::InvalidData(_)) => continue,
+            };
+
+            let response = match self.handle_frame(&frame).await {
+                None => continue, // Not for us
+                Some(response) => response,
+            };
+
+            match response {
+                Ok(mut data) => {
+                    append_crc(&mut data);
+                    connection.write_response(&escape(&data)).await?;
+                }
+                Err(exception) => {
+                    connection.write_response(&[exception.into()]).await?;
+                }
+            }
+        }
+    }
+
+    async fn handle_frame(&self, frame: &[u8]) -> Option<Result<Vec<u8>, EXOlineException>> {
+        let frame = unescape(frame);
+        let body = verify_and_remove_crc(&frame)?;
+        let [pla, ela, command_id, payload @ ..] = body else {
+            return None;
+        };
+        let address = (*pla, *ela);
+        if !self.handler.accepts_address(address) {
+            return None;
+        }
+
+        let request = match EXOlineRequest::decode((*command_id).into(), payload) {
+            Ok(request) => request,
+            Err(_) => return Some(Err(EXOlineException::IllegalMessageLength)),
+        };
+
+        let response = match self.handler.handle(address, request).await {
+            Ok(response) => response,
+            Err(exception) => return Some(Err(exception)),
+        };
+
+        Some(response.encode().map_err(|_| EXOlineException::TooBigMaxLength))
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use tokio::net::TcpStream;
+
+    use crate::client::{EXOlineError, EXOlineTCPClient, Variant};
+    use crate::controller::{FileKind, VariableKind};
+
+    use super::*;
+
+    struct Handler;
+
+    impl EXOlineRequestHandler for Handler {
+        fn accepts_address(&self, address: (u8, u8)) -> bool {
+            address.0 == 1
+        }
+
+        async fn handle(&self, address: (u8, u8), request: EXOlineRequest) -> Result<EXOlineResponse, EXOlineException> {
+            match request {
+                EXOlineRequest::ReadInteger(request) => Ok(EXOlineResponse::Value(Variant::Integer(address.1 as i16 + request.offset as i16))),
+                EXOlineRequest::WriteReal(_) => Ok(EXOlineResponse::Ack),
+                _ => Err(EXOlineException::IllegalCommand),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn handler() {
+        let (addr, _) = EXOlineTCPServer::new(Handler).listen("127.0.0.1:0").await.unwrap();
+        let (client, _) = EXOlineTCPClient::new(TcpStream::connect(addr).await.unwrap());
+
+        let value = client.read_variable_raw((1, 10), FileKind::VPac, 1, VariableKind::Integer, 5).await.unwrap();
+        assert_eq!(value, Variant::Integer(15));
+
+        let result = client.write_variable_raw((1, 10), FileKind::VPac, 1, VariableKind::Real, 5, &Variant::Real(1.0)).await;
+        assert!(result.is_ok());
+
+        let result = client.read_controller_id((1, 10)).await;
+        assert!(matches!(result, Err(EXOlineError::ExolineException(EXOlineException::IllegalCommand))));
+    }
+
+    /// Keeps the written values, and answers reads with them.
+    #[derive(Default)]
+    struct Memory(Mutex<HashMap<(u8, u32), Variant>>);
+
+    impl EXOlineRequestHandler for Memory {
+        fn accepts_address(&self, _address: (u8, u8)) -> bool {
+            true
+        }
+
+        async fn handle(&self, _address: (u8, u8), request: EXOlineRequest) -> Result<EXOlineResponse, EXOlineException> {
+            let mut values = self.0.lock().unwrap();
+            let (location, value) = match request {
+                EXOlineRequest::ReadInteger(request) | EXOlineRequest::ReadReal(request) | EXOlineRequest::ReadString(request) => {
+                    let value = values.get(&(request.load_number, request.offset));
+                    return value.cloned().map(EXOlineResponse::Value).ok_or(EXOlineException::AddressOutsideRange);
+                }
+                EXOlineRequest::WriteInteger(request) => ((request.load_number, request.offset), Variant::Integer(request.value)),
+                EXOlineRequest::WriteReal(request) => ((request.load_number, request.offset), Variant::Real(request.value)),
+                EXOlineRequest::WriteString(request) => ((request.load_number, request.offset), Variant::String(request.value.into())),
+                _ => return Err(EXOlineException::IllegalCommand),
+            };
+            values.insert(location, value);
+            Ok(EXOlineResponse::Ack)
+        }
+    }
+
+    #[tokio::test]
+    async fn write_round_trip() {
+        let (addr, _) = EXOlineTCPServer::new(Memory::default()).listen("127.0.0.1:0").await.unwrap();
+        let (client, _) = EXOlineTCPClient::new(TcpStream::connect(addr).await.unwrap());
+
+        let writes = [
+            (VariableKind::Integer, 2, Variant::Integer(-1234)),
+            (VariableKind::Real, 3, Variant::Real(21.5)),
+            (VariableKind::String, 4, Variant::String("Hello".into())),
+        ];
+        for (kind, offset, value) in &writes {
+            client.write_variable_raw((1, 1), FileKind::VPac, 7, *kind, *offset, value).await.unwrap();
+        }
+        for (kind, offset, value) in &writes {
+            assert_eq!(&client.read_variable_raw((1, 1), FileKind::VPac, 7, *kind, *offset).await.unwrap(), value);
+        }
+    }
+}
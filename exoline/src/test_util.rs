@@ -0,0 +1,110 @@
+use std::sync::Mutex;
+
+use tokio::net::TcpStream;
+
+use crate::client::internal::consts::*;
+use crate::client::{CommandId, EXOlineTCPClient, Frame, FrameDirection, FrameObserver};
+use crate::controller::{load_from_sources, Controller};
+use crate::emulator::VirtualController;
+
+const TEST_VPAC: &str = "{ VPac
+Name = Test
+Ln = 10
+}
+{ Variables
+H Huge
+X Index
+I Integer
+L Logic
+R Real
+$ Text:20
+I Values[69]
+}
+";
+
+const TABLE_BPAC: &str = "{ BPac
+Name = Table
+Ln = 20
+}
+{ Values
+# : R Value : X Flag :
+1.0 : 1 #0
+2.0 : 0 #1
+3.0 : 1 #2
+}
+";
+
+const PROGRAM_TASK: &str = "{ Task
+Name = Program
+Ln = 5
+}
+{ Locals
+I Counter
+R Sum
+X State
+}
+";
+
+const TEXTS_TEXT: &str = "{ Text
+Name = Texts
+}
+{ Strings
+$ Greeting:20
+$ Farewell:20
+}
+";
+
+/// A small controller with one file of each kind.
+pub fn test_controller() -> Controller {
+    load_from_sources(
+        (2, 1),
+        &[("Test", TEST_VPAC), ("Table", TABLE_BPAC)],
+        &[("Program", PROGRAM_TASK)],
+        &[("Texts", TEXTS_TEXT)],
+    )
+}
+
+/// Starts a virtual controller for the [test_controller], and connects a client to it.
+pub async fn connect_test_controller() -> (Controller, VirtualController, EXOlineTCPClient) {
+    let controller = test_controller();
+    let device = VirtualController::new(&controller);
+    let (addr, _) = device.listen("127.0.0.1:0").await.unwrap();
+    let (client, _) = EXOlineTCPClient::new(TcpStream::connect(addr).await.unwrap());
+    (controller, device, client)
+}
+
+/// A [FrameObserver] that keeps every frame.
+#[derive(Default)]
+pub struct Frames(pub Mutex<Vec<Frame>>);
+
+impl Frames {
+    /// The number of requests sent with the command.
+    pub fn requests(&self, command_id: CommandId) -> usize {
+        let frames = self.0.lock().unwrap();
+        frames
+            .iter()
+            .filter(|frame| frame.direction == FrameDirection::Outgoing && frame.command_id == Some(command_id))
+            .count()
+    }
+
+    /// The frames as they were sent over the connection, with the begin and end markers.
+    pub fn wire_bytes(&self) -> Vec<(FrameDirection, Vec<u8>)> {
+        let frames = self.0.lock().unwrap();
+        frames
+            .iter()
+            .map(|frame| {
+                let begin = match frame.direction {
+                    FrameDirection::Outgoing => BEGIN_REQUEST,
+                    FrameDirection::Incoming => BEGIN_RESPONSE,
+                };
+                (frame.direction, [&[begin][..], &frame.raw, &[END_MESSAGE]].concat())
+            })
+            .collect()
+    }
+}
+
+impl FrameObserver for Frames {
+    fn on_frame(&self, frame: &Frame) {
+        self.0.lock().unwrap().push(frame.clone());
+    }
+}